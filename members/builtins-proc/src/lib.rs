@@ -3,7 +3,7 @@ use darling::{util::Flag, FromMeta};
 use proc_macro::TokenStream;
 use quote::quote;
 use std::{fs::File, io::Write};
-use syn;
+use syn::{self, spanned::Spanned};
 
 #[derive(Debug, FromMeta)]
 struct MacroArgs {
@@ -60,14 +60,294 @@ AUTHORS
         man.write(help.as_bytes()).unwrap();
     }
 
+    if is_untyped(inputs) {
+        let result = quote! {
+            #[doc = #help]
+            #vis #fn_token #name(#inputs) #output {
+                if ion_shell::builtins::man_pages::check_help(args, #man) {
+                    return ion_shell::builtins::Status::SUCCESS;
+                }
+                #block
+            }
+        };
+        return result.into();
+    }
+
+    let params = match inputs.iter().map(Param::parse).collect::<Result<Vec<_>, _>>() {
+        Ok(params) => params,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let command = names.split(',').next().unwrap_or_default().trim();
+    let signature = quote::format_ident!(
+        "BUILTIN_{}_SIGNATURE",
+        ident.to_string().to_uppercase(),
+        span = ident.span()
+    );
+
+    let mut flags = Vec::new();
+    let mut positionals = Vec::new();
+    let mut flag_decls = Vec::new();
+    let mut parsers = Vec::new();
+    let mut call = Vec::new();
+    for param in &params {
+        match param {
+            Param::Shell => call.push(quote! { shell }),
+            Param::Flag { ident, short } => {
+                let long = ident.to_string().replace('_', "-");
+                let short = match short {
+                    Some(short) => quote! { Some(#short) },
+                    None => quote! { None },
+                };
+                flags.push(quote! { ion_shell::builtins::Flag { long: #long, short: #short } });
+                flag_decls.push(quote! { let mut #ident = false; });
+                call.push(quote! { #ident });
+            }
+            Param::Positional { ident, ty, kind } => {
+                let name = ident.to_string().to_uppercase();
+                let ty_name = type_name(ty);
+                let (optional, variadic) = match kind {
+                    Kind::Required => (false, false),
+                    Kind::Optional => (true, false),
+                    Kind::Variadic => (true, true),
+                };
+                positionals.push(quote! {
+                    ion_shell::builtins::Param {
+                        name: #name,
+                        kind: #ty_name,
+                        optional: #optional,
+                        variadic: #variadic,
+                    }
+                });
+                let parse = quote! {
+                    match arg.parse::<#ty>() {
+                        Ok(value) => value,
+                        Err(_) => return ion_shell::builtins::Status::bad_argument(format!(
+                            "{}: invalid value '{}' for {}", #command, arg, #name
+                        )),
+                    }
+                };
+                parsers.push(match kind {
+                    Kind::Required => quote! {
+                        let #ident = match positionals.next() {
+                            Some(arg) => #parse,
+                            None => return ion_shell::builtins::Status::bad_argument(format!(
+                                "{}: missing argument {}", #command, #name
+                            )),
+                        };
+                    },
+                    Kind::Optional => quote! {
+                        let #ident = match positionals.next() {
+                            Some(arg) => Some(#parse),
+                            None => None,
+                        };
+                    },
+                    Kind::Variadic => quote! {
+                        let mut #ident = Vec::new();
+                        for arg in &mut positionals {
+                            #ident.push(#parse);
+                        }
+                    },
+                });
+                call.push(quote! { #ident });
+            }
+        }
+    }
+    let flag_matches = params.iter().filter_map(|param| match param {
+        Param::Flag { ident, .. } => {
+            let long = ident.to_string().replace('_', "-");
+            Some(quote! { Some(#long) => #ident = true, })
+        }
+        _ => None,
+    });
+    // Without flags to recognize, the arguments starting with a dash are taken as positionals
+    let parse_flag = if flags.is_empty() {
+        quote! { rest.push(arg); }
+    } else {
+        quote! {
+            match #signature.flag(arg).map(|flag| flag.long) {
+                #(#flag_matches)*
+                _ => return ion_shell::builtins::Status::bad_argument(format!(
+                    "{}: unrecognized flag '{}'", #command, arg
+                )),
+            }
+        }
+    };
+    let inner_inputs = inputs.iter().cloned().map(|mut input| {
+        if let syn::FnArg::Typed(ref mut input) = input {
+            input.attrs.retain(|attr| !attr.path.is_ident("flag"));
+        }
+        input
+    });
+
     let result = quote! {
+        /// The arguments accepted by the builtin
+        #vis const #signature: ion_shell::builtins::Signature = ion_shell::builtins::Signature {
+            flags:  &[#(#flags),*],
+            params: &[#(#positionals),*],
+        };
+
         #[doc = #help]
-        #vis #fn_token #name(#inputs) #output {
+        #[allow(unused_variables)]
+        #vis #fn_token #name(
+            args: &[ion_shell::types::Str],
+            shell: &mut ion_shell::Shell<'_>,
+        ) -> ion_shell::builtins::Status {
+            #fn_token #ident(#(#inner_inputs),*) #output #block
+
             if ion_shell::builtins::man_pages::check_help(args, #man) {
                 return ion_shell::builtins::Status::SUCCESS;
             }
-            #block
+
+            #(#flag_decls)*
+            let mut rest = Vec::with_capacity(args.len());
+            let mut flags_ended = false;
+            for arg in args.iter().skip(1).map(ion_shell::types::Str::as_str) {
+                let is_flag = arg.starts_with('-')
+                    && arg.len() > 1
+                    && !arg[1..].starts_with(|c: char| c.is_ascii_digit());
+                if !flags_ended && arg == "--" {
+                    flags_ended = true;
+                } else if flags_ended || !is_flag {
+                    rest.push(arg);
+                } else {
+                    #parse_flag
+                }
+            }
+            let mut positionals = rest.into_iter();
+            #(#parsers)*
+            if let Some(arg) = positionals.next() {
+                return ion_shell::builtins::Status::bad_argument(format!(
+                    "{}: unexpected argument '{}'", #command, arg
+                ));
+            }
+
+            #ident(#(#call),*)
         }
     };
     result.into()
 }
+
+/// The kind of a positional parameter of a typed builtin
+enum Kind {
+    /// `T`
+    Required,
+    /// `Option<T>`
+    Optional,
+    /// `Vec<T>`, collecting the remaining arguments
+    Variadic,
+}
+
+/// A parameter of a typed builtin
+enum Param {
+    /// The shell the builtin is running in
+    Shell,
+    /// A boolean flag, marked with `#[flag]` or `#[flag(short = 'x')]`
+    Flag { ident: syn::Ident, short: Option<char> },
+    /// A positional argument parsed with `FromStr`
+    Positional { ident: syn::Ident, ty: syn::Type, kind: Kind },
+}
+
+impl Param {
+    fn parse(input: &syn::FnArg) -> syn::Result<Self> {
+        let input = match input {
+            syn::FnArg::Typed(input) => input,
+            syn::FnArg::Receiver(_) => {
+                return Err(syn::Error::new(input.span(), "builtins can not take self"))
+            }
+        };
+        let ident = match *input.pat {
+            syn::Pat::Ident(syn::PatIdent { ref ident, .. }) => ident.clone(),
+            ref pat => return Err(syn::Error::new(pat.span(), "expected an identifier")),
+        };
+
+        if let Some(attr) = input.attrs.iter().find(|attr| attr.path.is_ident("flag")) {
+            let short = match attr.parse_meta()? {
+                syn::Meta::Path(_) => None,
+                syn::Meta::List(list) => match list.nested.iter().next() {
+                    Some(syn::NestedMeta::Meta(syn::Meta::NameValue(value)))
+                        if value.path.is_ident("short") =>
+                    {
+                        match value.lit {
+                            syn::Lit::Char(ref short) => Some(short.value()),
+                            syn::Lit::Str(ref short) if short.value().chars().count() == 1 => {
+                                short.value().chars().next()
+                            }
+                            ref lit => {
+                                return Err(syn::Error::new(lit.span(), "expected a character"))
+                            }
+                        }
+                    }
+                    _ => return Err(syn::Error::new(list.span(), "expected `short = 'x'`")),
+                },
+                meta => return Err(syn::Error::new(meta.span(), "expected `short = 'x'`")),
+            };
+            return Ok(Param::Flag { ident, short });
+        }
+
+        match *input.ty {
+            syn::Type::Reference(ref reference) if reference.mutability.is_some() => {
+                match last_segment(&reference.elem) {
+                    Some(segment) if segment.ident == "Shell" => Ok(Param::Shell),
+                    _ => Err(syn::Error::new(
+                        reference.span(),
+                        "only the shell may be taken by reference",
+                    )),
+                }
+            }
+            ref ty => {
+                let segment = last_segment(ty);
+                let kind = match segment {
+                    Some(segment) if segment.ident == "Option" => Kind::Optional,
+                    Some(segment) if segment.ident == "Vec" => Kind::Variadic,
+                    _ => {
+                        return Ok(Param::Positional {
+                            ident,
+                            ty: ty.clone(),
+                            kind: Kind::Required,
+                        })
+                    }
+                };
+                match segment.map(|segment| &segment.arguments) {
+                    Some(syn::PathArguments::AngleBracketed(arguments)) => {
+                        match arguments.args.iter().next() {
+                            Some(syn::GenericArgument::Type(ty)) => {
+                                Ok(Param::Positional { ident, ty: ty.clone(), kind })
+                            }
+                            _ => Err(syn::Error::new(arguments.span(), "expected a type")),
+                        }
+                    }
+                    _ => Err(syn::Error::new(ty.span(), "expected a type argument")),
+                }
+            }
+        }
+    }
+}
+
+/// Builtins taking the raw arguments and the shell are not parsed
+fn is_untyped(inputs: &syn::punctuated::Punctuated<syn::FnArg, syn::Token![,]>) -> bool {
+    match inputs.first() {
+        Some(syn::FnArg::Typed(input)) => match *input.ty {
+            syn::Type::Reference(ref reference) => {
+                if let syn::Type::Slice(_) = *reference.elem {
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn last_segment(ty: &syn::Type) -> Option<&syn::PathSegment> {
+    match ty {
+        syn::Type::Path(path) => path.path.segments.last(),
+        _ => None,
+    }
+}
+
+/// The name of the type shown in the signature of the builtin
+fn type_name(ty: &syn::Type) -> String {
+    last_segment(ty).map_or_else(|| quote!(#ty).to_string(), |segment| segment.ident.to_string())
+}
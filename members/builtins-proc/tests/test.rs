@@ -28,3 +28,70 @@ fn works() {
         Status::SUCCESS
     );
 }
+
+#[builtins_proc::builtin(
+    desc = "sums numbers",
+    man = "
+SYNOPSIS
+    sum [-h | --help] [-n | --negate] [START] [NUMBERS...]
+
+DESCRIPTION
+    Exits with the sum of the numbers, negated if requested.
+"
+)]
+fn sum(#[flag(short = 'n')] negate: bool, start: Option<i32>, numbers: Vec<i32>) -> Status {
+    let sum = start.unwrap_or(0) + numbers.iter().sum::<i32>();
+    Status::from_exit_code(if negate { -sum } else { sum })
+}
+
+fn args(args: &[&str]) -> Vec<types::Str> { args.iter().map(|&arg| arg.into()).collect() }
+
+#[test]
+fn typed_arguments() {
+    let mut shell = Shell::default();
+    assert_eq!(builtin_sum(&args(&["sum"]), &mut shell), Status::from_exit_code(0));
+    assert_eq!(builtin_sum(&args(&["sum", "1", "2", "3"]), &mut shell), Status::from_exit_code(6));
+    assert_eq!(
+        builtin_sum(&args(&["sum", "-n", "1", "-2"]), &mut shell),
+        Status::from_exit_code(1)
+    );
+    assert_eq!(
+        builtin_sum(&args(&["sum", "--negate", "3"]), &mut shell),
+        Status::from_exit_code(-3)
+    );
+    assert_eq!(builtin_sum(&args(&["sum", "--", "-n"]), &mut shell), Status::bad_argument(""));
+    assert_eq!(builtin_sum(&args(&["sum", "--loud"]), &mut shell), Status::bad_argument(""));
+    assert_eq!(builtin_sum(&args(&["sum", "--help"]), &mut shell), Status::SUCCESS);
+}
+
+#[builtins_proc::builtin(
+    desc = "counts its arguments",
+    man = "
+SYNOPSIS
+    count [-h | --help] [ARGUMENTS...]
+
+DESCRIPTION
+    Exits with the number of arguments.
+"
+)]
+fn count(arguments: Vec<String>) -> Status { Status::from_exit_code(arguments.len() as i32) }
+
+#[test]
+fn arguments_without_flags() {
+    let mut shell = Shell::default();
+    assert_eq!(
+        builtin_count(&args(&["count", "-n", "--loud"]), &mut shell),
+        Status::from_exit_code(2)
+    );
+    assert_eq!(builtin_count(&args(&["count", "--", "--"]), &mut shell), Status::from_exit_code(1));
+    assert_eq!(builtin_count(&args(&["count", "--help"]), &mut shell), Status::SUCCESS);
+}
+
+#[test]
+fn signature() {
+    assert_eq!(BUILTIN_SUM_SIGNATURE.flags[0].long, "negate");
+    assert_eq!(BUILTIN_SUM_SIGNATURE.flag("-n"), Some(&BUILTIN_SUM_SIGNATURE.flags[0]));
+    assert_eq!(BUILTIN_SUM_SIGNATURE.params.len(), 2);
+    assert_eq!(BUILTIN_SUM_SIGNATURE.params[1].name, "NUMBERS");
+    assert!(BUILTIN_SUM_SIGNATURE.params[1].variadic);
+}
@@ -0,0 +1,304 @@
+use liner::Editor;
+use std::io::{self, Write};
+use termion::event::Key;
+
+pub(crate) const MAN_BIND: &str = r#"NAME
+    bind - bind a key to a function or an editing action
+
+SYNOPSIS
+    bind [KEYS [FUNCTION | ACTION]]
+    bind -e KEYS...
+
+DESCRIPTION
+    Binds KEYS to an Ion function or an editing action, run when the keys are pressed in the line
+    editor. Without a target, the binding of KEYS is printed, and without arguments all the
+    bindings are.
+
+    The function is called with the line being edited in $BUFFER and the position of the cursor
+    in $CURSOR, counted in characters. The exported values of BUFFER and CURSOR replace the line
+    and move the cursor once the function returns, and the previous values of the variables are
+    restored. The output of the function is printed below the line, which is drawn again after
+    it.
+
+    A KEY is a character, a character prefixed by ctrl- or alt-, as in ctrl-g or alt-s, or the
+    name of a key: up, down, left, right, home, end, insert, delete, backspace, pageup, pagedown,
+    tab, or f1 to f12. The binding replaces the action of the key in the line editor, if any.
+
+    KEYS is a key, or a sequence of keys separated by spaces and given as one argument, as in
+    'ctrl-x ctrl-e'. The first keys of a sequence are held until the sequence is complete, or
+    given to the line editor with the next key if it does not continue the sequence. A key bound
+    alone runs its binding at once, so that it can not start a sequence too.
+
+ACTIONS
+    beginning-of-line  Move the cursor to the start of the line.
+    end-of-line        Move the cursor to the end of the line.
+    kill-line          Delete the line after the cursor.
+    backward-kill-line Delete the line before the cursor.
+    kill-whole-line    Delete the whole line.
+    undo               Undo the last change.
+    redo               Redo the last change undone.
+    clear-screen       Clear the screen and draw the line again.
+
+OPTIONS
+    -e  Remove the bindings of each KEYS.
+
+EXAMPLES
+    fn prefix_sudo
+        export BUFFER = "sudo $BUFFER"
+        export CURSOR = $((CURSOR + 5))
+    end
+    bind alt-s prefix_sudo
+    bind 'ctrl-x ctrl-k' kill-whole-line
+"#;
+
+/// An editing action of the line editor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    BeginningOfLine,
+    EndOfLine,
+    KillLine,
+    BackwardKillLine,
+    KillWholeLine,
+    Undo,
+    Redo,
+    ClearScreen,
+}
+
+impl Action {
+    const NAMES: &'static [(&'static str, Action)] = &[
+        ("beginning-of-line", Action::BeginningOfLine),
+        ("end-of-line", Action::EndOfLine),
+        ("kill-line", Action::KillLine),
+        ("backward-kill-line", Action::BackwardKillLine),
+        ("kill-whole-line", Action::KillWholeLine),
+        ("undo", Action::Undo),
+        ("redo", Action::Redo),
+        ("clear-screen", Action::ClearScreen),
+    ];
+
+    fn name(self) -> &'static str {
+        Self::NAMES.iter().find(|&&(_, action)| action == self).map_or("", |&(name, _)| name)
+    }
+
+    pub fn apply<W: Write>(self, editor: &mut Editor<'_, W>) -> io::Result<()> {
+        match self {
+            Action::BeginningOfLine => editor.move_cursor_to_start_of_line(),
+            Action::EndOfLine => editor.move_cursor_to_end_of_line(),
+            Action::KillLine => editor.delete_all_after_cursor(),
+            Action::BackwardKillLine => editor.delete_all_before_cursor(),
+            Action::KillWholeLine => {
+                editor.move_cursor_to_end_of_line()?;
+                editor.delete_all_before_cursor()
+            }
+            Action::Undo => editor.undo().map(|_| ()),
+            Action::Redo => editor.redo().map(|_| ()),
+            Action::ClearScreen => editor.clear(),
+        }
+    }
+}
+
+/// What a key is bound to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binding {
+    Action(Action),
+    /// The name of an Ion function
+    Function(String),
+}
+
+impl Binding {
+    fn parse(target: &str) -> Self {
+        Action::NAMES.iter().find(|&&(name, _)| name == target).map_or_else(
+            || Binding::Function(target.into()),
+            |&(_, action)| Binding::Action(action),
+        )
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Binding::Action(action) => action.name(),
+            Binding::Function(name) => name,
+        }
+    }
+}
+
+/// The names of the keys without a character
+const KEYS: &[(&str, Key)] = &[
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("insert", Key::Insert),
+    ("delete", Key::Delete),
+    ("backspace", Key::Backspace),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("tab", Key::Char('\t')),
+];
+
+/// Parse a key such as `ctrl-g`, `alt-s`, `f5`, `up` or `x`
+fn parse_key(key: &str) -> Option<Key> {
+    let single = |text: &str| {
+        let mut chars = text.chars();
+        chars.next().filter(|_| chars.next().is_none())
+    };
+    if let Some(&(_, key)) = KEYS.iter().find(|&&(name, _)| name == key) {
+        Some(key)
+    } else if key.starts_with("ctrl-") {
+        single(&key["ctrl-".len()..]).map(|c| Key::Ctrl(c.to_ascii_lowercase()))
+    } else if key.starts_with("alt-") {
+        single(&key["alt-".len()..]).map(Key::Alt)
+    } else if key.len() > 1 && key.starts_with('f') {
+        key[1..].parse().ok().filter(|number| (1..=12).contains(number)).map(Key::F)
+    } else {
+        single(key).map(Key::Char)
+    }
+}
+
+/// Parse a sequence of keys separated by spaces, such as `ctrl-x ctrl-e`
+fn parse_sequence(keys: &str) -> Result<Vec<Key>, String> {
+    let invalid = || format!("invalid key: {}", keys);
+    let keys = keys.split_whitespace().map(parse_key).collect::<Option<Vec<_>>>();
+    keys.filter(|keys| !keys.is_empty()).ok_or_else(invalid)
+}
+
+fn key_name(key: Key) -> String {
+    if let Some(&(name, _)) = KEYS.iter().find(|&&(_, named)| named == key) {
+        return name.into();
+    }
+    match key {
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Alt(c) => format!("alt-{}", c),
+        Key::F(number) => format!("f{}", number),
+        Key::Char(c) => c.to_string(),
+        key => format!("{:?}", key),
+    }
+}
+
+/// The names of a sequence of keys, quoted as one argument if there are several
+fn keys_name(keys: &[Key]) -> String {
+    let names: Vec<_> = keys.iter().map(|&key| key_name(key)).collect();
+    if names.len() > 1 {
+        format!("'{}'", names.join(" "))
+    } else {
+        names.concat()
+    }
+}
+
+/// What the keys pressed match among the bindings
+#[derive(Debug, PartialEq)]
+pub enum Lookup {
+    /// The keys are bound
+    Bound(Binding),
+    /// The keys start one or more sequences bound
+    Prefix,
+    Unbound,
+}
+
+/// The keys bound with the `bind` builtin
+#[derive(Debug, Default)]
+pub struct Bindings(Vec<(Vec<Key>, Binding)>);
+
+impl Bindings {
+    pub fn get(&self, keys: &[Key]) -> Lookup {
+        if let Some((_, binding)) = self.0.iter().find(|(bound, _)| bound.as_slice() == keys) {
+            Lookup::Bound(binding.clone())
+        } else if self.0.iter().any(|(bound, _)| bound.starts_with(keys)) {
+            Lookup::Prefix
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    /// The bindings of the keys, or of all the keys, as the `bind` commands creating them
+    pub fn listing(&self, keys: Option<&str>) -> Result<String, String> {
+        let keys = keys.map(parse_sequence).transpose()?;
+        Ok(self
+            .0
+            .iter()
+            .filter(|(bound, _)| keys.as_ref().map_or(true, |keys| keys == bound))
+            .map(|(keys, binding)| format!("bind {} {}\n", keys_name(keys), binding.name()))
+            .collect())
+    }
+
+    pub fn bind(&mut self, keys: &str, target: &str) -> Result<(), String> {
+        let keys = parse_sequence(keys)?;
+        if target.is_empty() {
+            return Err("the function can not be empty".into());
+        }
+        self.0.retain(|(bound, _)| *bound != keys);
+        self.0.push((keys, Binding::parse(target)));
+        Ok(())
+    }
+
+    pub fn unbind(&mut self, keys: &str) -> Result<(), String> {
+        let keys = parse_sequence(keys)?;
+        self.0.retain(|(bound, _)| *bound != keys);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keys() {
+        assert_eq!(parse_key("ctrl-G"), Some(Key::Ctrl('g')));
+        assert_eq!(parse_key("alt-S"), Some(Key::Alt('S')));
+        assert_eq!(parse_key("f12"), Some(Key::F(12)));
+        assert_eq!(parse_key("pageup"), Some(Key::PageUp));
+        assert_eq!(parse_key("f"), Some(Key::Char('f')));
+        assert_eq!(parse_key("é"), Some(Key::Char('é')));
+        assert_eq!(parse_key("f13"), None);
+        assert_eq!(parse_key("ctrl-gg"), None);
+        assert_eq!(parse_key("meta-x"), None);
+        for &key in &[Key::Ctrl('x'), Key::Alt('s'), Key::F(5), Key::Char('\t'), Key::Char('q')] {
+            assert_eq!(parse_key(&key_name(key)), Some(key));
+        }
+        assert_eq!(parse_sequence("ctrl-x  ctrl-E"), Ok(vec![Key::Ctrl('x'), Key::Ctrl('e')]));
+        assert_eq!(parse_sequence("ctrl-x meta-e"), Err("invalid key: ctrl-x meta-e".into()));
+        assert_eq!(parse_sequence(" "), Err("invalid key:  ".into()));
+    }
+
+    #[test]
+    fn bind_keys() {
+        let mut bindings = Bindings::default();
+        bindings.bind("ctrl-g", "pick_file").unwrap();
+        bindings.bind("alt-s", "kill-whole-line").unwrap();
+        bindings.bind("ctrl-g", "fzf_file").unwrap();
+        assert!(bindings.bind("ctrl-", "fzf_file").is_err());
+        let fzf_file = Binding::Function("fzf_file".into());
+        assert_eq!(bindings.get(&[Key::Ctrl('g')]), Lookup::Bound(fzf_file));
+        let kill_whole_line = Binding::Action(Action::KillWholeLine);
+        assert_eq!(bindings.get(&[Key::Alt('s')]), Lookup::Bound(kill_whole_line));
+        assert_eq!(
+            bindings.listing(None),
+            Ok("bind alt-s kill-whole-line\nbind ctrl-g fzf_file\n".into())
+        );
+        assert_eq!(bindings.listing(Some("ctrl-g")), Ok("bind ctrl-g fzf_file\n".into()));
+        bindings.unbind("ctrl-g").unwrap();
+        assert_eq!(bindings.get(&[Key::Ctrl('g')]), Lookup::Unbound);
+    }
+
+    #[test]
+    fn bind_sequences() {
+        let mut bindings = Bindings::default();
+        bindings.bind("ctrl-x ctrl-e", "edit_line").unwrap();
+        bindings.bind("ctrl-x u", "undo").unwrap();
+        let edit_line = Binding::Function("edit_line".into());
+        assert_eq!(bindings.get(&[Key::Ctrl('x')]), Lookup::Prefix);
+        assert_eq!(bindings.get(&[Key::Ctrl('x'), Key::Ctrl('e')]), Lookup::Bound(edit_line));
+        assert_eq!(bindings.get(&[Key::Ctrl('x'), Key::Char('v')]), Lookup::Unbound);
+        assert_eq!(bindings.get(&[Key::Ctrl('e')]), Lookup::Unbound);
+        assert_eq!(
+            bindings.listing(None),
+            Ok("bind 'ctrl-x ctrl-e' edit_line\nbind 'ctrl-x u' undo\n".into())
+        );
+        assert_eq!(bindings.listing(Some("ctrl-x u")), Ok("bind 'ctrl-x u' undo\n".into()));
+        bindings.unbind("ctrl-x ctrl-e").unwrap();
+        assert_eq!(bindings.get(&[Key::Ctrl('x'), Key::Ctrl('e')]), Lookup::Unbound);
+        assert_eq!(bindings.get(&[Key::Ctrl('x')]), Lookup::Prefix);
+    }
+}
@@ -0,0 +1,174 @@
+use ion_shell::parser::{StatementSplitter, StatementVariant, Terminator};
+use liner::Editor;
+use std::io::{self, Write};
+
+/// The indentation of a level of the blocks
+const INDENT: &str = "    ";
+
+/// A block opened by a statement
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Block {
+    /// A `match`, whose statements are indented under its cases
+    Match,
+    Other,
+}
+
+impl Block {
+    /// The levels of indentation of the statements in the block
+    fn levels(self) -> usize {
+        match self {
+            Block::Match => 2,
+            Block::Other => 1,
+        }
+    }
+}
+
+/// Open and close the blocks with the statements
+fn nest(blocks: &mut Vec<Block>, statements: &str) {
+    for statement in StatementSplitter::new(statements).filter_map(Result::ok) {
+        let statement = match statement {
+            StatementVariant::And(statement)
+            | StatementVariant::Or(statement)
+            | StatementVariant::Default(statement) => statement,
+        };
+        match statement.split_whitespace().next() {
+            Some("if") | Some("while") | Some("for") | Some("fn") => blocks.push(Block::Other),
+            Some("match") => blocks.push(Block::Match),
+            Some("end") => {
+                blocks.pop();
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The blocks left open by the text, or `None` if it ends in the middle of a statement, as in
+/// an unterminated quote
+fn open_blocks(text: &str) -> Option<Vec<Block>> {
+    let mut blocks = Vec::new();
+    let mut bytes = text.bytes();
+    loop {
+        let mut terminator = Terminator::new(&mut bytes);
+        match terminator.terminate() {
+            Some(_) if !terminator.is_terminated() => return None,
+            Some(statements) => nest(&mut blocks, &statements),
+            None => return Some(blocks),
+        }
+    }
+}
+
+/// Whether the text can be run, every statement and block of it being terminated
+pub fn is_complete(text: &str) -> bool {
+    open_blocks(text).map_or(false, |blocks| blocks.is_empty())
+}
+
+/// The levels of indentation of a line following the text, or `None` if the line continues a
+/// statement
+fn indentation(text: &str, line: &str) -> Option<usize> {
+    let mut blocks = open_blocks(text)?;
+    let levels = |blocks: &[Block]| blocks.iter().map(|block| block.levels()).sum::<usize>();
+    Some(match line.split_whitespace().next() {
+        Some("end") => {
+            blocks.pop();
+            levels(&blocks)
+        }
+        Some("else") | Some("case") => levels(&blocks).saturating_sub(1),
+        _ => levels(&blocks),
+    })
+}
+
+/// Indent the line of the cursor according to the blocks it is in, unless it is the first line
+pub fn indent_line<W: Write>(editor: &mut Editor<'_, W>) -> io::Result<()> {
+    let buffer: Vec<char> = editor.current_buffer().chars().copied().collect();
+    let cursor = editor.cursor();
+    let start = match buffer[..cursor].iter().rposition(|&c| c == '\n') {
+        Some(newline) => newline + 1,
+        None => return Ok(()),
+    };
+    let end =
+        buffer[start..].iter().position(|&c| c == '\n').map_or(buffer.len(), |end| start + end);
+    let text: String = buffer[..start].iter().collect();
+    let line: String = buffer[start..end].iter().collect();
+    let indent = match indentation(&text, &line) {
+        Some(levels) => INDENT.repeat(levels),
+        None => return Ok(()),
+    };
+    let spaces = line.chars().take_while(|&c| c == ' ' || c == '\t').count();
+    if line[..spaces] == indent {
+        return Ok(());
+    }
+
+    editor.move_cursor_to(start + spaces)?;
+    for _ in 0..spaces {
+        editor.delete_before_cursor()?;
+    }
+    editor.insert_str_after_cursor(&indent)?;
+    editor.move_cursor_to(start + indent.len() + cursor.saturating_sub(start + spaces))
+}
+
+/// Split the text in the commands to run. The statements of a block are kept together, as they
+/// were written, so that the block is recorded in the history as one command.
+pub fn commands(text: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut blocks = Vec::new();
+    let mut bytes = text.bytes();
+    let (mut start, mut statements) = (0, 0);
+    loop {
+        let statement = match Terminator::new(&mut bytes).terminate() {
+            Some(statement) => statement,
+            None => break,
+        };
+        nest(&mut blocks, &statement);
+        statements += 1;
+        if blocks.is_empty() {
+            let end = text.len() - bytes.len();
+            commands.push(if statements == 1 { statement } else { text[start..end].trim().into() });
+            start = end;
+            statements = 0;
+        }
+    }
+    if statements > 0 {
+        commands.push(text[start..].trim().into());
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn complete_commands() {
+        assert!(is_complete("echo one; echo two\n"));
+        assert!(is_complete("if test 1; echo one; end"));
+        assert!(is_complete("for i in [1 2]\n    echo $i\nend"));
+        assert!(!is_complete("for i in [1 2]\n    echo $i"));
+        assert!(!is_complete("echo 'one\ntwo"));
+        assert!(!is_complete("echo [1\n2"));
+        assert!(!is_complete("match $x\n    case 1\n"));
+    }
+
+    #[test]
+    fn indent_blocks() {
+        assert_eq!(indentation("", "echo"), Some(0));
+        assert_eq!(indentation("if test 1\n", "echo"), Some(1));
+        assert_eq!(indentation("if test 1\n    echo\n", "  else"), Some(0));
+        assert_eq!(indentation("fn f\n    for i in [1]\n", "echo"), Some(2));
+        assert_eq!(indentation("fn f\n    for i in [1]\n        echo\n", "end"), Some(1));
+        assert_eq!(indentation("match $x\n", "case 1"), Some(1));
+        assert_eq!(indentation("match $x\n    case 1\n", "echo"), Some(2));
+        assert_eq!(indentation("match $x\n    case 1\n        echo\n", "end"), Some(0));
+        assert_eq!(indentation("echo 'one\n", "two'"), None);
+    }
+
+    #[test]
+    fn split_commands() {
+        assert_eq!(commands("echo one\necho two\n"), vec!["echo one", "echo two"]);
+        assert_eq!(
+            commands("echo one\nfor i in [1 2]\n    echo $i\nend\necho two"),
+            vec!["echo one", "for i in [1 2]\n    echo $i\nend", "echo two"]
+        );
+        assert_eq!(commands("if test 1; echo one; end"), vec!["if test 1; echo one; end"]);
+        assert_eq!(commands("while true\n    echo"), vec!["while true\n    echo"]);
+    }
+}
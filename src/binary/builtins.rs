@@ -1,6 +1,6 @@
 use ion_shell::{builtin, builtins::Status, types::Str, Shell, Signal};
 use nix::{sys::signal, unistd::Pid};
-use std::{error::Error, os::unix::process::CommandExt, process::Command};
+use std::{error::Error, os::unix::process::CommandExt};
 
 #[builtin(
     desc = "suspend the current shell",
@@ -73,7 +73,7 @@ DESCRIPTION
 OPTIONS
     -c  Execute command with an empty environment."
 )]
-pub fn exec(args: &[Str], _shell: &mut Shell<'_>) -> Status {
+pub fn exec(args: &[Str], shell: &mut Shell<'_>) -> Status {
     let mut clear_env = false;
     let mut idx = 1;
     for arg in args.iter().skip(1) {
@@ -87,7 +87,7 @@ pub fn exec(args: &[Str], _shell: &mut Shell<'_>) -> Status {
     match args.get(idx) {
         Some(argument) => {
             let args = if args.len() > idx + 1 { &args[idx + 1..] } else { &[] };
-            let mut command = Command::new(argument.as_str());
+            let mut command = shell.child_command(argument.as_str());
             command.args(args.iter().map(Str::as_str));
             if clear_env {
                 command.env_clear();
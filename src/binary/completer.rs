@@ -1,12 +1,51 @@
+use super::{
+    bindings::{Binding, Bindings, Lookup},
+    blocks,
+    completions::{Argument, Specs},
+    directories::Directories,
+    suggestions::Suggestions,
+};
 use auto_enums::auto_enum;
-use glob::{glob_with, MatchOptions};
-use ion_shell::{expansion::Expander, Shell};
-use liner::{Completer, CursorPosition, Event, EventKind};
-use std::{env, iter, path::PathBuf, str};
+use glob::{glob_with, MatchOptions, Pattern};
+use ion_shell::{
+    expansion::{Expander, ARRAY_METHODS, STRING_METHODS},
+    variables::Variables,
+    Colors, Shell, Value,
+};
+use liner::{Completer, CursorPosition, Editor, Event, EventKind};
+use nix::{libc, unistd};
+use std::{
+    cell::RefCell,
+    env,
+    fs::File,
+    io::{self, Read, Write},
+    iter, mem,
+    os::unix::io::FromRawFd,
+    path::PathBuf,
+    rc::Rc,
+    str,
+};
+use termion::event::Key;
 
 pub struct IonCompleter<'a, 'b> {
-    shell:      &'b Shell<'a>,
-    completion: CompletionType,
+    shell:       &'b mut Shell<'a>,
+    completion:  CompletionType,
+    /// The command whose arguments are completed
+    command:     Option<String>,
+    /// The words of the command before the one completed
+    words:       Vec<String>,
+    /// The suggestions shown at the end of the line, accepted with the right arrow or end keys
+    suggestions: Rc<Suggestions>,
+    /// The completion specs registered with the `complete` builtin
+    specs:       Rc<RefCell<Specs>>,
+    /// The directories visited, completed for the `z` builtin
+    directories: Rc<RefCell<Directories>>,
+    /// The keys bound with the `bind` builtin
+    bindings:    Rc<RefCell<Bindings>>,
+    /// The line after the previous key, if it was the tab key
+    tabbed:      Option<String>,
+    /// The keys pressed starting a sequence bound
+    pending:     Vec<Key>,
 }
 
 /// Unescape filenames for the completer so that special characters will be properly shown.
@@ -50,6 +89,28 @@ fn escape(input: &str) -> String {
     unsafe { String::from_utf8_unchecked(output) }
 }
 
+/// The variables giving the line and the cursor to the functions bound to keys
+const EDITOR_VARIABLES: &[&str] = &["BUFFER", "CURSOR"];
+
+/// What became of a key read by the line editor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Intercept {
+    /// The key is left to the line editor
+    Pass,
+    /// The key was handled
+    Consumed,
+    /// The key was handled by a function whose output was printed below the line, which must be
+    /// drawn again after it with the cursor at the given position
+    Redraw { cursor: usize },
+}
+
+/// Write the text to the terminal in raw mode, where a line feed does not return the cursor to
+/// the start of the line
+fn print_raw<W: Write>(output: &mut W, text: &str) -> io::Result<()> {
+    output.write_all(text.replace('\n', "\r\n").as_bytes())?;
+    output.flush()
+}
+
 enum CompletionType {
     Nothing,
     Command,
@@ -57,13 +118,314 @@ enum CompletionType {
 }
 
 impl<'a, 'b> IonCompleter<'a, 'b> {
-    pub fn new(shell: &'b Shell<'a>) -> Self {
-        IonCompleter { shell, completion: CompletionType::Nothing }
+    pub fn new(
+        shell: &'b mut Shell<'a>,
+        suggestions: Rc<Suggestions>,
+        specs: Rc<RefCell<Specs>>,
+        directories: Rc<RefCell<Directories>>,
+        bindings: Rc<RefCell<Bindings>>,
+    ) -> Self {
+        IonCompleter {
+            shell,
+            completion: CompletionType::Nothing,
+            command: None,
+            words: Vec::new(),
+            suggestions,
+            specs,
+            directories,
+            bindings,
+            tabbed: None,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Handle the keys bound with the `bind` builtin, and the enter key in an unfinished
+    /// statement or block, in place of the line editor
+    pub fn intercept<W: Write>(&mut self, key: Key, editor: &mut Editor<'_, W>) -> Intercept {
+        let tabbed = self.tabbed.take();
+        let mut keys = mem::replace(&mut self.pending, Vec::new());
+        keys.push(key);
+        // The keys held are given up if the key does not continue their sequence
+        if keys.len() > 1 && self.bindings.borrow().get(&keys) == Lookup::Unbound {
+            keys = vec![key];
+        }
+        let lookup = self.bindings.borrow().get(&keys);
+        match lookup {
+            Lookup::Bound(binding) => return self.run_binding(binding, editor),
+            Lookup::Prefix => {
+                self.pending = keys;
+                return Intercept::Consumed;
+            }
+            Lookup::Unbound => (),
+        }
+        // A second tab which completed nothing lists the candidates with their descriptions
+        if key == Key::Char('\t') && tabbed == Some(editor.current_buffer().to_string()) {
+            let intercept = self.describe(editor);
+            if intercept != Intercept::Pass {
+                return intercept;
+            }
+        }
+        // Continue on a new line, wherever the cursor is in the buffer
+        if key == Key::Char('\n') && !blocks::is_complete(&editor.current_buffer().to_string()) {
+            let result = editor.insert_after_cursor('\n').and_then(|_| blocks::indent_line(editor));
+            if let Err(why) = result {
+                eprintln!("ion: {}", why);
+            }
+            return Intercept::Consumed;
+        }
+        Intercept::Pass
+    }
+
+    /// Run the action or the function bound to a key
+    fn run_binding<W: Write>(&mut self, binding: Binding, editor: &mut Editor<'_, W>) -> Intercept {
+        let name = match binding {
+            Binding::Action(action) => {
+                if let Err(why) = action.apply(editor) {
+                    eprintln!("ion: bind: {}", why);
+                }
+                return Intercept::Consumed;
+            }
+            Binding::Function(name) => name,
+        };
+        match self.shell.variables().get(&name) {
+            Some(Value::Function(_)) => (),
+            _ => {
+                eprintln!("ion: bind: {}: function not found", name);
+                return Intercept::Consumed;
+            }
+        }
+
+        // The variables of the same names exported by the user are given back afterwards
+        let previous: Vec<_> = EDITOR_VARIABLES
+            .iter()
+            .map(|&variable| self.shell.variables().get_env(variable).map(String::from))
+            .collect();
+        let buffer = editor.current_buffer().to_string();
+        self.shell.variables_mut().set_env("BUFFER", buffer.as_str());
+        self.shell.variables_mut().set_env("CURSOR", editor.cursor().to_string());
+        let output = self.shell.capture(name.as_bytes());
+        // Take the terminal back from the commands run by the function
+        let _ = unistd::tcsetpgrp(libc::STDIN_FILENO, unistd::Pid::this());
+        let new_buffer = self.shell.variables_mut().remove_env("BUFFER").unwrap_or_default();
+        let new_cursor = self.shell.variables_mut().remove_env("CURSOR");
+        for (&variable, value) in EDITOR_VARIABLES.iter().zip(previous) {
+            if let Some(value) = value {
+                self.shell.variables_mut().set_env(variable, value);
+            }
+        }
+        let output = match output {
+            Ok(output) => output,
+            Err(why) => {
+                eprintln!("ion: bind: {}: {}", name, why);
+                return Intercept::Consumed;
+            }
+        };
+
+        let result = (|| -> io::Result<Intercept> {
+            if new_buffer != buffer {
+                editor.move_cursor_to_end_of_line()?;
+                editor.delete_all_before_cursor()?;
+                editor.insert_str_after_cursor(&new_buffer)?;
+            }
+            let cursor = match new_cursor.and_then(|cursor| cursor.parse().ok()) {
+                Some(cursor) => editor.current_buffer().num_chars().min(cursor),
+                None => editor.cursor(),
+            };
+            if output.stdout.is_empty() && output.stderr.is_empty() {
+                editor.move_cursor_to(cursor)?;
+                return Ok(Intercept::Consumed);
+            }
+            // The output is printed below the line, which the line editor can not draw again
+            // without erasing it
+            editor.move_cursor_to_end_of_line()?;
+            print_raw(&mut io::stdout(), "\n")?;
+            print_raw(&mut io::stdout(), &output.stdout)?;
+            print_raw(&mut io::stderr(), &output.stderr)?;
+            let last = if output.stderr.is_empty() { &output.stdout } else { &output.stderr };
+            if !last.ends_with('\n') {
+                print_raw(&mut io::stdout(), "\n")?;
+            }
+            Ok(Intercept::Redraw { cursor })
+        })();
+        result.unwrap_or_else(|why| {
+            eprintln!("ion: bind: {}", why);
+            Intercept::Consumed
+        })
+    }
+
+    /// Find the command and the words before the cursor, and what the word at the cursor is.
+    /// The word at the cursor, up to the cursor, is returned.
+    fn locate<W: Write>(&mut self, editor: &Editor<'_, W>) -> String {
+        let (words, pos) = editor.get_words_and_cursor_position();
+        let buffer = editor.current_buffer();
+        self.command = words.first().map(|&(start, end)| buffer.range(start, end));
+        let current = match pos {
+            CursorPosition::InWord(index)
+            | CursorPosition::OnWordLeftEdge(index)
+            | CursorPosition::OnWordRightEdge(index) => index,
+            CursorPosition::InSpace(Some(index), _) => index + 1,
+            CursorPosition::InSpace(None, _) => 0,
+        };
+        let before: Vec<_> =
+            words[..current].iter().map(|&(start, end)| buffer.range(start, end)).collect();
+        // Only keep the words of the last command of a pipeline
+        let command = before
+            .iter()
+            .rposition(|word| word.ends_with('|') || word.ends_with('&') || word.ends_with(';'))
+            .map_or(0, |index| index + 1);
+        self.words = before[command..].to_vec();
+        self.completion = match pos {
+            _ if words.is_empty() => CompletionType::Nothing,
+            CursorPosition::InWord(0) => CompletionType::Command,
+            CursorPosition::OnWordRightEdge(index) => {
+                if index == 0 {
+                    CompletionType::Command
+                } else {
+                    let is_pipe = words
+                        .get(index - 1)
+                        .map(|&(start, end)| buffer.range(start, end))
+                        .filter(|filename| {
+                            filename.ends_with('|')
+                                || filename.ends_with('&')
+                                || filename.ends_with(';')
+                        })
+                        .is_some();
+                    if is_pipe {
+                        CompletionType::Command
+                    } else {
+                        CompletionType::VariableAndFiles
+                    }
+                }
+            }
+            _ => CompletionType::VariableAndFiles,
+        };
+        match pos {
+            CursorPosition::InWord(index) | CursorPosition::OnWordRightEdge(index) => {
+                buffer.range(words[index].0, editor.cursor())
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// List the candidates of the spec of the command with their descriptions, on the second
+    /// tab in a row, as the line editor only shows the candidates themselves
+    fn describe<W: Write>(&mut self, editor: &mut Editor<'_, W>) -> Intercept {
+        let current = self.locate(editor);
+        match self.completion {
+            CompletionType::VariableAndFiles
+                if !current.starts_with('$') && !current.starts_with('@') => {}
+            _ => return Intercept::Pass,
+        }
+        let words: Vec<_> = self.words.iter().map(String::as_str).collect();
+        let candidates = match self.specs.borrow().complete(&words, &current) {
+            Some(candidates) => candidates,
+            None => return Intercept::Pass,
+        };
+        // The files and the output of functions have no description
+        if candidates.argument.is_some() || candidates.descriptions.is_empty() {
+            return Intercept::Pass;
+        }
+
+        let width = candidates.words.iter().map(|word| word.chars().count()).max().unwrap_or(0);
+        let mut listing = String::from("\n");
+        for word in &candidates.words {
+            match candidates.descriptions.get(word) {
+                Some(description) => {
+                    listing.push_str(&format!("{:2$}  ({})\n", word, description, width))
+                }
+                None => listing.push_str(&format!("{}\n", word)),
+            }
+        }
+        let cursor = editor.cursor();
+        // As for the output of functions bound to keys, the line is drawn again below the listing
+        let result = editor.move_cursor_to_end_of_line();
+        match result.and_then(|_| print_raw(&mut io::stdout(), &listing)) {
+            Ok(()) => Intercept::Redraw { cursor },
+            Err(why) => {
+                eprintln!("ion: {}", why);
+                Intercept::Consumed
+            }
+        }
+    }
+
+    /// Complete the directories visited matching the arguments of `z`
+    fn directory_completions(&self, start: &str) -> Vec<String> {
+        let mut fragments: Vec<_> = self.words[1..].iter().map(String::as_str).collect();
+        fragments.retain(|fragment| !fragment.starts_with('-'));
+        fragments.push(start);
+        let matches = self.directories.borrow().matches(&fragments).unwrap_or_default();
+        matches.into_iter().map(|(_, dir)| escape(&dir.to_string_lossy())).collect()
+    }
+
+    /// Complete the arguments of a command from its spec, if it has one
+    fn spec_completions(&mut self, start: &str) -> Option<Vec<String>> {
+        let words: Vec<_> = self.words.iter().map(String::as_str).collect();
+        let candidates = self.specs.borrow().complete(&words, start)?;
+        let mut completions = candidates.words;
+        match candidates.argument {
+            Some(Argument::File) => {
+                completions.extend(IonFileCompleter::new(None, &self.shell).completions(start))
+            }
+            Some(Argument::Directory) => completions.extend(
+                IonFileCompleter::new(None, &self.shell)
+                    .completions(start)
+                    .into_iter()
+                    .filter(|file| file.ends_with('/')),
+            ),
+            Some(Argument::Function(name)) => {
+                completions.extend(self.function_completions(&name, start))
+            }
+            Some(Argument::List(_)) | None => (),
+        }
+        Some(completions)
+    }
+
+    /// Call a completion function with the word being completed and the command line before
+    /// it, and read the candidates it prints, one per line
+    fn function_completions(&mut self, name: &str, start: &str) -> Vec<String> {
+        let function = match self.shell.variables().get(name) {
+            Some(Value::Function(function)) => function.clone(),
+            _ => return Vec::new(),
+        };
+        let (mut reader, writer) = match unistd::pipe() {
+            Ok((reader, writer)) => unsafe {
+                (File::from_raw_fd(reader), File::from_raw_fd(writer))
+            },
+            Err(_) => return Vec::new(),
+        };
+
+        let line = self.words.join(" ");
+        let previous = self.shell.stdout(writer);
+        let result = self.shell.execute_function(&function, &[name, start, line.as_str()]);
+        // Close the pipe, so that the output can be read to the end
+        self.shell.stdout(previous);
+        // Take the terminal back from the commands run by the function
+        let _ = unistd::tcsetpgrp(libc::STDIN_FILENO, unistd::Pid::this());
+
+        let mut output = String::new();
+        if let Err(why) = result {
+            eprintln!("ion: completion function {}: {}", name, why);
+        } else if let Err(why) = reader.read_to_string(&mut output) {
+            eprintln!("ion: completion function {}: {}", name, why);
+        }
+        output.lines().filter(|line| line.starts_with(start)).map(Into::into).collect()
     }
 }
 
 impl<'a, 'b> Completer for IonCompleter<'a, 'b> {
     fn completions(&mut self, start: &str) -> Vec<String> {
+        if let CompletionType::VariableAndFiles = self.completion {
+            if self.words.first().map_or(false, |command| command == "z") && !start.starts_with('-')
+            {
+                return self.directory_completions(start);
+            }
+            if !start.starts_with('$') && !start.starts_with('@') {
+                if let Some(completions) = self.spec_completions(start) {
+                    return completions;
+                }
+            }
+        }
+
         let mut completions = IonFileCompleter::new(None, &self.shell).completions(start);
         let vars = self.shell.variables();
 
@@ -73,7 +435,32 @@ impl<'a, 'b> Completer for IonCompleter<'a, 'b> {
                 // Creates a list of definitions from the shell environment that
                 // will be used
                 // in the creation of a custom completer.
-                if start.is_empty() {
+                if start.starts_with('-') {
+                    // Add the flags declared by the builtin
+                    let signature = self
+                        .command
+                        .as_ref()
+                        .and_then(|command| self.shell.builtins().get_signature(command));
+                    if let Some(signature) = signature {
+                        completions.extend(
+                            signature
+                                .flags
+                                .iter()
+                                .map(|flag| format!("--{}", flag.long))
+                                .filter(|flag| flag.starts_with(start)),
+                        );
+                    }
+                } else if self.command.as_ref().map_or(false, |cmd| cmd == "fn" || cmd == "drop") {
+                    completions = vars
+                        .functions()
+                        .map(|(name, _)| name.to_string())
+                        .filter(|name| name.starts_with(start))
+                        .collect();
+                } else if let Some(expansions) =
+                    index_completions(vars, start).or_else(|| color_completions(start))
+                {
+                    completions = expansions;
+                } else if start.is_empty() {
                     completions.extend(vars.string_vars().map(|(s, _)| format!("${}", s)));
                     completions.extend(vars.arrays().map(|(s, _)| format!("@{}", s)));
                 } else if start.starts_with('$') {
@@ -86,12 +473,24 @@ impl<'a, 'b> Completer for IonCompleter<'a, 'b> {
                             .filter(|(s, _)| s.starts_with(&start[1..]))
                             .map(|(s, _)| format!("${}", &s)),
                     );
+                    completions.extend(
+                        STRING_METHODS
+                            .iter()
+                            .filter(|method| method.starts_with(&start[1..]))
+                            .map(|method| format!("${}(", method)),
+                    );
                 } else if start.starts_with('@') {
                     completions.extend(
                         vars.arrays()
                             .filter(|(s, _)| s.starts_with(&start[1..]))
                             .map(|(s, _)| format!("@{}", &s)),
                     );
+                    completions.extend(
+                        ARRAY_METHODS
+                            .iter()
+                            .filter(|method| method.starts_with(&start[1..]))
+                            .map(|method| format!("@{}(", method)),
+                    );
                 }
             }
             CompletionType::Command => {
@@ -115,22 +514,23 @@ impl<'a, 'b> Completer for IonCompleter<'a, 'b> {
                 // Creates completers containing definitions from all directories
                 // listed
                 // in the environment's **$PATH** variable.
-                let file_completers: Vec<_> = if let Some(paths) = env::var_os("PATH") {
-                    env::split_paths(&paths)
-                        .map(|s| {
-                            let s = if !s.to_string_lossy().ends_with('/') {
-                                let mut oss = s.into_os_string();
-                                oss.push("/");
-                                oss.into()
-                            } else {
-                                s
-                            };
-                            IonFileCompleter::new(Some(s), &self.shell)
-                        })
-                        .collect()
-                } else {
-                    vec![IonFileCompleter::new(Some("/bin/".into()), &self.shell)]
-                };
+                let file_completers: Vec<_> =
+                    if let Some(paths) = self.shell.variables().get_env("PATH") {
+                        env::split_paths(paths)
+                            .map(|s| {
+                                let s = if !s.to_string_lossy().ends_with('/') {
+                                    let mut oss = s.into_os_string();
+                                    oss.push("/");
+                                    oss.into()
+                                } else {
+                                    s
+                                };
+                                IonFileCompleter::new(Some(s), &self.shell)
+                            })
+                            .collect()
+                    } else {
+                        vec![IonFileCompleter::new(Some("/bin/".into()), &self.shell)]
+                    };
                 // Merge the collected definitions with the file path definitions.
                 completions.extend(MultiCompleter::new(file_completers).completions(start));
             }
@@ -140,45 +540,72 @@ impl<'a, 'b> Completer for IonCompleter<'a, 'b> {
         completions
     }
 
-    fn on_event<W: std::io::Write>(&mut self, event: Event<'_, '_, W>) {
-        if let EventKind::BeforeComplete = event.kind {
-            let (words, pos) = event.editor.get_words_and_cursor_position();
-            self.completion = match pos {
-                _ if words.is_empty() => CompletionType::Nothing,
-                CursorPosition::InWord(0) => CompletionType::Command,
-                CursorPosition::OnWordRightEdge(index) => {
-                    if index == 0 {
-                        CompletionType::Command
-                    } else {
-                        let is_pipe = words
-                            .into_iter()
-                            .nth(index - 1)
-                            .map(|(start, end)| event.editor.current_buffer().range(start, end))
-                            .filter(|filename| {
-                                filename.ends_with('|')
-                                    || filename.ends_with('&')
-                                    || filename.ends_with(';')
-                            })
-                            .is_some();
-                        if is_pipe {
-                            CompletionType::Command
-                        } else {
-                            CompletionType::VariableAndFiles
-                        }
-                    }
+    fn on_event<W: Write>(&mut self, event: Event<'_, '_, W>) {
+        // Dedent the lines starting with `end`, `else` or `case` as they are typed
+        if let EventKind::AfterKey(Key::Char(c)) = event.kind {
+            if c != '\n' && c != '\t' {
+                let _ = blocks::indent_line(event.editor);
+                return;
+            }
+        }
+
+        if let EventKind::BeforeKey(Key::Right) | EventKind::BeforeKey(Key::End) = event.kind {
+            // Accept the suggestion when moving past the end of the line
+            if event.editor.cursor() == event.editor.current_buffer().num_chars() {
+                let line = event.editor.current_buffer().to_string();
+                if let Some(suggestion) = self.suggestions.suggest(&line) {
+                    let _ = event.editor.insert_str_after_cursor(suggestion);
                 }
-                _ => CompletionType::VariableAndFiles,
-            };
+            }
+        } else if let EventKind::AfterKey(Key::Char('\t')) = event.kind {
+            self.tabbed = Some(event.editor.current_buffer().to_string());
+        } else if let EventKind::BeforeComplete = event.kind {
+            self.locate(event.editor);
         }
     }
 }
 
+/// Complete the keys of a map or the indices of an array, as in `$map[key` or `@array[index`
+fn index_completions(vars: &Variables<'_>, start: &str) -> Option<Vec<String>> {
+    if (!start.starts_with('$') && !start.starts_with('@')) || start.contains(']') {
+        return None;
+    }
+    let bracket = start.find('[')?;
+    let mut keys: Vec<String> = match vars.get(&start[1..bracket])? {
+        Value::HashMap(map) => map.keys().map(ToString::to_string).collect(),
+        Value::BTreeMap(map) => map.keys().map(ToString::to_string).collect(),
+        Value::Array(array) => (0..array.len()).map(|index| index.to_string()).collect(),
+        _ => Vec::new(),
+    };
+    keys.sort();
+    let (prefix, key) = start.split_at(bracket + 1);
+    Some(
+        keys.into_iter()
+            .filter(|candidate| candidate.starts_with(key))
+            .map(|candidate| format!("{}{}]", prefix, candidate))
+            .collect(),
+    )
+}
+
+/// Complete the names of the `c::` and `color::` namespaces, as in `$c::bold,red`
+fn color_completions(start: &str) -> Option<Vec<String>> {
+    let namespace = ["$c::", "$color::"].iter().find(|namespace| start.starts_with(*namespace))?;
+    let (prefix, color) =
+        start.split_at(start.rfind(',').map_or(namespace.len(), |comma| comma + 1));
+    Some(
+        Colors::names()
+            .filter(|name| name.starts_with(color))
+            .map(|name| [prefix, name].concat())
+            .collect(),
+    )
+}
+
 /// Performs escaping to an inner `FilenameCompleter` to enable a handful of special cases
 /// needed by the shell, such as expanding '~' to a home directory, or adding a backslash
 /// when a special character is contained within an expanded filename.
 pub struct IonFileCompleter<'a, 'b> {
     shell:       &'b Shell<'a>,
-    /// The directory the expansion takes place in
+    /// The directory the expansion takes place in, the shell's working directory by default
     path:        PathBuf,
     for_command: bool,
 }
@@ -188,7 +615,7 @@ impl<'a, 'b> IonFileCompleter<'a, 'b> {
         // The only time a path is Some is when looking for a command not a directory
         // so save this fact to strip the paths when completing commands.
         let for_command = path.is_some();
-        let path = path.unwrap_or_default();
+        let path = path.unwrap_or_else(|| shell.dir_stack().current_dir().to_path_buf());
         IonFileCompleter { shell, path, for_command }
     }
 }
@@ -268,8 +695,14 @@ fn filename_completion<'a>(start: &'a str, path: &'a PathBuf) -> impl Iterator<I
         split_start.next();
         string.push('/');
     } else {
-        string.push_str(&path.to_string_lossy());
+        // The process does not follow the working directory of the shell, so relative paths
+        // are resolved against the directory given, and shown without it
+        string.push_str(&Pattern::escape(&path.to_string_lossy()));
+        if !string.ends_with('/') {
+            string.push('/');
+        }
     }
+    let relative = !unescaped_start.starts_with('/');
 
     for element in split_start {
         string.push_str(element);
@@ -294,7 +727,8 @@ fn filename_completion<'a>(start: &'a str, path: &'a PathBuf) -> impl Iterator<I
     .ok()
     .map(|completions| {
         completions.filter_map(Result::ok).filter_map(move |file| {
-            let out = file.to_str()?;
+            let shown = if relative { file.strip_prefix(path).ok()? } else { file.as_path() };
+            let out = shown.to_str()?;
             let mut joined = String::with_capacity(out.len() + 3); // worst case senario
             if unescaped_start.starts_with("./") {
                 joined.push_str("./");
@@ -334,6 +768,9 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ion_shell::types::{self, array};
+    use liner::{Context, Prompt};
+    use std::path::Path;
 
     #[test]
     fn filename_completion() {
@@ -346,4 +783,143 @@ mod tests {
         }
         assert_eq!(completer.completions("tes/fil"), vec!["testing/file_with_text"]);
     }
+
+    #[test]
+    fn shell_directory_completion() {
+        let mut shell = Shell::default();
+        shell.dir_stack_mut().change_and_push_dir(Path::new("testing")).unwrap();
+        let mut completer = IonFileCompleter::new(None, &shell);
+        assert_eq!(completer.completions("file"), vec!["file_with_text"]);
+        assert_eq!(completer.completions("./emp"), vec!["./empty_file"]);
+        assert_eq!(completer.completions("../testing/sym"), vec!["../testing/symlink"]);
+    }
+
+    #[test]
+    fn expansion_completion() {
+        let mut shell = Shell::default();
+        shell.variables_mut().set("array", array!["a", "b"]);
+        assert_eq!(
+            index_completions(shell.variables(), "@array["),
+            Some(vec!["@array[0]".to_string(), "@array[1]".to_string()])
+        );
+        assert_eq!(index_completions(shell.variables(), "@array"), None);
+        assert_eq!(
+            color_completions("$c::bold,light_b"),
+            Some(vec!["$c::bold,light_blue".to_string(), "$c::bold,light_bluebg".to_string()])
+        );
+    }
+
+    #[test]
+    fn run_bindings() {
+        let mut shell = Shell::default();
+        let functions = r#"
+fn prefix_sudo
+    export BUFFER = "sudo $BUFFER"
+    export CURSOR = 5
+end
+fn greet
+    echo hello
+end
+"#;
+        shell.execute_command(functions.as_bytes()).unwrap();
+        shell.variables_mut().set_env("BUFFER", "kept");
+        let bindings = Rc::new(RefCell::new(Bindings::default()));
+        bindings.borrow_mut().bind("alt-s", "prefix_sudo").unwrap();
+        bindings.borrow_mut().bind("ctrl-a", "end-of-line").unwrap();
+        bindings.borrow_mut().bind("f1", "greet").unwrap();
+        bindings.borrow_mut().bind("ctrl-x ctrl-e", "beginning-of-line").unwrap();
+        let mut completer =
+            IonCompleter::new(&mut shell, Rc::default(), Rc::default(), Rc::default(), bindings);
+        let mut context = Context::new();
+        let prompt = Prompt::from("$ ".to_string());
+        let mut editor = Editor::new(Vec::new(), prompt, None, &mut context).unwrap();
+        editor.insert_str_after_cursor("echo").unwrap();
+        editor.move_cursor_to(0).unwrap();
+
+        // The keys bound are not given to the line editor
+        assert_eq!(completer.intercept(Key::Char('x'), &mut editor), Intercept::Pass);
+        assert_eq!(completer.intercept(Key::Ctrl('a'), &mut editor), Intercept::Consumed);
+        assert_eq!(editor.cursor(), 4);
+        assert_eq!(completer.intercept(Key::Alt('s'), &mut editor), Intercept::Consumed);
+        assert_eq!(editor.current_buffer().to_string(), "sudo echo");
+        assert_eq!(editor.cursor(), 5);
+        assert_eq!(completer.shell.variables().get_env("BUFFER"), Some("kept"));
+        assert_eq!(completer.shell.variables().get_env("CURSOR"), None);
+        assert_eq!(completer.intercept(Key::F(1), &mut editor), Intercept::Redraw { cursor: 5 });
+        assert_eq!(editor.current_buffer().to_string(), "sudo echo");
+
+        // The first keys of a sequence are held until it is complete
+        assert_eq!(completer.intercept(Key::Ctrl('x'), &mut editor), Intercept::Consumed);
+        assert_eq!(completer.intercept(Key::Ctrl('e'), &mut editor), Intercept::Consumed);
+        assert_eq!(editor.cursor(), 0);
+        assert_eq!(completer.intercept(Key::Ctrl('x'), &mut editor), Intercept::Consumed);
+        assert_eq!(completer.intercept(Key::Char('y'), &mut editor), Intercept::Pass);
+        assert_eq!(completer.intercept(Key::Ctrl('e'), &mut editor), Intercept::Pass);
+        assert_eq!(completer.intercept(Key::Ctrl('x'), &mut editor), Intercept::Consumed);
+        assert_eq!(completer.intercept(Key::Ctrl('a'), &mut editor), Intercept::Consumed);
+        assert_eq!(editor.cursor(), 9);
+    }
+
+    #[test]
+    fn continue_blocks() {
+        let mut shell = Shell::default();
+        let mut completer = IonCompleter::new(
+            &mut shell,
+            Rc::default(),
+            Rc::default(),
+            Rc::default(),
+            Rc::default(),
+        );
+        let mut context = Context::new();
+        let prompt = Prompt::from("$ ".to_string());
+        let mut editor = Editor::new(Vec::new(), prompt, None, &mut context).unwrap();
+        editor.insert_str_after_cursor("for i in [1 2]\n    echo $i").unwrap();
+
+        // Enter on the first line of the block opens an indented line below it
+        editor.move_cursor_to(14).unwrap();
+        assert_eq!(completer.intercept(Key::Char('\n'), &mut editor), Intercept::Consumed);
+        assert_eq!(editor.current_buffer().to_string(), "for i in [1 2]\n    \n    echo $i");
+        assert_eq!(editor.cursor(), 19);
+
+        editor.move_cursor_to_end_of_line().unwrap();
+        assert_eq!(completer.intercept(Key::Char('\n'), &mut editor), Intercept::Consumed);
+        editor.insert_str_after_cursor("end").unwrap();
+        assert_eq!(
+            editor.current_buffer().to_string(),
+            "for i in [1 2]\n    \n    echo $i\n    end"
+        );
+        // The block is complete: the line is left to be accepted, even with the cursor before
+        // its end
+        editor.move_cursor_to(3).unwrap();
+        assert_eq!(completer.intercept(Key::Char('\n'), &mut editor), Intercept::Pass);
+    }
+
+    #[test]
+    fn describe_candidates() {
+        let mut shell = Shell::default();
+        let specs = Rc::new(RefCell::new(Specs::default()));
+        let options = ["-s", "add", "-d", "Add files", "-s", "commit"];
+        let options: Vec<types::Str> = options.iter().map(|&option| option.into()).collect();
+        specs.borrow_mut().register("git", &options).unwrap();
+        let mut completer =
+            IonCompleter::new(&mut shell, Rc::default(), specs, Rc::default(), Rc::default());
+        let mut context = Context::new();
+        let prompt = Prompt::from("$ ".to_string());
+        let mut editor = Editor::new(Vec::new(), prompt, None, &mut context).unwrap();
+        editor.insert_str_after_cursor("git ").unwrap();
+
+        // The first tab is left to the line editor, and the second one lists the descriptions
+        assert_eq!(completer.intercept(Key::Char('\t'), &mut editor), Intercept::Pass);
+        completer.tabbed = Some("git ".into());
+        assert_eq!(
+            completer.intercept(Key::Char('\t'), &mut editor),
+            Intercept::Redraw { cursor: 4 }
+        );
+        assert_eq!(editor.current_buffer().to_string(), "git ");
+
+        // Without descriptions, the line editor lists the candidates
+        editor.insert_str_after_cursor("add ").unwrap();
+        completer.tabbed = Some("git add ".into());
+        assert_eq!(completer.intercept(Key::Char('\t'), &mut editor), Intercept::Pass);
+    }
 }
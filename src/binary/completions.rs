@@ -0,0 +1,343 @@
+use ion_shell::types::Str;
+use itertools::Itertools;
+use std::{collections::BTreeMap, fmt};
+
+pub(crate) const MAN_COMPLETE: &str = r#"NAME
+    complete - describe how the arguments of a command are completed
+
+SYNOPSIS
+    complete [-e] [COMMAND [OPTIONS...]]
+
+DESCRIPTION
+    Registers the completion spec of COMMAND, used when completing its arguments with the tab
+    key. The spec of a subcommand is registered with its full name, for instance "git commit".
+    Without options, the spec of COMMAND is printed, and without arguments all the specs are.
+
+    The specs found in $XDG_CONFIG_HOME/ion/completions/*.ion are loaded on startup.
+
+OPTIONS:
+    -s, --subcommand NAME  Complete NAME as a subcommand.
+    -f, --flag FLAG        Complete FLAG, such as -v or --verbose.
+    -d, --description TEXT Describe the previous subcommand or flag. The descriptions are shown
+                           with the candidates when tab is pressed twice.
+    --file                 The previous flag, or the arguments if no flag was given, take a file.
+    --directory            The previous flag, or the arguments, take a directory.
+    -l, --list WORDS       The previous flag, or the arguments, take one of the WORDS.
+    -F, --function NAME    The previous flag, or the arguments, are completed with the lines
+                           printed by the function NAME. It is called with the word being
+                           completed and the command line before it.
+    -e, --erase            Remove the spec of COMMAND.
+
+EXAMPLES:
+    complete git -s add -d "Add file contents to the index" -s commit
+    complete "git commit" -f --message -d "Use the given message" -f --amend
+    complete "git checkout" -F git_branches
+    complete ls --file -f --color -l "auto always never"
+"#;
+
+/// The values taken by a flag or by the arguments of a command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Argument {
+    File,
+    Directory,
+    List(Vec<String>),
+    /// The name of the function printing the candidates
+    Function(String),
+}
+
+/// A subcommand or a flag
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Entry {
+    name:        String,
+    description: Option<String>,
+    argument:    Option<Argument>,
+}
+
+/// How the arguments of a command are completed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Spec {
+    subcommands: Vec<Entry>,
+    flags:       Vec<Entry>,
+    arguments:   Option<Argument>,
+}
+
+/// The candidates for the word being completed
+#[derive(Debug, Default, PartialEq)]
+pub struct Candidates {
+    /// The candidates known from the spec
+    pub words:        Vec<String>,
+    /// The descriptions of the subcommands and flags among the candidates
+    pub descriptions: BTreeMap<String, String>,
+    /// The files, directories or function output to complete as well
+    pub argument:     Option<Argument>,
+}
+
+/// The specs registered with the `complete` builtin, by command
+#[derive(Debug, Default)]
+pub struct Specs(BTreeMap<String, Spec>);
+
+/// The option of the spec last added, which `--description` and the argument types apply to
+enum Last {
+    Nothing,
+    Subcommand,
+    Flag,
+}
+
+fn value<'s>(args: &mut impl Iterator<Item = &'s Str>, option: &str) -> Result<&'s str, String> {
+    args.next().map(Str::as_str).ok_or_else(|| format!("{} requires a value", option))
+}
+
+/// Quote a word of the spec so that it can be read back by the shell
+fn quote(word: &str) -> String {
+    if !word.is_empty() && word.chars().all(|c| c.is_alphanumeric() || "-_./:=+,".contains(c)) {
+        word.into()
+    } else if !word.contains('\'') {
+        format!("'{}'", word)
+    } else {
+        let mut quoted = String::with_capacity(word.len() + 2);
+        quoted.push('"');
+        for character in word.chars() {
+            if "\\\"$@".contains(character) {
+                quoted.push('\\');
+            }
+            quoted.push(character);
+        }
+        quoted.push('"');
+        quoted
+    }
+}
+
+impl Candidates {
+    fn new(argument: &Argument, current: &str) -> Self {
+        match argument {
+            Argument::List(words) => Candidates {
+                words: words.iter().filter(|word| word.starts_with(current)).cloned().collect(),
+                ..Candidates::default()
+            },
+            argument => Candidates { argument: Some(argument.clone()), ..Candidates::default() },
+        }
+    }
+
+    /// Add the subcommands or flags starting with `current`, with their descriptions
+    fn extend<'e>(&mut self, entries: impl Iterator<Item = &'e Entry>, current: &str) {
+        for entry in entries.filter(|entry| entry.name.starts_with(current)) {
+            self.words.push(entry.name.clone());
+            if let Some(ref description) = entry.description {
+                self.descriptions.insert(entry.name.clone(), description.clone());
+            }
+        }
+    }
+}
+
+impl Spec {
+    fn flag(&self, name: &str) -> Option<&Entry> { self.flags.iter().find(|flag| flag.name == name) }
+
+    fn is_subcommand(&self, name: &str) -> bool {
+        self.subcommands.iter().any(|subcommand| subcommand.name == name)
+    }
+}
+
+/// The name of the spec of a command, with the words separated by a single space
+fn key(command: &str) -> String { command.split_whitespace().join(" ") }
+
+impl Specs {
+    /// Remove the spec of a command
+    pub fn remove(&mut self, command: &str) -> Option<Spec> { self.0.remove(&key(command)) }
+
+    /// The commands registering the spec of `command`, or all the specs if `None`
+    pub fn listing(&self, command: Option<&str>) -> String {
+        self.0
+            .iter()
+            .filter(|(name, _)| command.map_or(true, |command| key(command) == **name))
+            .map(|(name, spec)| format!("complete {}{}\n", quote(name), spec))
+            .collect()
+    }
+
+    /// Add the options given to the `complete` builtin to the spec of `command`
+    pub fn register(&mut self, command: &str, options: &[Str]) -> Result<(), String> {
+        let command = key(command);
+        if command.is_empty() {
+            return Err("the command can not be empty".into());
+        }
+        let mut spec = self.0.get(&command).cloned().unwrap_or_default();
+        let mut last = Last::Nothing;
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            let argument = match option.as_str() {
+                "-s" | "--subcommand" => {
+                    let name = value(&mut options, option)?;
+                    spec.subcommands.retain(|subcommand| subcommand.name != name);
+                    spec.subcommands.push(Entry { name: name.into(), ..Entry::default() });
+                    last = Last::Subcommand;
+                    continue;
+                }
+                "-f" | "--flag" => {
+                    let name = value(&mut options, option)?;
+                    if !name.starts_with('-') {
+                        return Err(format!("the flag '{}' must start with a dash", name));
+                    }
+                    spec.flags.retain(|flag| flag.name != name);
+                    spec.flags.push(Entry { name: name.into(), ..Entry::default() });
+                    last = Last::Flag;
+                    continue;
+                }
+                "-d" | "--description" => {
+                    let entry = match last {
+                        Last::Subcommand => spec.subcommands.last_mut(),
+                        Last::Flag => spec.flags.last_mut(),
+                        Last::Nothing => None,
+                    };
+                    let entry = entry
+                        .ok_or_else(|| format!("{} must follow a subcommand or a flag", option))?;
+                    entry.description = Some(value(&mut options, option)?.into());
+                    continue;
+                }
+                "--file" => Argument::File,
+                "--directory" => Argument::Directory,
+                "-l" | "--list" => Argument::List(
+                    value(&mut options, option)?.split_whitespace().map(Into::into).collect(),
+                ),
+                "-F" | "--function" => Argument::Function(value(&mut options, option)?.into()),
+                _ => return Err(format!("invalid option '{}'", option)),
+            };
+            match last {
+                Last::Flag => spec.flags.last_mut().unwrap().argument = Some(argument),
+                _ => spec.arguments = Some(argument),
+            }
+        }
+        self.0.insert(command, spec);
+        Ok(())
+    }
+
+    /// The candidates for the `current` word, given the words before it. `None` is returned if
+    /// there is no spec for the command, so that the default completions are used.
+    pub fn complete(&self, words: &[&str], current: &str) -> Option<Candidates> {
+        let mut command = (*words.first()?).to_string();
+        let mut spec = self.0.get(&command)?;
+        // Whether an argument was given, after which subcommands are not expected anymore
+        let mut positional = false;
+        // The argument of the previous flag
+        let mut pending = None;
+        for word in &words[1..] {
+            if pending.take().is_some() {
+                continue;
+            }
+            if let Some(flag) = spec.flag(word) {
+                pending = flag.argument.as_ref();
+            } else if !positional && spec.is_subcommand(word) {
+                command.push(' ');
+                command.push_str(word);
+                spec = self.0.get(&command)?;
+            } else if !word.starts_with('-') {
+                positional = true;
+            }
+        }
+
+        if let Some(argument) = pending {
+            return Some(Candidates::new(argument, current));
+        }
+        if current.starts_with('-') {
+            let mut candidates = Candidates::default();
+            candidates.extend(spec.flags.iter(), current);
+            return Some(candidates);
+        }
+        let mut candidates = match spec.arguments {
+            Some(ref argument) => Candidates::new(argument, current),
+            None if positional || spec.subcommands.is_empty() => {
+                Candidates { argument: Some(Argument::File), ..Candidates::default() }
+            }
+            None => Candidates::default(),
+        };
+        if !positional {
+            candidates.extend(spec.subcommands.iter(), current);
+        }
+        Some(candidates)
+    }
+}
+
+impl fmt::Display for Argument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Argument::File => write!(f, "--file"),
+            Argument::Directory => write!(f, "--directory"),
+            Argument::List(words) => write!(f, "--list {}", quote(&words.join(" "))),
+            Argument::Function(name) => write!(f, "--function {}", quote(name)),
+        }
+    }
+}
+
+impl fmt::Display for Spec {
+    /// Print the options of the `complete` builtin registering the spec
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref argument) = self.arguments {
+            write!(f, " {}", argument)?;
+        }
+        let subcommands = self.subcommands.iter().map(|entry| ("--subcommand", entry));
+        let flags = self.flags.iter().map(|entry| ("--flag", entry));
+        for (option, entry) in subcommands.chain(flags) {
+            write!(f, " {} {}", option, quote(&entry.name))?;
+            if let Some(ref description) = entry.description {
+                write!(f, " --description {}", quote(description))?;
+            }
+            if let Some(ref argument) = entry.argument {
+                write!(f, " {}", argument)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(specs: &mut Specs, command: &str, options: &[&str]) {
+        let options: Vec<Str> = options.iter().map(|&option| option.into()).collect();
+        specs.register(command, &options).unwrap();
+    }
+
+    #[test]
+    fn complete_from_specs() {
+        let mut specs = Specs::default();
+        register(
+            &mut specs,
+            "git",
+            &["-s", "add", "-d", "Add files", "-s", "commit", "-s", "clone"],
+        );
+        register(&mut specs, "git", &["-f", "--no-pager", "-f", "-C", "--directory"]);
+        register(
+            &mut specs,
+            "git  commit",
+            &["-f", "--amend", "-f", "--cleanup", "-l", "strip verbatim"],
+        );
+        register(&mut specs, "git checkout", &["-F", "git_branches"]);
+
+        let words = |candidates: Option<Candidates>| candidates.unwrap().words;
+        assert_eq!(words(specs.complete(&["git"], "c")), vec!["commit", "clone"]);
+        assert_eq!(words(specs.complete(&["git", "-C", "/tmp"], "a")), vec!["add"]);
+        assert_eq!(specs.complete(&["git", "-C"], "").unwrap().argument, Some(Argument::Directory));
+        assert_eq!(words(specs.complete(&["git", "commit"], "--")), vec!["--amend", "--cleanup"]);
+        let candidates = specs.complete(&["git"], "").unwrap();
+        assert_eq!(candidates.words, vec!["add", "commit", "clone"]);
+        assert_eq!(candidates.descriptions.get("add").map(String::as_str), Some("Add files"));
+        assert_eq!(candidates.descriptions.len(), 1);
+        assert_eq!(words(specs.complete(&["git", "commit", "--cleanup"], "s")), vec!["strip"]);
+        assert_eq!(
+            specs.complete(&["git", "commit", "--amend"], "").unwrap().argument,
+            Some(Argument::File)
+        );
+        // Subcommands without a spec use the default completions
+        assert_eq!(specs.complete(&["git", "add"], ""), None);
+        assert_eq!(specs.complete(&["ls"], ""), None);
+
+        assert!(specs.register("git", &["-d".into(), "nothing to describe".into()]).is_err());
+        assert!(specs.register("git", &["-f".into(), "no-dash".into()]).is_err());
+        assert_eq!(
+            specs.listing(Some("git")).lines().next(),
+            Some(
+                "complete git --subcommand add --description 'Add files' --subcommand commit \
+                 --subcommand clone --flag --no-pager --flag -C --directory"
+            )
+        );
+    }
+}
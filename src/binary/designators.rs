@@ -1,53 +1,366 @@
-use super::lexer::{DesignatorLexer, DesignatorToken};
+use super::{
+    history::Record,
+    lexer::{DesignatorLexer, DesignatorToken},
+};
 use ion_shell::parser::lexers::ArgumentSplitter;
-use liner::Context;
-use std::{borrow::Cow, str};
-
-pub fn expand_designators<'a>(context: &Context, cmd: &'a str) -> Cow<'a, str> {
-    if let Some(buffer) = context.history.buffers.back() {
-        let buffer = buffer.as_bytes();
-        let buffer = unsafe { str::from_utf8_unchecked(&buffer) };
-        let mut output = String::with_capacity(cmd.len());
-        for token in DesignatorLexer::new(cmd.as_bytes()) {
-            match token {
-                DesignatorToken::Text(text) => output.push_str(text),
-                DesignatorToken::Designator(text) => match text {
-                    "!!" => output.push_str(buffer),
-                    "!$" => output.push_str(last_arg(buffer)),
-                    "!0" => output.push_str(command(buffer)),
-                    "!^" => output.push_str(first_arg(buffer)),
-                    "!*" => output.push_str(&args(buffer)),
-                    _ => output.push_str(text),
-                },
+use itertools::Itertools;
+use std::borrow::Cow;
+
+/// The command of the history selected by a designator
+#[derive(Debug, PartialEq)]
+enum Event<'a> {
+    /// `!n`, the n-th command of the history
+    Absolute(usize),
+    /// `!-n`: the n-th command before the current line, where `!!` is `!-1`
+    Relative(usize),
+    /// `!prefix`, the last command starting with the prefix
+    Prefix(&'a str),
+    /// `!?substring?`, the last command containing the substring
+    Contains(&'a str),
+}
+
+/// A word of the command, counting from the command name
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bound {
+    Index(usize),
+    /// `$`
+    Last,
+    /// The end of `x-`, which leaves out the last word
+    BeforeLast,
+}
+
+/// The words of the command selected by a designator
+#[derive(Debug, PartialEq)]
+enum Words {
+    /// The words between the bounds, included
+    Range(Bound, Bound),
+    /// `*`, the arguments, which may be none
+    Arguments,
+}
+
+#[derive(Debug, PartialEq)]
+enum Modifier {
+    /// `h`, remove the last component of the path
+    Head,
+    /// `t`, keep the last component of the path
+    Tail,
+    /// `r`, remove the extension
+    Root,
+    /// `e`, keep the extension
+    Extension,
+    /// `s/old/new/`, replace the first occurrence of `old`, or all of them with `gs`
+    Substitute { old: String, new: String, global: bool },
+    /// `p`, print the command without running it
+    Print,
+}
+
+/// A history designator, such as `!-2:1-3:h`
+#[derive(Debug, PartialEq)]
+struct Designator<'a> {
+    event:     Event<'a>,
+    words:     Option<Words>,
+    modifiers: Vec<Modifier>,
+}
+
+/// A command with its history designators expanded
+#[derive(Debug, PartialEq)]
+pub struct Expansion<'a> {
+    pub command: Cow<'a, str>,
+    /// Whether the command should be printed instead of run, with the `:p` modifier
+    pub print:   bool,
+}
+
+/// Expand the history designators of the command, given the history of the previous commands
+pub fn expand_designators<'a>(history: &[Record], cmd: &'a str) -> Result<Expansion<'a>, String> {
+    let tokens: Vec<_> = DesignatorLexer::new(cmd.as_bytes()).collect();
+    if let [DesignatorToken::Text(_)] = tokens.as_slice() {
+        return Ok(Expansion { command: cmd.into(), print: false });
+    }
+
+    let mut output = String::with_capacity(cmd.len());
+    let mut print = false;
+    for token in tokens {
+        match token {
+            DesignatorToken::Text(text) => output.push_str(text),
+            DesignatorToken::Designator(text) => {
+                let designator = parse(text)?;
+                print |= designator.modifiers.contains(&Modifier::Print);
+                match designator.expand(history) {
+                    Ok(expanded) => output.push_str(&expanded),
+                    Err(why) => return Err(format!("{}: {}", text, why)),
+                }
             }
         }
-        Cow::Owned(output)
+    }
+    Ok(Expansion { command: output.into(), print })
+}
+
+/// Whether the text starts with a word selector
+fn is_word_selector(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_digit() || "^$*-".contains(c))
+}
+
+fn parse_bound(bound: &str) -> Result<Bound, String> {
+    match bound {
+        "^" => Ok(Bound::Index(1)),
+        "$" => Ok(Bound::Last),
+        _ => bound.parse().map(Bound::Index).map_err(|_| "bad word specifier".into()),
+    }
+}
+
+fn parse_words(selector: &str) -> Result<Words, String> {
+    if selector == "*" {
+        return Ok(Words::Arguments);
+    }
+    if selector.ends_with('*') {
+        return Ok(Words::Range(parse_bound(&selector[..selector.len() - 1])?, Bound::Last));
+    }
+    match selector.find('-') {
+        Some(0) if selector.len() == 1 => Err("bad word specifier".into()),
+        Some(dash) => {
+            let first = if dash == 0 { Bound::Index(0) } else { parse_bound(&selector[..dash])? };
+            let last = match &selector[dash + 1..] {
+                "" => Bound::BeforeLast,
+                last => parse_bound(last)?,
+            };
+            Ok(Words::Range(first, last))
+        }
+        None => {
+            let bound = parse_bound(selector)?;
+            Ok(Words::Range(bound, bound))
+        }
+    }
+}
+
+/// Split the text of a substitution at the delimiter, unescaping the escaped delimiters
+fn delimited(text: &str, delimiter: char) -> (String, &str) {
+    let mut output = String::new();
+    let mut chars = text.char_indices();
+    while let Some((id, character)) = chars.next() {
+        match character {
+            '\\' if text[id + 1..].starts_with(delimiter) => {
+                output.push(delimiter);
+                chars.next();
+            }
+            _ if character == delimiter => return (output, &text[id + delimiter.len_utf8()..]),
+            _ => output.push(character),
+        }
+    }
+    (output, "")
+}
+
+/// Parse a designator found by the lexer
+fn parse(designator: &str) -> Result<Designator<'_>, String> {
+    let bad_event = || format!("{}: bad event specification", designator);
+    let mut rest = &designator[1..];
+    let event = if rest.starts_with('!') {
+        rest = &rest[1..];
+        Event::Relative(1)
+    } else if rest.starts_with(|c| "^$*:".contains(c)) {
+        Event::Relative(1)
+    } else if rest.starts_with('?') {
+        let end = rest[1..].find('?').map_or(rest.len(), |end| end + 1);
+        let substring = &rest[1..end];
+        rest = &rest[rest.len().min(end + 1)..];
+        if substring.is_empty() {
+            return Err(bad_event());
+        }
+        Event::Contains(substring)
+    } else if rest.starts_with('0') && !rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+        // As in older versions, `!0` is the command name of the previous command, like `!!:0`
+        Event::Relative(1)
+    } else if rest.starts_with(|c: char| c == '-' || c.is_ascii_digit()) {
+        let end = rest[1..].find(|c: char| !c.is_ascii_digit()).map_or(rest.len(), |end| end + 1);
+        let (number, relative) =
+            if rest.starts_with('-') { (&rest[1..end], true) } else { (&rest[..end], false) };
+        let number = number.parse::<usize>().ok().filter(|&number| number != 0);
+        let number = number.ok_or_else(bad_event)?;
+        rest = &rest[end..];
+        if relative {
+            Event::Relative(number)
+        } else {
+            Event::Absolute(number)
+        }
+    } else {
+        let end = rest.find(':').unwrap_or(rest.len());
+        let prefix = &rest[..end];
+        rest = &rest[end..];
+        Event::Prefix(prefix)
+    };
+
+    let selector = if rest.starts_with(':') && is_word_selector(&rest[1..]) {
+        Some(&rest[1..])
+    } else if is_word_selector(rest) {
+        Some(rest)
     } else {
-        Cow::Borrowed(cmd)
+        None
+    };
+    let words = match selector {
+        Some(selector) => {
+            let end = selector.find(':').unwrap_or(selector.len());
+            rest = &selector[end..];
+            Some(parse_words(&selector[..end]).map_err(|why| format!("{}: {}", designator, why))?)
+        }
+        None => None,
+    };
+
+    let mut modifiers = Vec::new();
+    while !rest.is_empty() {
+        let unknown = || format!("{}: unrecognized history modifier", designator);
+        if !rest.starts_with(':') {
+            return Err(unknown());
+        }
+        let (modifier, length) = match rest[1..].chars().next() {
+            Some('h') => (Modifier::Head, 2),
+            Some('t') => (Modifier::Tail, 2),
+            Some('r') => (Modifier::Root, 2),
+            Some('e') => (Modifier::Extension, 2),
+            Some('p') => (Modifier::Print, 2),
+            Some('s') | Some('g') => {
+                let global = rest[1..].starts_with('g');
+                if global && !rest[2..].starts_with('s') {
+                    return Err(unknown());
+                }
+                let substitution = &rest[if global { 3 } else { 2 }..];
+                let delimiter = substitution.chars().next().ok_or_else(unknown)?;
+                let substitution = &substitution[delimiter.len_utf8()..];
+                let (old, substitution) = delimited(substitution, delimiter);
+                let (new, substitution) = delimited(substitution, delimiter);
+                if old.is_empty() {
+                    return Err(format!("{}: no previous substitution", designator));
+                }
+                let modifier = Modifier::Substitute { old, new, global };
+                (modifier, rest.len() - substitution.len())
+            }
+            _ => return Err(unknown()),
+        };
+        modifiers.push(modifier);
+        rest = &rest[length..];
     }
+
+    Ok(Designator { event, words, modifiers })
 }
 
-fn command(text: &str) -> &str { ArgumentSplitter::new(text).next().unwrap_or(text) }
+impl<'a> Designator<'a> {
+    /// The command of the history selected by the event
+    fn command<'h>(&self, history: &'h [Record]) -> Option<&'h str> {
+        let mut commands = history.iter().map(|record| record.command.as_str());
+        match self.event {
+            Event::Absolute(number) => commands.nth(number - 1),
+            Event::Relative(number) => commands.nth_back(number - 1),
+            Event::Prefix(prefix) => commands.rev().find(|command| command.starts_with(prefix)),
+            Event::Contains(text) => commands.rev().find(|command| command.contains(text)),
+        }
+    }
+
+    fn expand(&self, history: &[Record]) -> Result<String, String> {
+        let command = self.command(history).ok_or("event not found")?;
+        let split: Vec<_> = ArgumentSplitter::new(command).collect();
+        let index = |bound| match bound {
+            Bound::Index(index) => Some(index).filter(|&index| index < split.len()),
+            Bound::Last => split.len().checked_sub(1),
+            Bound::BeforeLast => split.len().checked_sub(2),
+        };
+        let mut output = match self.words {
+            None => command.to_string(),
+            Some(Words::Arguments) => split.iter().skip(1).join(" "),
+            Some(Words::Range(first, last)) => match (index(first), index(last)) {
+                (Some(first), Some(last)) if first <= last => split[first..=last].join(" "),
+                _ => return Err("bad word specifier".into()),
+            },
+        };
 
-fn args(text: &str) -> &str {
-    let bytes = text.as_bytes();
-    bytes
-        .iter()
-        // Obtain position of the first space character,
-        .position(|&x| x == b' ')
-        // and then obtain the arguments to the command.
-        .and_then(|fp| {
-            bytes[fp + 1..]
-                .iter()
-                // Find the position of the first character in the first argument.
-                .position(|&x| x != b' ')
-                // Then slice the argument string from the original command.
-                .map(|sp| &text[fp + sp + 1..])
-        })
-        // Unwrap the arguments string if it exists, else return the original string.
-        .unwrap_or(text)
+        for modifier in &self.modifiers {
+            output = match modifier {
+                Modifier::Head => match output.rfind('/') {
+                    Some(end) => output[..end].to_string(),
+                    None => output,
+                },
+                Modifier::Tail => match output.rfind('/') {
+                    Some(start) => output[start + 1..].to_string(),
+                    None => output,
+                },
+                Modifier::Root => match extension(&output) {
+                    Some(start) => output[..start].to_string(),
+                    None => output,
+                },
+                Modifier::Extension => match extension(&output) {
+                    Some(start) => output[start..].to_string(),
+                    None => String::new(),
+                },
+                Modifier::Substitute { old, new, global } => {
+                    if !output.contains(old.as_str()) {
+                        return Err("substitution failed".into());
+                    }
+                    // `&` in the new text stands for the old one
+                    let new = new.split("\\&").map(|part| part.replace('&', old)).join("&");
+                    if *global {
+                        output.replace(old.as_str(), &new)
+                    } else {
+                        output.replacen(old.as_str(), &new, 1)
+                    }
+                }
+                Modifier::Print => output,
+            };
+        }
+        Ok(output)
+    }
+}
+
+/// The position of the dot starting the extension of the last component of the path
+fn extension(path: &str) -> Option<usize> {
+    let start = path.rfind('/').map_or(0, |start| start + 1);
+    path[start..].rfind('.').filter(|&dot| dot != 0).map(|dot| start + dot)
 }
 
-fn first_arg(text: &str) -> &str { ArgumentSplitter::new(text).nth(1).unwrap_or(text) }
+#[cfg(test)]
+mod tests {
+    use super::{super::history::tests::history, *};
 
-fn last_arg(text: &str) -> &str { ArgumentSplitter::new(text).last().unwrap_or(text) }
+    fn expand<'a>(history: &[Record], cmd: &'a str) -> Result<Cow<'a, str>, String> {
+        expand_designators(history, cmd).map(|expansion| expansion.command)
+    }
+
+    #[test]
+    fn events() {
+        let history = history(&["cd /tmp", "echo one two", "ls -l src/main.rs", "echo three"]);
+        assert_eq!(expand(&history, "sudo !!").unwrap(), "sudo echo three");
+        assert_eq!(expand(&history, "!1").unwrap(), "cd /tmp");
+        assert_eq!(expand(&history, "!-2").unwrap(), "ls -l src/main.rs");
+        assert_eq!(expand(&history, "!ech").unwrap(), "echo three");
+        assert_eq!(expand(&history, "!?two?; !?mai").unwrap(), "echo one two; ls -l src/main.rs");
+        assert_eq!(expand(&history, "!5"), Err("!5: event not found".into()));
+        assert_eq!(expand(&history, "!0 !0:s/e/a/").unwrap(), "echo acho");
+        assert_eq!(expand(&history, "!00"), Err("!00: bad event specification".into()));
+        assert_eq!(expand(&history, "!nothing"), Err("!nothing: event not found".into()));
+        // Not designators
+        assert_eq!(expand(&history, "test a != b; ! true"), Ok("test a != b; ! true".into()));
+        let quoted = "echo '!!' \"!!\" \\!! hi!";
+        assert_eq!(expand(&history, quoted), Ok(quoted.into()));
+    }
+
+    #[test]
+    fn words_and_modifiers() {
+        let history = history(&["ls -l src/main.rs", "echo one two three", "true"]);
+        assert_eq!(expand(&history, "!!*").unwrap(), "");
+        assert_eq!(expand(&history, "!e:2").unwrap(), "two");
+        assert_eq!(expand(&history, "!e:2-3 !e:^ !e:$").unwrap(), "two three one three");
+        assert_eq!(expand(&history, "!e:2* !e:1- !e:-1").unwrap(), "two three one two echo one");
+        assert_eq!(expand(&history, "!-2:*").unwrap(), "one two three");
+        let path = expand(&history, "!ls:$:h !ls:$:t !ls:$:r !ls:$:e").unwrap();
+        assert_eq!(path, "src main.rs src/main .rs");
+        assert_eq!(expand(&history, "!ls:$:t:r").unwrap(), "main");
+        assert_eq!(expand(&history, "!e:s/o/0/").unwrap(), "ech0 one two three");
+        assert_eq!(expand(&history, "!e:gs/o/0").unwrap(), "ech0 0ne tw0 three");
+        assert_eq!(expand(&history, "!e:s/one two/[&]").unwrap(), "echo [one two] three");
+        assert_eq!(expand(&history, "!e:s|o|\\|/|").unwrap(), "ech|/ one two three");
+        assert_eq!(expand(&history, "!e:5"), Err("!e:5: bad word specifier".into()));
+        assert_eq!(expand(&history, "!e:x"), Err("!e:x: unrecognized history modifier".into()));
+        assert_eq!(expand(&history, "!e:s/z/y/"), Err("!e:s/z/y/: substitution failed".into()));
+        assert_eq!(expand(&history, "!e:s//y/"), Err("!e:s//y/: no previous substitution".into()));
+
+        let expansion = expand_designators(&history, "!ls:p").unwrap();
+        assert!(expansion.print);
+        assert_eq!(expansion.command, "ls -l src/main.rs");
+    }
+}
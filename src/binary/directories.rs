@@ -0,0 +1,247 @@
+use super::history::now;
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+pub(crate) const MAN_Z: &str = r#"NAME
+    z - change to a frequently and recently used directory
+
+SYNOPSIS
+    z [-l | -x] [FRAGMENTS...]
+
+DESCRIPTION
+    Changes to the directory with the highest frecency matching the FRAGMENTS, other than the
+    current one. If the last fragment is the path of a directory, z changes to it.
+
+    Each directory the shell changes to is recorded in $XDG_DATA_HOME/ion/directories, with a
+    rank increased on each visit and the time of the last visit. The frecency of a directory is
+    its rank, weighted by how recent the last visit is.
+
+    A directory matches if the FRAGMENTS are found in its path in the given order, the last one
+    in the last component of the path. The case is ignored unless a fragment has an uppercase
+    letter.
+
+OPTIONS
+    -l  List the matching directories with their frecency, from the lowest to the highest.
+    -x  Forget the current directory.
+
+EXAMPLES
+    z proj
+    z ion src
+    z -l doc
+"#;
+
+/// The sum of the ranks above which the ranks are aged
+const MAX_RANK: f64 = 9000.0;
+
+/// A directory visited
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    path: PathBuf,
+    /// The number of visits, reduced as the ranks are aged
+    rank: f64,
+    /// The time of the last visit, in seconds since the Unix epoch
+    time: u64,
+}
+
+impl Entry {
+    /// Parse a line of the file, where the fields are separated by `|`, as in the file of `z`
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.rsplitn(3, '|');
+        let time = fields.next()?.parse().ok()?;
+        let rank = fields.next()?.parse().ok()?;
+        let path = fields.next()?.into();
+        Some(Entry { path, rank, time })
+    }
+
+    fn frecency(&self, now: u64) -> f64 {
+        match now.saturating_sub(self.time) {
+            age if age < 60 * 60 => self.rank * 4.0,
+            age if age < 24 * 60 * 60 => self.rank * 2.0,
+            age if age < 7 * 24 * 60 * 60 => self.rank / 2.0,
+            _ => self.rank / 4.0,
+        }
+    }
+}
+
+/// Whether the fragments are found in the path in order, the last one in its last component
+fn is_match(path: &str, fragments: &[&str]) -> bool {
+    let last = path.rfind('/').map_or(0, |slash| slash + 1);
+    let mut start = 0;
+    for (index, fragment) in fragments.iter().enumerate() {
+        if index + 1 == fragments.len() {
+            start = start.max(last);
+        }
+        match path[start..].find(fragment) {
+            Some(position) => start += position + fragment.len(),
+            None => return false,
+        }
+    }
+    true
+}
+
+/// The directories the shell changed to, recorded in a file shared by the sessions
+#[derive(Debug, Default)]
+pub struct Directories {
+    file:    Option<PathBuf>,
+    /// The working directory when the last change was looked for
+    current: Option<PathBuf>,
+}
+
+impl Directories {
+    pub fn set_file(&mut self, file: PathBuf) { self.file = Some(file); }
+
+    fn entries(&self) -> io::Result<Vec<Entry>> {
+        match self.file.as_ref().map(fs::read_to_string) {
+            Some(Ok(text)) => Ok(text.lines().filter_map(Entry::parse).collect()),
+            Some(Err(ref err)) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Some(Err(err)) => Err(err),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Replace the file with the entries, through a temporary file
+    fn save(&self, entries: &[Entry]) -> io::Result<()> {
+        let path = match self.file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let temporary = path.with_extension("new");
+        let mut file = File::create(&temporary)?;
+        for entry in entries {
+            writeln!(file, "{}|{}|{}", entry.path.display(), entry.rank, entry.time)?;
+        }
+        fs::rename(&temporary, path)
+    }
+
+    /// Record a visit of the working directory if it changed since the last call. The directory
+    /// of the first call, where the shell started, is not recorded.
+    pub fn change_to(&mut self, dir: &Path) -> io::Result<()> {
+        if self.current.as_ref().map_or(true, |current| current == dir) {
+            self.current = Some(dir.into());
+            return Ok(());
+        }
+        self.current = Some(dir.into());
+        self.visit(dir)
+    }
+
+    /// Record a visit of the directory
+    pub fn visit(&self, dir: &Path) -> io::Result<()> {
+        let mut entries = self.entries()?;
+        let now = now();
+        match entries.iter_mut().find(|entry| entry.path == dir) {
+            Some(entry) => {
+                entry.rank += 1.0;
+                entry.time = now;
+            }
+            None => entries.push(Entry { path: dir.into(), rank: 1.0, time: now }),
+        }
+        // Age the ranks, so that the directories not visited anymore are forgotten
+        if entries.iter().map(|entry| entry.rank).sum::<f64>() > MAX_RANK {
+            for entry in &mut entries {
+                entry.rank *= 0.99;
+            }
+            entries.retain(|entry| entry.rank >= 1.0);
+        }
+        self.save(&entries)
+    }
+
+    /// Forget the directory
+    pub fn remove(&self, dir: &Path) -> io::Result<()> {
+        let mut entries = self.entries()?;
+        entries.retain(|entry| entry.path != dir);
+        self.save(&entries)
+    }
+
+    /// The existing directories matching the fragments with their frecency, from the highest
+    /// frecency to the lowest
+    pub fn matches(&self, fragments: &[&str]) -> io::Result<Vec<(f64, PathBuf)>> {
+        let now = now();
+        let ignore_case = fragments.iter().all(|fragment| !fragment.contains(char::is_uppercase));
+        let fragments: Vec<_> = if ignore_case {
+            fragments.iter().map(|fragment| fragment.to_lowercase()).collect()
+        } else {
+            fragments.iter().map(|&fragment| fragment.to_string()).collect()
+        };
+        let fragments: Vec<_> = fragments.iter().map(String::as_str).collect();
+
+        let mut matches: Vec<_> = self
+            .entries()?
+            .into_iter()
+            .filter(|entry| {
+                let path = entry.path.to_string_lossy();
+                if ignore_case {
+                    is_match(&path.to_lowercase(), &fragments)
+                } else {
+                    is_match(&path, &fragments)
+                }
+            })
+            .filter(|entry| entry.path.is_dir())
+            .map(|entry| (entry.frecency(now), entry.path))
+            .collect();
+        matches.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_fragments() {
+        assert!(is_match("/home/ion/projects/ion", &["ion"]));
+        assert!(is_match("/home/ion/projects/ion", &["proj", "ion"]));
+        assert!(is_match("/home/ion/src/ion-shell", &["ion", "sh"]));
+        assert!(!is_match("/home/ion/projects/ion", &["ion", "proj"]));
+        assert!(!is_match("/home/ion/projects/src", &["proj"]));
+        assert!(is_match("/home/ion/projects/src", &[]));
+    }
+
+    #[test]
+    fn rank_directories() {
+        let file = mktemp::Temp::new_file().unwrap();
+        let mut directories = Directories::default();
+        directories.set_file(file.as_path().to_path_buf());
+        let temp = mktemp::Temp::new_dir().unwrap();
+        let dir = temp.as_path();
+        let (first, second) = (dir.join("first-project"), dir.join("second-project"));
+        fs::create_dir(&first).unwrap();
+        fs::create_dir(&second).unwrap();
+
+        directories.visit(&first).unwrap();
+        directories.visit(&second).unwrap();
+        directories.visit(&second).unwrap();
+        directories.visit(&dir.join("removed")).unwrap();
+        let matches = directories.matches(&["PROJ"]).unwrap();
+        assert!(matches.is_empty());
+        let matches = directories.matches(&["proj"]).unwrap();
+        assert_eq!(matches, vec![(8.0, second.clone()), (4.0, first.clone())]);
+
+        directories.remove(&second).unwrap();
+        assert_eq!(directories.matches(&[]).unwrap(), vec![(4.0, first)]);
+        let entry = Entry { path: "/a|b".into(), rank: 2.5, time: 10 };
+        assert_eq!(Entry::parse("/a|b|2.5|10"), Some(entry));
+    }
+
+    #[test]
+    fn record_changes() {
+        let file = mktemp::Temp::new_file().unwrap();
+        let mut directories = Directories::default();
+        directories.set_file(file.as_path().to_path_buf());
+        let temp = mktemp::Temp::new_dir().unwrap();
+        let (start, project) = (temp.as_path().join("start"), temp.as_path().join("project"));
+        fs::create_dir(&start).unwrap();
+        fs::create_dir(&project).unwrap();
+
+        directories.change_to(&start).unwrap();
+        directories.change_to(&project).unwrap();
+        directories.change_to(&project).unwrap();
+        directories.change_to(&start).unwrap();
+        directories.change_to(&project).unwrap();
+        assert_eq!(directories.matches(&["project"]).unwrap(), vec![(8.0, project)]);
+        assert_eq!(directories.matches(&["start"]).unwrap(), vec![(4.0, start)]);
+    }
+}
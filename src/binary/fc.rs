@@ -0,0 +1,237 @@
+use super::history::{History, Record};
+use ion_shell::{builtins::Status, types::Str, Shell};
+use itertools::Itertools;
+use mktemp::Temp;
+use std::{cell::RefCell, fs};
+
+pub(crate) const MAN_FC: &str = r#"NAME
+    fc - edit and run the commands of the history
+
+SYNOPSIS
+    fc [-r] [-e EDITOR] [FIRST [LAST]]
+    fc -l [-nr] [FIRST [LAST]]
+    fc -s [OLD=NEW] [COMMAND]
+
+DESCRIPTION
+    Opens the commands of the history from FIRST to LAST in an editor, and runs the edited
+    commands once the editor exits. The editor is given by -e, $VISUAL or $EDITOR, and defaults
+    to vi. Without FIRST, the last command is edited.
+
+    FIRST, LAST and COMMAND are either the number of a command, as listed by fc -l, a negative
+    offset from the current command, or the prefix of the last command starting with it.
+
+    The commands run are recorded in the history in place of the fc command.
+
+OPTIONS
+    -e EDITOR   Edit the commands with EDITOR.
+    -l          List the commands instead of editing them, the last 16 if FIRST is not given.
+    -n          List the commands without their numbers.
+    -r          List or edit the commands in reverse order.
+    -s OLD=NEW  Run COMMAND, or the last command, again after replacing OLD by NEW.
+
+EXAMPLES
+    fc -l -5
+    fc for
+    fc -s foo=bar
+"#;
+
+/// The options of the `fc` builtin
+#[derive(Debug, Default, PartialEq)]
+struct Options<'a> {
+    editor:     Option<&'a str>,
+    list:       bool,
+    unnumbered: bool,
+    reverse:    bool,
+    substitute: bool,
+    operands:   Vec<&'a str>,
+}
+
+impl<'a> Options<'a> {
+    fn parse(args: &'a [Str]) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut args = args.iter().map(Str::as_str);
+        while let Some(arg) = args.next() {
+            // Negative numbers are offsets in the history
+            if arg == "--" || !arg.starts_with('-') || arg[1..].parse::<usize>().is_ok() {
+                options.operands.extend(Some(arg).filter(|&arg| arg != "--"));
+                options.operands.extend(&mut args);
+                break;
+            }
+            for flag in arg[1..].chars() {
+                match flag {
+                    'e' => options.editor = Some(args.next().ok_or("-e requires an editor")?),
+                    'l' => options.list = true,
+                    'n' => options.unnumbered = true,
+                    'r' => options.reverse = true,
+                    's' => options.substitute = true,
+                    _ => return Err(format!("invalid option: -{}", flag)),
+                }
+            }
+        }
+        if options.operands.len() > 2 {
+            return Err("too many arguments".into());
+        }
+        Ok(options)
+    }
+}
+
+/// The index of a command of the history given by its number, a negative offset from the end of
+/// the history, or the prefix of the last command starting with it
+fn find(history: &[Record], operand: &str) -> Result<usize, String> {
+    let index = match operand.parse::<isize>() {
+        Ok(number) if number > 0 => {
+            Some(number as usize - 1).filter(|&index| index < history.len())
+        }
+        Ok(number) if number < 0 => history.len().checked_sub(-number as usize),
+        Ok(_) => None,
+        Err(_) => history.iter().rposition(|record| record.command.starts_with(operand)),
+    };
+    index.ok_or_else(|| format!("{}: no command found", operand))
+}
+
+/// The indices of the commands from `first` to `last`, given as operands
+fn range(
+    history: &[Record],
+    first: Option<&str>,
+    last: Option<&str>,
+    list: bool,
+) -> Result<Vec<usize>, String> {
+    if history.is_empty() {
+        return Err("the history is empty".into());
+    }
+    let first = match first {
+        Some(first) => find(history, first)?,
+        None if list => history.len().saturating_sub(16),
+        None => history.len() - 1,
+    };
+    let last = match last {
+        Some(last) => find(history, last)?,
+        None if list => history.len() - 1,
+        None => first,
+    };
+    Ok(if first <= last { (first..=last).collect() } else { (last..=first).rev().collect() })
+}
+
+/// Edit the text in the editor, through a temporary file
+fn edit(shell: &Shell<'_>, editor: Option<&str>, text: &str) -> Result<String, String> {
+    let variable = |name| shell.variables().get_str(name).ok().filter(|value| !value.is_empty());
+    let editor = editor
+        .map(Into::into)
+        .or_else(|| variable("VISUAL"))
+        .or_else(|| variable("EDITOR"))
+        .unwrap_or_else(|| "vi".into());
+    let file = Temp::new_file().map_err(|err| format!("could not create a file: {}", err))?;
+    fs::write(&file, text).map_err(|err| format!("could not write the commands: {}", err))?;
+    // The editor can be given with arguments
+    let status = shell
+        .child_command("sh")
+        .args(&["-c", &format!("{} \"$1\"", editor), "sh"])
+        .arg(file.as_path())
+        .status()
+        .map_err(|err| format!("could not run {}: {}", editor, err))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", editor, status));
+    }
+    fs::read_to_string(&file).map_err(|err| format!("could not read the commands: {}", err))
+}
+
+/// Run the `fc` builtin
+pub fn fc(
+    args: &[Str],
+    shell: &mut Shell<'_>,
+    history: &RefCell<History>,
+) -> Result<Status, String> {
+    let options = Options::parse(&args[1..])?;
+    let mut operands = options.operands.iter().cloned();
+    let commands = {
+        let history = history.borrow();
+        let records = history.records();
+        if options.substitute {
+            let substitution = options.operands.first().and_then(|operand| {
+                let equal = operand.find('=')?;
+                Some((&operand[..equal], &operand[equal + 1..]))
+            });
+            if substitution.is_some() {
+                operands.next();
+            }
+            let command = range(records, operands.next(), None, false)?;
+            let command = &records[command[0]].command;
+            match substitution {
+                Some((old, new)) if !old.is_empty() => command.replace(old, new),
+                _ => command.clone(),
+            }
+        } else {
+            let mut indices = range(records, operands.next(), operands.next(), options.list)?;
+            if options.reverse {
+                indices.reverse();
+            }
+            if options.list {
+                for index in indices {
+                    if options.unnumbered {
+                        println!("\t{}", records[index].command);
+                    } else {
+                        println!("{}\t{}", index + 1, records[index].command);
+                    }
+                }
+                return Ok(Status::SUCCESS);
+            }
+            let text = indices.into_iter().map(|index| &records[index].command).join("\n");
+            // Release the history while the editor runs
+            drop(history);
+            edit(shell, options.editor, &(text + "\n"))?
+        }
+    };
+
+    let commands = commands.trim_end();
+    if commands.trim_start().is_empty() {
+        return Ok(Status::SUCCESS);
+    }
+    println!("{}", commands);
+    history.borrow_mut().rerun = Some(commands.into());
+    shell.execute_command(commands.as_bytes()).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::history::tests::{args, history},
+        *,
+    };
+
+    #[test]
+    fn parse_options() {
+        let options = args(&["-lnr", "-5"]);
+        assert_eq!(
+            Options::parse(&options),
+            Ok(Options {
+                list: true,
+                unnumbered: true,
+                reverse: true,
+                operands: vec!["-5"],
+                ..Options::default()
+            })
+        );
+        let options = args(&["-e", "nano", "--", "-s"]);
+        assert_eq!(
+            Options::parse(&options),
+            Ok(Options { editor: Some("nano"), operands: vec!["-s"], ..Options::default() })
+        );
+        assert!(Options::parse(&args(&["-e"])).is_err());
+        assert!(Options::parse(&args(&["-x"])).is_err());
+        assert!(Options::parse(&args(&["1", "2", "3"])).is_err());
+    }
+
+    #[test]
+    fn select_commands() {
+        let history = history(&["cd /tmp", "ls", "echo one", "echo two"]);
+        assert_eq!(range(&history, None, None, false), Ok(vec![3]));
+        assert_eq!(range(&history, None, None, true), Ok(vec![0, 1, 2, 3]));
+        assert_eq!(range(&history, Some("2"), None, false), Ok(vec![1]));
+        assert_eq!(range(&history, Some("-3"), None, true), Ok(vec![1, 2, 3]));
+        assert_eq!(range(&history, Some("echo"), Some("cd"), false), Ok(vec![3, 2, 1, 0]));
+        assert!(range(&history, Some("5"), None, false).is_err());
+        assert!(range(&history, Some("0"), None, false).is_err());
+        assert!(range(&history, Some("pwd"), None, false).is_err());
+        assert!(range(&[], None, None, true).is_err());
+    }
+}
@@ -0,0 +1,426 @@
+use ion_shell::Shell;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+const RESET: &str = "\x1b[0m";
+
+/// The keywords of the language. The words following the ones in `COMMAND_KEYWORDS` are commands.
+const KEYWORDS: &[&str] = &[
+    "and", "break", "case", "continue", "else", "end", "export", "fn", "for", "if", "let", "match",
+    "not", "or", "return", "time", "while",
+];
+const COMMAND_KEYWORDS: &[&str] = &["and", "else", "if", "not", "or", "time", "while"];
+
+/// The ANSI sequences used for each kind of token. An empty sequence leaves the token uncolored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Palette {
+    /// Keywords, builtins, functions and aliases
+    pub keyword:     String,
+    /// Commands found in the `PATH`
+    pub command:     String,
+    /// Commands that can not be found
+    pub unknown:     String,
+    /// Quoted strings
+    pub string:      String,
+    /// Variables and process expansions
+    pub variable:    String,
+    /// Method calls
+    pub method:      String,
+    /// Redirections
+    pub redirection: String,
+    /// Suggestions from the history
+    pub suggestion:  String,
+}
+
+impl Palette {
+    /// The variables configuring the colors, with their default value
+    pub const VARIABLES: &'static [(&'static str, &'static str)] = &[
+        ("HIGHLIGHT_KEYWORD", "blue,bold"),
+        ("HIGHLIGHT_COMMAND", "green"),
+        ("HIGHLIGHT_UNKNOWN", "red"),
+        ("HIGHLIGHT_STRING", "yellow"),
+        ("HIGHLIGHT_VARIABLE", "cyan"),
+        ("HIGHLIGHT_METHOD", "magenta"),
+        ("HIGHLIGHT_REDIRECTION", "light_magenta"),
+        ("HIGHLIGHT_SUGGESTION", "dim"),
+    ];
+
+    /// Read the colors from the `HIGHLIGHT_*` variables, which use the names of the `c::`
+    /// namespace
+    pub fn new(shell: &Shell<'_>) -> Self {
+        let vars = shell.variables();
+        let color = |name: &str| {
+            vars.get_str(name)
+                .ok()
+                .filter(|spec| !spec.is_empty())
+                .and_then(|spec| vars.get_str(&format!("c::{}", spec)).ok())
+                .map(|color| color.to_string())
+                .unwrap_or_default()
+        };
+        Palette {
+            keyword:     color("HIGHLIGHT_KEYWORD"),
+            command:     color("HIGHLIGHT_COMMAND"),
+            unknown:     color("HIGHLIGHT_UNKNOWN"),
+            string:      color("HIGHLIGHT_STRING"),
+            variable:    color("HIGHLIGHT_VARIABLE"),
+            method:      color("HIGHLIGHT_METHOD"),
+            redirection: color("HIGHLIGHT_REDIRECTION"),
+            suggestion:  color("HIGHLIGHT_SUGGESTION"),
+        }
+    }
+}
+
+/// Colorizes the buffer of the line editor.
+///
+/// The highlighter does not borrow the shell, so that it can be given to liner: the names of the
+/// builtins, functions and aliases are collected when it is created.
+pub struct Highlighter {
+    palette:  Palette,
+    known:    HashSet<String>,
+    paths:    Vec<PathBuf>,
+    cwd:      PathBuf,
+    /// Whether the commands looked up in the `PATH` exist
+    commands: RefCell<HashMap<String, bool>>,
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+/// Find the end of the delimited group opened at `start`, or the end of the text if unclosed
+fn closing(text: &str, start: usize, open: u8, close: u8) -> usize {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut pos = start;
+    while pos < bytes.len() {
+        match (bytes[pos], quote) {
+            (b'\\', _) => pos += 1,
+            (b'\'', None) | (b'"', None) => quote = Some(bytes[pos]),
+            (character, Some(quoted)) if character == quoted => quote = None,
+            (character, None) if character == open => depth += 1,
+            (character, None) if character == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return pos + 1;
+                }
+            }
+            _ => (),
+        }
+        pos += 1;
+    }
+    bytes.len()
+}
+
+/// Find the end of the word starting at `start`
+fn word_end(text: &str, start: usize) -> usize {
+    let bytes = text.as_bytes();
+    let mut pos = start;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b' ' | b'\t' | b'\n' | b';' | b'|' | b'&' | b'<' | b'>' => break,
+            b'^' if bytes.get(pos + 1).map_or(false, |&next| next == b'>' || next == b'|') => break,
+            b'\\' => pos += 2,
+            b'\'' | b'"' => {
+                let quote = bytes[pos];
+                pos += 1 + text[pos + 1..].find(quote as char).map_or(text.len(), |end| end + 1);
+            }
+            b'(' => pos = closing(text, pos, b'(', b')'),
+            b'{' => pos = closing(text, pos, b'{', b'}'),
+            _ => pos += 1,
+        }
+    }
+    pos.min(bytes.len())
+}
+
+impl Highlighter {
+    pub fn new(shell: &Shell<'_>) -> Self {
+        let vars = shell.variables();
+        let known = shell
+            .builtins()
+            .keys()
+            .map(ToString::to_string)
+            .chain(vars.aliases().map(|(key, _)| key.to_string()))
+            .chain(vars.functions().map(|(key, _)| key.to_string()))
+            .collect();
+        let paths = vars.get_env("PATH").map(|paths| env::split_paths(paths).collect());
+        Highlighter {
+            palette: Palette::new(shell),
+            known,
+            paths: paths.unwrap_or_else(|| vec!["/bin".into()]),
+            cwd: shell.dir_stack().current_dir().to_path_buf(),
+            commands: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Return the line with the ANSI color sequences of the palette inserted
+    pub fn highlight(&self, line: &str) -> String {
+        let mut output = String::with_capacity(line.len() * 2);
+        self.statements(line, &mut output);
+        output
+    }
+
+    /// Append the suggested end of the line, in the color of the suggestions
+    pub fn suggest(&self, output: &mut String, suggestion: &str) {
+        Self::paint(output, &self.palette.suggestion, suggestion);
+    }
+
+    fn paint(output: &mut String, color: &str, text: &str) {
+        if color.is_empty() || text.is_empty() {
+            output.push_str(text);
+        } else {
+            output.push_str(color);
+            output.push_str(text);
+            output.push_str(RESET);
+        }
+    }
+
+    /// Check if a command can be run: it must be found in the `PATH` or, if it is a path, be
+    /// executable or a directory to change to.
+    fn command_exists(&self, command: &str) -> bool {
+        if command.starts_with('~') {
+            return true;
+        }
+        let path = self.cwd.join(command);
+        if command.contains('/') {
+            return path.is_dir() || is_executable(&path);
+        }
+        if let Some(&exists) = self.commands.borrow().get(command) {
+            return exists || path.is_dir();
+        }
+        let exists = self.paths.iter().any(|dir| is_executable(&dir.join(command)));
+        self.commands.borrow_mut().insert(command.into(), exists);
+        exists || path.is_dir()
+    }
+
+    /// Highlight a sequence of statements, like a whole line or the inside of `$()`
+    fn statements(&self, text: &str, output: &mut String) {
+        let bytes = text.as_bytes();
+        let mut pos = 0;
+        let mut command = true;
+        let mut after_for = false;
+        while pos < bytes.len() {
+            let next = bytes.get(pos + 1).cloned();
+            match (bytes[pos], next) {
+                (b' ', _) | (b'\t', _) | (b'\n', _) => {
+                    output.push(bytes[pos] as char);
+                    pos += 1;
+                }
+                (b'#', _) => {
+                    output.push_str(&text[pos..]);
+                    break;
+                }
+                (b'&', Some(b'>')) | (b'^', Some(b'>')) | (b'>', _) | (b'<', _) => {
+                    let end = text[pos..]
+                        .find(|c| !"&^<>".contains(c))
+                        .map_or(text.len(), |end| pos + end);
+                    Self::paint(output, &self.palette.redirection, &text[pos..end]);
+                    pos = end;
+                }
+                (b'&', Some(b'&'))
+                | (b'|', Some(b'|'))
+                | (b'&', Some(b'|'))
+                | (b'^', Some(b'|')) => {
+                    output.push_str(&text[pos..pos + 2]);
+                    pos += 2;
+                    command = true;
+                }
+                (b';', _) | (b'|', _) | (b'&', _) => {
+                    output.push(bytes[pos] as char);
+                    pos += 1;
+                    command = true;
+                    after_for = false;
+                }
+                _ => {
+                    let end = word_end(text, pos);
+                    let word = &text[pos..end];
+                    if command && !word.contains(|c| "$@'\"\\".contains(c)) {
+                        let color = if KEYWORDS.contains(&word) || self.known.contains(word) {
+                            &self.palette.keyword
+                        } else if self.command_exists(word) {
+                            &self.palette.command
+                        } else {
+                            &self.palette.unknown
+                        };
+                        Self::paint(output, color, word);
+                        command = COMMAND_KEYWORDS.contains(&word);
+                        after_for = word == "for";
+                    } else if after_for && word == "in" {
+                        Self::paint(output, &self.palette.keyword, word);
+                        after_for = false;
+                    } else {
+                        self.word(word, output);
+                        command = false;
+                    }
+                    pos = end;
+                }
+            }
+        }
+    }
+
+    /// Highlight the quotes and expansions of a word
+    fn word(&self, text: &str, output: &mut String) {
+        let bytes = text.as_bytes();
+        let mut pos = 0;
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'\\' => {
+                    let end = text[pos..].chars().take(2).map(char::len_utf8).sum::<usize>();
+                    output.push_str(&text[pos..pos + end]);
+                    pos += end;
+                }
+                b'\'' => {
+                    let end = text[pos + 1..].find('\'').map_or(text.len(), |end| pos + end + 2);
+                    Self::paint(output, &self.palette.string, &text[pos..end]);
+                    pos = end;
+                }
+                b'"' => pos = self.double_quoted(text, pos, output),
+                b'$' | b'@' => pos = self.expansion(text, pos, output),
+                _ => {
+                    let start = pos + text[pos..].chars().next().map_or(1, char::len_utf8);
+                    let end = text[start..]
+                        .find(|c| "\\'\"$@".contains(c))
+                        .map_or(text.len(), |end| start + end);
+                    output.push_str(&text[pos..end]);
+                    pos = end;
+                }
+            }
+        }
+    }
+
+    /// Highlight a double quoted string starting at `start`, returning its end
+    fn double_quoted(&self, text: &str, start: usize, output: &mut String) -> usize {
+        let bytes = text.as_bytes();
+        let mut pos = start + 1;
+        let mut plain = start;
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'\\' => pos += 2,
+                b'"' => {
+                    pos += 1;
+                    break;
+                }
+                b'$' | b'@' => {
+                    Self::paint(output, &self.palette.string, &text[plain..pos]);
+                    pos = self.expansion(text, pos, output);
+                    plain = pos;
+                }
+                _ => pos += 1,
+            }
+        }
+        let pos = pos.min(bytes.len());
+        Self::paint(output, &self.palette.string, &text[plain..pos]);
+        pos
+    }
+
+    /// Highlight a variable, method or process expansion starting at `start`, returning its end
+    fn expansion(&self, text: &str, start: usize, output: &mut String) -> usize {
+        let bytes = text.as_bytes();
+        let name_end = text[start + 1..]
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+            .map_or(text.len(), |end| start + end + 1);
+        match bytes.get(name_end) {
+            // Process expansions: `$(command)` and `@(command)`
+            Some(b'(') if name_end == start + 1 => {
+                let end = closing(text, name_end, b'(', b')');
+                let inner_end = if text[..end].ends_with(')') { end - 1 } else { end };
+                Self::paint(output, &self.palette.variable, &text[start..=name_end]);
+                self.statements(&text[name_end + 1..inner_end], output);
+                Self::paint(output, &self.palette.variable, &text[inner_end..end]);
+                end
+            }
+            // Methods: `$join(@array ', ')`
+            Some(b'(') => {
+                let end = closing(text, name_end, b'(', b')');
+                let inner_end = if text[..end].ends_with(')') { end - 1 } else { end };
+                Self::paint(output, &self.palette.method, &text[start..=name_end]);
+                self.word(&text[name_end + 1..inner_end], output);
+                Self::paint(output, &self.palette.method, &text[inner_end..end]);
+                end
+            }
+            // Braced variables: `${var}`
+            Some(b'{') if name_end == start + 1 => {
+                let end = closing(text, name_end, b'{', b'}');
+                Self::paint(output, &self.palette.variable, &text[start..end]);
+                end
+            }
+            // Special variables such as `$?`
+            Some(b'?') if name_end == start + 1 => {
+                Self::paint(output, &self.palette.variable, &text[start..start + 2]);
+                start + 2
+            }
+            _ if name_end == start + 1 => {
+                output.push(bytes[start] as char);
+                start + 1
+            }
+            // Variables, possibly indexed: `$var`, `@array[1]`
+            Some(b'[') => {
+                let end = closing(text, name_end, b'[', b']');
+                Self::paint(output, &self.palette.variable, &text[start..end]);
+                end
+            }
+            _ => {
+                Self::paint(output, &self.palette.variable, &text[start..name_end]);
+                name_end
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highlighter() -> Highlighter {
+        let palette = Palette {
+            keyword:     "<k>".into(),
+            command:     "<c>".into(),
+            unknown:     "<u>".into(),
+            string:      "<s>".into(),
+            variable:    "<v>".into(),
+            method:      "<m>".into(),
+            redirection: "<r>".into(),
+            suggestion:  "<g>".into(),
+        };
+        Highlighter {
+            palette,
+            known: vec!["echo".to_string()].into_iter().collect(),
+            paths: vec!["/bin".into(), "/usr/bin".into()],
+            cwd: "/".into(),
+            commands: RefCell::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn commands_and_keywords() {
+        let highlighter = highlighter();
+        assert_eq!(
+            highlighter.highlight("if echo a && not_a_command_at_all; ls; end"),
+            "<k>if\x1b[0m <k>echo\x1b[0m a && <u>not_a_command_at_all\x1b[0m; <c>ls\x1b[0m; \
+             <k>end\x1b[0m"
+        );
+        assert_eq!(highlighter.highlight("for i in 1..3"), "<k>for\x1b[0m i <k>in\x1b[0m 1..3");
+    }
+
+    #[test]
+    fn strings_variables_and_redirections() {
+        let highlighter = highlighter();
+        assert_eq!(
+            highlighter.highlight("echo 'a' \"b $x\" @arr[0] $join(@a ', ') > out"),
+            "<k>echo\x1b[0m <s>'a'\x1b[0m <s>\"b \x1b[0m<v>$x\x1b[0m<s>\"\x1b[0m \
+             <v>@arr[0]\x1b[0m <m>$join(\x1b[0m<v>@a\x1b[0m <s>', '\x1b[0m<m>)\x1b[0m \
+             <r>>\x1b[0m out"
+        );
+        assert_eq!(
+            highlighter.highlight("echo $(ls | cat)"),
+            "<k>echo\x1b[0m <v>$(\x1b[0m<c>ls\x1b[0m | <c>cat\x1b[0m<v>)\x1b[0m"
+        );
+    }
+}
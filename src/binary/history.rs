@@ -1,8 +1,388 @@
 use super::InteractiveShell;
-use ion_shell::{builtins::Status, Value};
+use ion_shell::{builtins::Status, types::Str, Value};
 
+use itertools::Itertools;
 use regex::Regex;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    iter::Peekable,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The first line of the history file, identifying its format
+const HEADER: &str = "#ion-history 1";
+
+/// A command of the history, with the context it was run in. The fields other than the command
+/// are unknown for the commands migrated from the plain history file of older versions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub command:  String,
+    /// The time the command was started at, in seconds since the Unix epoch
+    pub start:    Option<u64>,
+    pub duration: Option<Duration>,
+    pub status:   Option<i32>,
+    pub cwd:      Option<PathBuf>,
+    /// The session the command was run in
+    pub session:  Option<String>,
+}
+
+/// The structured history, appended to the history file.
+///
+/// Each line of the file holds a record: the start time, the duration in milliseconds, the exit
+/// status, the session, the working directory and the command, separated by tabs. Unknown fields
+/// are written as `-`, and the backslashes, tabs and newlines are escaped.
+#[derive(Debug)]
+pub struct History {
+    records:        Vec<Record>,
+    session:        String,
+    file:           Option<PathBuf>,
+    /// The number of records not written to the file yet
+    unsaved:        usize,
+    /// The length of the file already read, to load the commands of the other sessions
+    read:           u64,
+    /// Append each command to the file as it is entered
+    pub inc_append: bool,
+    /// Load the commands of the other sessions, implies `inc_append`
+    pub share:      bool,
+    /// Keep the duplicate commands of the file in the line editor
+    pub duplicates: bool,
+    /// The commands run by `fc`, recorded in place of the `fc` command
+    pub rerun:      Option<String>,
+}
+
+/// The criteria selecting the records printed by the `history` builtin
+#[derive(Debug, Default, PartialEq)]
+pub struct Filter {
+    cwd:     Option<PathBuf>,
+    failed:  bool,
+    since:   Option<u64>,
+    session: Option<String>,
+}
+
+pub fn now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs()) }
+
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for character in field.chars() {
+        match character {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(character) = chars.next() {
+        if character != '\\' {
+            unescaped.push(character);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Parse a field of a record, which is `-` if unknown. `None` is returned if it is invalid.
+fn field<T>(field: &str, parse: impl FnOnce(&str) -> Option<T>) -> Option<Option<T>> {
+    if field == "-" {
+        Some(None)
+    } else {
+        parse(field).map(Some)
+    }
+}
+
+/// Parse the duration given to `--since`: either a number of seconds, minutes, hours, days or
+/// weeks ago, such as `2h`, or a time in seconds since the Unix epoch
+fn parse_since(since: &str, now: u64) -> Option<u64> {
+    let unit = match since.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return since.parse().ok(),
+    };
+    let count: u64 = since[..since.len() - 1].parse().ok()?;
+    Some(now.saturating_sub(count * unit))
+}
+
+/// The value of an option, if the next argument is not another option
+fn optional<'s>(args: &mut Peekable<impl Iterator<Item = &'s str>>) -> Option<&'s str> {
+    if args.peek().map_or(false, |value| !value.starts_with('-')) {
+        args.next()
+    } else {
+        None
+    }
+}
+
+impl Record {
+    /// A record of the command, without any context
+    pub fn from_command<S: Into<String>>(command: S) -> Self {
+        Record {
+            command:  command.into(),
+            start:    None,
+            duration: None,
+            status:   None,
+            cwd:      None,
+            session:  None,
+        }
+    }
+
+    /// Parse a line of the history file
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(6, '\t');
+        let start = field(fields.next()?, |start| start.parse().ok())?;
+        let duration =
+            field(fields.next()?, |millis| millis.parse().ok().map(Duration::from_millis))?;
+        let status = field(fields.next()?, |status| status.parse().ok())?;
+        let session = field(fields.next()?, |session| Some(unescape(session)))?;
+        let cwd = field(fields.next()?, |cwd| Some(unescape(cwd).into()))?;
+        let command = unescape(fields.next()?);
+        Some(Record { command, start, duration, status, cwd, session })
+    }
+
+    /// Read the plain history file of older versions, where a command could be preceded by a
+    /// `#summary#` line giving the duration of the command and a `#<epoch>` line giving its
+    /// start time
+    fn migrate(text: &str) -> Vec<Self> {
+        const SUMMARY: &str = "#summary# elapsed real time: ";
+        let mut records = Vec::new();
+        let (mut start, mut duration) = (None, None);
+        for line in text.lines().filter(|line| !line.is_empty()) {
+            if line.starts_with(SUMMARY) {
+                duration = line[SUMMARY.len()..]
+                    .trim_end_matches(" seconds")
+                    .parse()
+                    .ok()
+                    .map(Duration::from_secs_f64);
+            } else if line.starts_with('#') && line[1..].parse::<u64>().is_ok() {
+                start = line[1..].parse().ok();
+            } else {
+                records.push(Record {
+                    start:    start.take(),
+                    duration: duration.take(),
+                    ..Record::from_command(line)
+                });
+            }
+        }
+        records
+    }
+}
+
+impl fmt::Display for Record {
+    /// Format the record as a line of the history file, without the line break
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn unknown<T: fmt::Display>(field: Option<T>) -> String {
+            field.map_or_else(|| "-".into(), |field| field.to_string())
+        }
+
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            unknown(self.start),
+            unknown(self.duration.map(|duration| duration.as_millis())),
+            unknown(self.status),
+            unknown(self.session.as_ref().map(|session| escape(session))),
+            unknown(self.cwd.as_ref().map(|cwd| escape(&cwd.to_string_lossy()))),
+            escape(&self.command)
+        )
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            records:    Vec::new(),
+            session:    format!("{:x}-{:x}", now(), process::id()),
+            file:       None,
+            unsaved:    0,
+            read:       0,
+            inc_append: false,
+            share:      false,
+            duplicates: true,
+            rerun:      None,
+        }
+    }
+}
+
+impl History {
+    /// The identifier of the session, recorded with its commands
+    pub fn session(&self) -> &str { &self.session }
+
+    pub fn records(&self) -> &[Record] { &self.records }
+
+    /// Load the history file, keeping at most `limit` records. A plain history file from an
+    /// older version is converted to the structured format.
+    pub fn load(&mut self, path: PathBuf, limit: usize) -> io::Result<()> {
+        let mut text = String::new();
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&path)?
+            .read_to_string(&mut text)?;
+        let mut lines = text.lines();
+        let rewrite = if lines.next() == Some(HEADER) {
+            self.records.extend(lines.filter_map(Record::parse));
+            false
+        } else {
+            self.records.extend(Record::migrate(&text));
+            true
+        };
+        self.file = Some(path);
+        if rewrite || self.records.len() > limit {
+            let excess = self.records.len().saturating_sub(limit);
+            self.records.drain(..excess);
+            self.rewrite()?;
+        } else {
+            self.read = text.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// Replace the history file with the records, through a temporary file
+    fn rewrite(&mut self) -> io::Result<()> {
+        let path = match self.file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let temporary = path.with_extension("new");
+        let mut file = File::create(&temporary)?;
+        writeln!(file, "{}", HEADER)?;
+        for record in &self.records {
+            writeln!(file, "{}", record)?;
+        }
+        fs::rename(&temporary, path)?;
+        self.read = fs::metadata(path)?.len();
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    /// Add a command to the history, writing it to the file if `inc_append` is set
+    pub fn push(&mut self, record: Record) -> io::Result<()> {
+        self.records.push(record);
+        self.unsaved += 1;
+        if self.inc_append || self.share {
+            self.commit()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Append the records not yet saved to the history file
+    pub fn commit(&mut self) -> io::Result<()> {
+        if let Some(ref path) = self.file {
+            if self.unsaved != 0 {
+                let mut file = OpenOptions::new().append(true).open(path)?;
+                let records = &self.records[self.records.len() - self.unsaved..];
+                file.write_all(
+                    records.iter().map(|record| format!("{}\n", record)).join("").as_bytes(),
+                )?;
+            }
+        }
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    /// Load the records appended to the file by the other sessions since the last call
+    pub fn sync(&mut self) -> io::Result<Vec<Record>> {
+        let path = match self.file {
+            Some(ref path) => path,
+            None => return Ok(Vec::new()),
+        };
+        let mut file = File::open(path)?;
+        let length = file.metadata()?.len();
+        // The file was rewritten by another session
+        if length < self.read {
+            self.read = length;
+        }
+        let mut text = String::new();
+        file.seek(SeekFrom::Start(self.read))?;
+        file.read_to_string(&mut text)?;
+        // Only read the complete lines
+        let end = text.rfind('\n').map_or(0, |end| end + 1);
+        self.read += end as u64;
+        let session = &self.session;
+        let records: Vec<_> = text[..end]
+            .lines()
+            .filter_map(Record::parse)
+            .filter(|record| record.session.as_ref() != Some(session))
+            .collect();
+        let unsaved = self.records.split_off(self.records.len() - self.unsaved);
+        self.records.extend(records.iter().cloned());
+        self.records.extend(unsaved);
+        Ok(records)
+    }
+
+    /// Remove the previous occurrences of a command
+    pub fn remove_duplicates(&mut self, command: &str) {
+        let saved = self.records.len() - self.unsaved;
+        let mut index = 0;
+        let mut removed = 0;
+        self.records.retain(|record| {
+            index += 1;
+            let keep = record.command != command;
+            if !keep && index > saved {
+                removed += 1;
+            }
+            keep
+        });
+        self.unsaved -= removed;
+    }
+}
+
+impl Filter {
+    /// Parse the options of the `history` builtin: `--cwd [DIR]`, `--failed`, `--since TIME`
+    /// and `--session [ID]`, where the current directory and session are used if not given
+    pub fn parse(args: &[Str], cwd: &Path, session: &str) -> Result<Self, String> {
+        let mut filter = Filter::default();
+        let mut args = args.iter().map(Str::as_str).peekable();
+        while let Some(arg) = args.next() {
+            match arg {
+                "--cwd" => {
+                    let dir = optional(&mut args);
+                    filter.cwd = Some(dir.map_or_else(|| cwd.to_path_buf(), |dir| cwd.join(dir)));
+                }
+                "--failed" => filter.failed = true,
+                "--since" => {
+                    let since = args.next().ok_or("--since requires a time")?;
+                    filter.since = Some(
+                        parse_since(since, now())
+                            .ok_or_else(|| format!("invalid time for --since: {}", since))?,
+                    );
+                }
+                "--session" => filter.session = Some(optional(&mut args).unwrap_or(session).into()),
+                _ => return Err(format!("invalid option: {}", arg)),
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Whether the record is selected. The records with unknown fields are not selected by
+    /// the criteria on those fields.
+    pub fn matches(&self, record: &Record) -> bool {
+        (self.cwd.is_none() || record.cwd == self.cwd)
+            && (!self.failed || record.status.map_or(false, |status| status != 0))
+            && self.since.map_or(true, |since| record.start.map_or(false, |start| start >= since))
+            && (self.session.is_none() || record.session == self.session)
+    }
+}
 
 #[derive(Debug, Default)]
 pub struct IgnoreSetting {
@@ -61,26 +441,15 @@ impl<'a> InteractiveShell<'a> {
 
     /// Saves a command in the history, depending on @HISTORY_IGNORE. Should be called
     /// immediately after `on_command()`
-    pub fn save_command_in_history(&self, command: &str) {
-        if self.should_save_command(command) {
-            if self.shell.borrow().variables().get_str("HISTORY_TIMESTAMP").unwrap_or_default()
-                == "1"
+    pub fn save_command_in_history(&self, record: Record) {
+        if self.should_save_command(&record.command) {
+            if let Err(err) = self.context.borrow_mut().history.push(record.command.as_str().into())
             {
-                // Get current time stamp
-                let since_unix_epoch =
-                    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-                let cur_time_sys = ["#", &since_unix_epoch.to_owned().to_string()].concat();
-
-                // Push current time to history
-                if let Err(err) = self.context.borrow_mut().history.push(cur_time_sys.into()) {
-                    eprintln!("ion: {}", err)
-                }
-            }
-
-            // Push command itself to history
-            if let Err(err) = self.context.borrow_mut().history.push(command.into()) {
                 eprintln!("ion: {}", err);
             }
+            if let Err(err) = self.history.borrow_mut().push(record) {
+                eprintln!("ion: history append: {}", err);
+            }
         }
     }
 
@@ -110,6 +479,7 @@ impl<'a> InteractiveShell<'a> {
 
         if ignore.duplicates {
             self.context.borrow_mut().history.remove_duplicates(command);
+            self.history.borrow_mut().remove_duplicates(command);
         }
 
         // ignore command when regex is matched but only if it does not contain
@@ -126,3 +496,75 @@ impl<'a> InteractiveShell<'a> {
         true
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A history of the commands, without any context
+    pub fn history(commands: &[&str]) -> Vec<Record> {
+        commands.iter().map(|&command| Record::from_command(command)).collect()
+    }
+
+    /// The arguments of a builtin
+    pub fn args(args: &[&str]) -> Vec<Str> { args.iter().map(|&arg| arg.into()).collect() }
+
+    #[test]
+    fn records_round_trip() {
+        let record = Record {
+            command:  "echo 'a\tb' \\\nc".into(),
+            start:    Some(1_576_000_000),
+            duration: Some(Duration::from_millis(1500)),
+            status:   Some(1),
+            cwd:      Some("/home/ion".into()),
+            session:  Some("5df8-1f".into()),
+        };
+        let line = record.to_string();
+        assert!(!line.contains('\n'));
+        assert_eq!(Record::parse(&line), Some(record));
+        assert_eq!(Record::parse("-\t-\t-\t-\t-\tls"), Some(Record::from_command("ls")));
+        assert_eq!(Record::parse("not a record"), None);
+    }
+
+    #[test]
+    fn migrate_plain_history() {
+        let text = "ls\n#summary# elapsed real time: 0.5 seconds\n#1576000000\nsleep 1\necho \
+                    done\n";
+        let records = Record::migrate(text);
+        let commands: Vec<_> = records.iter().map(|record| record.command.as_str()).collect();
+        assert_eq!(commands, ["ls", "sleep 1", "echo done"]);
+        assert_eq!(records[1].start, Some(1_576_000_000));
+        assert_eq!(records[1].duration, Some(Duration::from_millis(500)));
+        assert_eq!(records[2].start, None);
+    }
+
+    #[test]
+    fn filter_records() {
+        let cwd = Path::new("/home/ion");
+        let filter = Filter::parse(&args(&["--cwd", "--failed"]), cwd, "this").unwrap();
+        assert_eq!(
+            filter,
+            Filter { cwd: Some(cwd.into()), failed: true, since: None, session: None }
+        );
+        let filter = Filter::parse(&args(&["--cwd", "src", "--session"]), cwd, "this").unwrap();
+        assert_eq!(filter.cwd, Some("/home/ion/src".into()));
+        assert_eq!(filter.session, Some("this".into()));
+        assert!(Filter::parse(&args(&["--since"]), cwd, "this").is_err());
+        assert!(Filter::parse(&args(&["--since", "yesterday"]), cwd, "this").is_err());
+        assert!(Filter::parse(&args(&["--all"]), cwd, "this").is_err());
+
+        assert_eq!(parse_since("2h", 10_000), Some(10_000 - 7200));
+        assert_eq!(parse_since("1576000000", 10_000), Some(1_576_000_000));
+
+        let record = Record {
+            start:  Some(1000),
+            status: Some(1),
+            cwd:    Some(cwd.into()),
+            ..Record::from_command("false")
+        };
+        let filter = Filter { since: Some(1000), failed: true, ..Filter::default() };
+        assert!(filter.matches(&record));
+        assert!(!Filter { since: Some(1001), ..Filter::default() }.matches(&record));
+        assert!(!Filter { session: Some("this".into()), ..Filter::default() }.matches(&record));
+    }
+}
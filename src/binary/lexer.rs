@@ -17,7 +17,89 @@ pub enum DesignatorToken<'a> {
 pub struct DesignatorLexer<'a> {
     data:   &'a [u8],
     quotes: Quotes,
-    design: bool,
+}
+
+/// The characters of a word selector, such as `2-4`, `^`, `$` or `*`
+const WORD_SELECTOR: &[u8] = b"0123456789^$*-";
+
+/// The characters ending the prefix of a `!prefix` designator
+const PREFIX_END: &[u8] = b" \t\n:'\";|&<>()";
+
+fn count_while(data: &[u8], predicate: impl Fn(u8) -> bool) -> usize {
+    data.iter().position(|&byte| !predicate(byte)).unwrap_or(data.len())
+}
+
+/// The length of the word selector at the start of `data`, with its leading `:` if any
+fn words_length(data: &[u8]) -> usize {
+    match data.first() {
+        Some(b':') if data.get(1).map_or(false, |byte| WORD_SELECTOR.contains(byte)) => {
+            1 + count_while(&data[1..], |byte| WORD_SELECTOR.contains(&byte))
+        }
+        Some(b'^') | Some(b'$') | Some(b'*') | Some(b'-') => {
+            count_while(data, |byte| WORD_SELECTOR.contains(&byte))
+        }
+        _ => 0,
+    }
+}
+
+/// The length of the modifiers at the start of `data`, such as `:h:s/old/new/`
+fn modifiers_length(data: &[u8]) -> usize {
+    let mut id = 0;
+    while data.get(id) == Some(&b':')
+        && data.get(id + 1).map_or(false, |byte| byte.is_ascii_alphabetic() || *byte == b'&')
+    {
+        id += 1;
+        if data[id..].starts_with(b"s") {
+            id += 1;
+        } else if data[id..].starts_with(b"gs") {
+            id += 2;
+        } else {
+            id += 1;
+            continue;
+        }
+        // The old and new texts, ended by the delimiter following the `s`
+        let delimiter = match data.get(id) {
+            Some(&delimiter) if delimiter != b'\n' => delimiter,
+            _ => break,
+        };
+        id += 1;
+        let mut delimiters = 0;
+        while delimiters < 2 {
+            match data.get(id) {
+                None | Some(b'\n') => break,
+                Some(b'\\') if data.get(id + 1).map_or(false, |&byte| byte == delimiter) => id += 1,
+                Some(&byte) if byte == delimiter => delimiters += 1,
+                _ => (),
+            }
+            id += 1;
+        }
+    }
+    id
+}
+
+/// The length of the designator at the start of `data`, which starts with a `!`. `None` is
+/// returned if the `!` does not start a designator, as in `!=` or `! command`.
+fn designator_length(data: &[u8]) -> Option<usize> {
+    let event = match data.get(1)? {
+        b' ' | b'\t' | b'\n' | b'=' | b'(' => return None,
+        b'!' => 2,
+        // Words of the previous command, such as `!$`
+        b'^' | b'$' | b'*' => return Some(2 + modifiers_length(&data[2..])),
+        b'?' => match data[2..].iter().position(|&byte| byte == b'?' || byte == b'\n') {
+            Some(end) if data[2 + end] == b'?' => 3 + end,
+            Some(end) => 2 + end,
+            None => data.len(),
+        },
+        b'-' | b'0'..=b'9' => 2 + count_while(&data[2..], |byte| byte.is_ascii_digit()),
+        _ => 1 + count_while(&data[1..], |byte| !PREFIX_END.contains(&byte)),
+    };
+    let words = words_length(&data[event..]);
+    let length = event + words + modifiers_length(&data[event + words..]);
+    if length == 1 {
+        None
+    } else {
+        Some(length)
+    }
 }
 
 impl<'a> DesignatorLexer<'a> {
@@ -27,9 +109,7 @@ impl<'a> DesignatorLexer<'a> {
         output
     }
 
-    pub fn new(data: &'a [u8]) -> DesignatorLexer {
-        DesignatorLexer { data, quotes: Quotes::None, design: false }
-    }
+    pub fn new(data: &'a [u8]) -> DesignatorLexer { DesignatorLexer { data, quotes: Quotes::None } }
 }
 
 impl<'a> Iterator for DesignatorLexer<'a> {
@@ -46,16 +126,15 @@ impl<'a> Iterator for DesignatorLexer<'a> {
                 b'"' if self.quotes == Quotes::Double => self.quotes = Quotes::None,
                 b'\'' if self.quotes == Quotes::None => self.quotes = Quotes::Single,
                 b'\'' if self.quotes == Quotes::Single => self.quotes = Quotes::None,
-                b'!' if self.quotes != Quotes::Double && !self.design => {
-                    self.design = true;
-                    if id != 0 {
-                        return Some(DesignatorToken::Text(self.grab_and_shorten(id)));
+                b'!' if self.quotes == Quotes::None => {
+                    if let Some(length) = designator_length(&self.data[id..]) {
+                        return Some(if id != 0 {
+                            DesignatorToken::Text(self.grab_and_shorten(id))
+                        } else {
+                            DesignatorToken::Designator(self.grab_and_shorten(length))
+                        });
                     }
                 }
-                b' ' | b'\t' | b'\'' | b'"' | b'a'..=b'z' | b'A'..=b'Z' if self.design => {
-                    self.design = false;
-                    return Some(DesignatorToken::Designator(self.grab_and_shorten(id)));
-                }
                 _ => (),
             }
         }
@@ -65,11 +144,7 @@ impl<'a> Iterator for DesignatorLexer<'a> {
         } else {
             let output = unsafe { str::from_utf8_unchecked(&self.data) };
             self.data = b"";
-            Some(if self.design {
-                DesignatorToken::Designator(output)
-            } else {
-                DesignatorToken::Text(output)
-            })
+            Some(DesignatorToken::Text(output))
         }
     }
 }
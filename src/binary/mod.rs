@@ -1,16 +1,30 @@
 //! Contains the binary logic of Ion.
+mod bindings;
+mod blocks;
 pub mod builtins;
 mod completer;
+mod completions;
 mod designators;
+mod directories;
+mod fc;
+mod highlighter;
 mod history;
 mod lexer;
 mod prompt;
 mod readln;
-
+mod suggestions;
+
+use self::{
+    bindings::{Bindings, MAN_BIND},
+    completions::{Specs, MAN_COMPLETE},
+    directories::{Directories, MAN_Z},
+    fc::MAN_FC,
+    highlighter::Palette,
+    history::{Filter, History, Record},
+};
 use ion_shell::{
     builtins::{man_pages, BuiltinFunction, Status},
     expansion::Expander,
-    parser::Terminator,
     types::{self, array},
     IonError, PipelineError, Shell, Signal, Value,
 };
@@ -18,11 +32,13 @@ use itertools::Itertools;
 use liner::{Buffer, Context, KeyBindings};
 use std::{
     cell::{Cell, RefCell},
+    collections::HashSet,
     fs::{self, OpenOptions},
     io::{self, Write},
     os::unix::io::{AsRawFd, IntoRawFd},
-    path::Path,
+    path::{Path, PathBuf},
     rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
 };
 use xdg::BaseDirectories;
 
@@ -39,12 +55,15 @@ FLAGS:
     -h, --help                Prints help information
     -i, --interactive         Force interactive mode
     -n, --no-execute          Do not execute any commands, perform only syntax checking
+    -r, --restricted          Run in restricted mode, for scripts that can not be trusted
+    -u, --no-unset            Treat references to unset variables as errors
     -x                        Print commands before execution
     -v, --version             Print the version, platform and revision of Ion then exit
 
 OPTIONS:
     -c <command>             Evaluate given commands instead of reading from the commandline
     -o <key-bindings>        Shortcut layout. Valid options: "vi", "emacs"
+        --source-dir <dir>   Allow sourcing the scripts of the directory in restricted mode
 
 ARGS:
     <args>...    Script arguments (@args). If the -c option is not specified, the first parameter is taken as a
@@ -54,44 +73,70 @@ pub(crate) const MAN_HISTORY: &str = r#"NAME
     history - print command history
 
 SYNOPSIS
-    history [option]
+    history [option | filters...]
 
 DESCRIPTION
-    Prints or manupulate the command history.
+    Prints or manupulate the command history. The commands printed can be selected by the
+    filters, with the directory, exit status, start time and session recorded along with them.
 
 OPTIONS:
     +inc_append: Append each command to history as entered.
     -inc_append: Default, do not append each command to history as entered.
-    +shared: Share history between shells using the same history file, implies inc_append.
-    -shared: Default, do not share shell history.
+    +share: Share history between shells using the same history file, implies inc_append.
+    -share: Default, do not share shell history.
     +duplicates: Default, allow duplicates in history.
     -duplicates: Do not allow duplicates in history.
+
+FILTERS:
+    --cwd [DIR]: The commands run in DIR, or in the current directory.
+    --failed: The commands which returned a non-zero exit status.
+    --since TIME: The commands started in the last TIME, such as 30m, 2h, 1d or 1w, or since
+        TIME in seconds since the Unix epoch.
+    --session [ID]: The commands of the session ID, or of the current one ($HISTORY_SESSION).
 "#;
 
 pub struct InteractiveShell<'a> {
-    context:    Rc<RefCell<Context>>,
-    shell:      RefCell<Shell<'a>>,
-    terminated: Cell<bool>,
-    huponexit:  Rc<Cell<bool>>,
+    context:     Rc<RefCell<Context>>,
+    shell:       RefCell<Shell<'a>>,
+    terminated:  Cell<bool>,
+    huponexit:   Rc<Cell<bool>>,
+    /// The commands run, with their outcome
+    history:     Rc<RefCell<History>>,
+    /// The completion specs registered with the `complete` builtin
+    specs:       Rc<RefCell<Specs>>,
+    /// The directories visited, to jump to with the `z` builtin
+    directories: Rc<RefCell<Directories>>,
+    /// The keys bound with the `bind` builtin
+    bindings:    Rc<RefCell<Bindings>>,
 }
 
 impl<'a> InteractiveShell<'a> {
     const CONFIG_FILE_NAME: &'static str = "initrc";
 
-    pub fn new(shell: Shell<'a>) -> Self {
+    pub fn new(mut shell: Shell<'a>) -> Self {
         let mut context = Context::new();
         context.word_divider_fn = Box::new(word_divide);
+        for &(name, color) in Palette::VARIABLES {
+            shell.variables_mut().set(name, color);
+        }
         InteractiveShell {
-            context:    Rc::new(RefCell::new(context)),
-            shell:      RefCell::new(shell),
-            terminated: Cell::new(true),
-            huponexit:  Rc::new(Cell::new(false)),
+            context:     Rc::new(RefCell::new(context)),
+            shell:       RefCell::new(shell),
+            terminated:  Cell::new(true),
+            huponexit:   Rc::new(Cell::new(false)),
+            history:     Rc::new(RefCell::new(History::default())),
+            specs:       Rc::new(RefCell::new(Specs::default())),
+            directories: Rc::new(RefCell::new(Directories::default())),
+            bindings:    Rc::new(RefCell::new(Bindings::default())),
         }
     }
 
     /// Handles commands given by the REPL, and saves them to history.
-    pub fn save_command(&self, cmd: &str) {
-        if !cmd.ends_with('/')
+    pub fn save_command(&self, cmd: &str, started: SystemTime, cwd: PathBuf) {
+        let rerun = self.history.borrow_mut().rerun.take();
+        let command = if let Some(command) = rerun {
+            command
+        } else if !cmd.ends_with('/')
             && self
                 .shell
                 .borrow()
@@ -99,25 +144,42 @@ impl<'a> InteractiveShell<'a> {
                 .ok()
                 .map_or(false, |path| Path::new(&path.as_str()).is_dir())
         {
-            self.save_command_in_history(&[cmd, "/"].concat());
+            [cmd, "/"].concat()
         } else {
-            self.save_command_in_history(cmd);
+            cmd.to_string()
+        };
+        let record = Record {
+            command,
+            start: started.duration_since(UNIX_EPOCH).ok().map(|start| start.as_secs()),
+            duration: started.elapsed().ok(),
+            status: Some(self.shell.borrow().previous_status().as_os_code()),
+            cwd: Some(cwd),
+            session: Some(self.history.borrow().session().into()),
+        };
+        self.save_command_in_history(record);
+    }
+
+    /// Record the working directory of the shell if it changed since the last command
+    fn record_directory(directories: &RefCell<Directories>, shell: &Shell<'_>) {
+        let dir = shell.dir_stack().current_dir();
+        if let Err(err) = directories.borrow_mut().change_to(dir) {
+            eprintln!("ion: could not record the directory: {}", err);
         }
     }
 
     pub fn add_callbacks(&self) {
-        let context = self.context.clone();
+        let directories = self.directories.clone();
+        Self::record_directory(&directories, &self.shell.borrow());
         self.shell.borrow_mut().set_on_command(Some(Box::new(move |shell, elapsed| {
-            // If `RECORD_SUMMARY` is set to "1" (True, Yes), then write a summary of the
-            // pipline just executed to the the file and context histories. At the
-            // moment, this means record how long it took.
+            // Every directory changed to is recorded, including the ones a function or a script
+            // goes through
+            Self::record_directory(&directories, shell);
+
+            // If `RECORD_SUMMARY` is set to "1" (True, Yes), then print a summary of the
+            // pipline just executed. At the moment, this means how long it took. The
+            // duration is recorded in the history in any case.
             if Some("1".into()) == shell.variables().get_str("RECORD_SUMMARY").ok() {
-                let summary =
-                    format!("#summary# elapsed real time: {:.9} seconds", elapsed.as_secs_f32(),);
-                println!("{}", summary);
-                context.borrow_mut().history.push(summary.into()).unwrap_or_else(|err| {
-                    eprintln!("ion: history append: {}", err);
-                });
+                println!("#summary# elapsed real time: {:.9} seconds", elapsed.as_secs_f32());
             }
         })));
     }
@@ -130,16 +192,17 @@ impl<'a> InteractiveShell<'a> {
     /// Creates an interactive session that reads from a prompt provided by
     /// Liner.
     pub fn execute_interactive(self) -> ! {
-        let context_bis = self.context.clone();
+        let history_bis = self.history.clone();
         let huponexit = self.huponexit.clone();
         let prep_for_exit = &move |shell: &mut Shell<'_>| {
-            // context will be sent a signal to commit all changes to the history file,
-            // and waiting for the history thread in the background to finish.
+            // Append the commands of the session to the history file
             if huponexit.get() {
                 shell.resume_stopped();
                 shell.background_send(Signal::SIGHUP).expect("Failed to prepare for exit");
             }
-            context_bis.borrow_mut().history.commit_to_file();
+            if let Err(err) = history_bis.borrow_mut().commit() {
+                eprintln!("ion: history append: {}", err);
+            }
         };
 
         let exit = self.shell.borrow().builtins().get("exit").unwrap();
@@ -154,46 +217,51 @@ impl<'a> InteractiveShell<'a> {
             exec(args, shell)
         };
 
-        let context_bis = self.context.clone();
-        let history = &move |args: &[types::Str], _shell: &mut Shell<'_>| -> Status {
+        let history_bis = self.history.clone();
+        let manage_history = &move |args: &[types::Str], shell: &mut Shell<'_>| -> Status {
             if man_pages::check_help(args, MAN_HISTORY) {
                 return Status::SUCCESS;
             }
 
+            let mut history = history_bis.borrow_mut();
             match args.get(1).map(|s| s.as_str()) {
-                Some("+inc_append") => {
-                    context_bis.borrow_mut().history.inc_append = true;
-                }
-                Some("-inc_append") => {
-                    context_bis.borrow_mut().history.inc_append = false;
-                }
+                Some("+inc_append") => history.inc_append = true,
+                Some("-inc_append") => history.inc_append = false,
                 Some("+share") => {
-                    context_bis.borrow_mut().history.inc_append = true;
-                    context_bis.borrow_mut().history.share = true;
+                    history.inc_append = true;
+                    history.share = true;
                 }
                 Some("-share") => {
-                    context_bis.borrow_mut().history.inc_append = false;
-                    context_bis.borrow_mut().history.share = false;
-                }
-                Some("+duplicates") => {
-                    context_bis.borrow_mut().history.load_duplicates = true;
+                    history.inc_append = false;
+                    history.share = false;
                 }
-                Some("-duplicates") => {
-                    context_bis.borrow_mut().history.load_duplicates = false;
-                }
-                Some(_) => {
-                    Status::error(
-                        "Invalid history option. Choices are [+|-] inc_append, duplicates and \
-                         share (implies inc_append).",
-                    );
-                }
-                None => {
-                    print!("{}", context_bis.borrow().history.buffers.iter().format("\n"));
+                Some("+duplicates") => history.duplicates = true,
+                Some("-duplicates") => history.duplicates = false,
+                _ => {
+                    let cwd = shell.dir_stack().current_dir();
+                    let filter = match Filter::parse(&args[1..], cwd, history.session()) {
+                        Ok(filter) => filter,
+                        Err(why) => return Status::error(format!("ion: history: {}", why)),
+                    };
+                    let records = history.records().iter().filter(|record| filter.matches(record));
+                    print!("{}", records.map(|record| &record.command).format("\n"));
                 }
             }
             Status::SUCCESS
         };
 
+        let history_bis = self.history.clone();
+        let fix_command = &move |args: &[types::Str], shell: &mut Shell<'_>| -> Status {
+            if man_pages::check_help(args, MAN_FC) {
+                return Status::SUCCESS;
+            }
+
+            match fc::fc(args, shell, &history_bis) {
+                Ok(status) => status,
+                Err(why) => Status::error(format!("ion: fc: {}", why)),
+            }
+        };
+
         let huponexit = self.huponexit.clone();
         let set_huponexit: BuiltinFunction = &move |args, _shell| {
             huponexit.set(match args.get(1).map(AsRef::as_ref) {
@@ -219,50 +287,196 @@ impl<'a> InteractiveShell<'a> {
             }
         };
 
+        let specs_bis = self.specs.clone();
+        let complete = &move |args: &[types::Str], _shell: &mut Shell<'_>| -> Status {
+            // Flags named -h can be registered
+            if args.len() == 2 && man_pages::check_help(args, MAN_COMPLETE) {
+                return Status::SUCCESS;
+            }
+
+            let mut specs = specs_bis.borrow_mut();
+            match args.get(1).map(|s| s.as_str()) {
+                None => print!("{}", specs.listing(None)),
+                Some("-e") | Some("--erase") => {
+                    for command in &args[2..] {
+                        specs.remove(command);
+                    }
+                }
+                Some(command) if args.len() == 2 => print!("{}", specs.listing(Some(command))),
+                Some(command) => {
+                    if let Err(why) = specs.register(command, &args[2..]) {
+                        return Status::error(format!("ion: complete: {}", why));
+                    }
+                }
+            }
+            Status::SUCCESS
+        };
+
+        let directories_bis = self.directories.clone();
+        let jump = &move |args: &[types::Str], shell: &mut Shell<'_>| -> Status {
+            if man_pages::check_help(args, MAN_Z) {
+                return Status::SUCCESS;
+            }
+
+            let directories = directories_bis.borrow();
+            let cwd = shell.dir_stack().current_dir().to_path_buf();
+            let option = args.get(1).map(|arg| arg.as_str()).filter(|arg| arg.starts_with('-'));
+            let fragments: Vec<_> =
+                args[1 + usize::from(option.is_some())..].iter().map(|arg| arg.as_str()).collect();
+            let matches = match option {
+                Some("-x") => {
+                    return match directories.remove(&cwd) {
+                        Ok(()) => Status::SUCCESS,
+                        Err(err) => Status::error(format!("ion: z: {}", err)),
+                    };
+                }
+                Some("-l") | None => match directories.matches(&fragments) {
+                    Ok(matches) => matches,
+                    Err(err) => return Status::error(format!("ion: z: {}", err)),
+                },
+                Some(option) => {
+                    return Status::error(format!("ion: z: invalid option: {}", option))
+                }
+            };
+            if option == Some("-l") {
+                for (frecency, dir) in matches.iter().rev() {
+                    println!("{:<10.1} {}", frecency, dir.display());
+                }
+                return Status::SUCCESS;
+            }
+
+            // The directories completed are given by their path
+            let target = match fragments.last() {
+                Some(last) if last.contains('/') && shell.dir_stack().resolve(last).is_dir() => {
+                    Some(last.into())
+                }
+                _ => matches.into_iter().map(|(_, dir)| dir).find(|dir| *dir != cwd),
+            };
+            match (target, shell.builtins().get("cd")) {
+                (Some(dir), Some(cd)) => {
+                    cd(&["cd".into(), dir.to_string_lossy().as_ref().into()], shell)
+                }
+                _ => Status::error("ion: z: no matching directory"),
+            }
+        };
+
+        let bindings_bis = self.bindings.clone();
+        let bind = &move |args: &[types::Str], _shell: &mut Shell<'_>| -> Status {
+            if man_pages::check_help(args, MAN_BIND) {
+                return Status::SUCCESS;
+            }
+
+            let mut bindings = bindings_bis.borrow_mut();
+            let result = match args.get(1).map(|arg| arg.as_str()) {
+                None => bindings.listing(None).map(|listing| print!("{}", listing)),
+                Some("-e") => args[2..].iter().map(|key| bindings.unbind(key)).collect(),
+                Some(key) if args.len() == 2 => {
+                    bindings.listing(Some(key)).map(|listing| print!("{}", listing))
+                }
+                Some(key) if args.len() == 3 => bindings.bind(key, &args[2]),
+                Some(_) => Err("too many arguments".into()),
+            };
+            match result {
+                Ok(()) => Status::SUCCESS,
+                Err(why) => Status::error(format!("ion: bind: {}", why)),
+            }
+        };
+
         // change the lifetime to allow adding local builtins
-        let InteractiveShell { context, shell, terminated, huponexit } = self;
+        let InteractiveShell {
+            context,
+            shell,
+            terminated,
+            huponexit,
+            history,
+            specs,
+            directories,
+            bindings,
+        } = self;
         let mut shell = shell.into_inner();
         shell
             .builtins_mut()
-            .add("history", history, "Display a log of all commands previously executed")
+            .add("history", manage_history, "Display a log of all commands previously executed")
+            .add("fc", fix_command, "Edit and run the commands of the history")
             .add("keybindings", keybindings, "Change the keybindings")
             .add("exit", exit, "Exits the current session")
             .add("exec", exec, "Replace the shell with the given command.")
-            .add("huponexit", set_huponexit, "Hangup the shell's background jobs on exit");
+            .add("huponexit", set_huponexit, "Hangup the shell's background jobs on exit")
+            .add("complete", complete, "Describe how the arguments of a command are completed")
+            .add("z", jump, "Change to a frequently and recently used directory")
+            .add("bind", bind, "Bind a key to a function or an editing action");
 
         match BaseDirectories::with_prefix("ion") {
             Ok(project_dir) => {
                 Self::exec_init_file(&project_dir, &mut shell);
-                Self::load_history(&project_dir, &mut shell, &mut context.borrow_mut());
+                Self::load_completions(&project_dir, &mut shell);
+                Self::load_history(
+                    &project_dir,
+                    &mut shell,
+                    &mut context.borrow_mut(),
+                    &mut history.borrow_mut(),
+                );
+                match project_dir.place_data_file("directories") {
+                    Ok(file) => directories.borrow_mut().set_file(file),
+                    Err(err) => eprintln!("ion: could not create the directories file: {}", err),
+                }
             }
             Err(err) => eprintln!("ion: unable to get xdg base directory: {}", err),
         }
 
-        InteractiveShell { context, shell: RefCell::new(shell), terminated, huponexit }
-            .exec(prep_for_exit)
+        InteractiveShell {
+            context,
+            shell:       RefCell::new(shell),
+            terminated,
+            huponexit,
+            history,
+            specs,
+            directories,
+            bindings,
+        }
+        .exec(prep_for_exit)
     }
 
-    fn load_history(project_dir: &BaseDirectories, shell: &mut Shell, context: &mut Context) {
+    fn load_history(
+        project_dir: &BaseDirectories,
+        shell: &mut Shell,
+        context: &mut Context,
+        history: &mut History,
+    ) {
         shell.variables_mut().set("HISTFILE_ENABLED", "1");
-
-        // History Timestamps enabled variable, disabled by default
-        shell.variables_mut().set("HISTORY_TIMESTAMP", "0");
+        shell.variables_mut().set("HISTORY_SESSION", history.session());
         shell
             .variables_mut()
             .set("HISTORY_IGNORE", array!["no_such_command", "whitespace", "duplicates"]);
         // Initialize the HISTFILE variable
-        if let Some(histfile) = project_dir.find_data_file("history") {
-            shell.variables_mut().set("HISTFILE", histfile.to_string_lossy().as_ref());
-            let _ = context.history.set_file_name_and_load_history(&histfile);
-        } else {
-            match project_dir.place_data_file("history") {
+        let histfile = match project_dir.find_data_file("history") {
+            Some(histfile) => histfile,
+            None => match project_dir.place_data_file("history") {
                 Ok(histfile) => {
                     eprintln!("ion: creating history file at \"{}\"", histfile.display());
-                    shell.variables_mut().set("HISTFILE", histfile.to_string_lossy().as_ref());
-                    let _ = context.history.set_file_name_and_load_history(&histfile);
+                    histfile
                 }
-                Err(err) => println!("ion: could not create history file: {}", err),
-            }
+                Err(err) => return println!("ion: could not create history file: {}", err),
+            },
+        };
+        shell.variables_mut().set("HISTFILE", histfile.to_string_lossy().as_ref());
+        let limit =
+            shell.variables().get_str("HISTFILE_SIZE").ok().and_then(|size| size.parse().ok());
+        if let Err(err) = history.load(histfile, limit.unwrap_or(100_000)) {
+            eprintln!("ion: could not load the history: {}", err);
+        }
+
+        // The line editor keeps the last occurrence of the duplicate commands
+        let mut seen = HashSet::new();
+        let commands: Vec<_> = history
+            .records()
+            .iter()
+            .rev()
+            .map(|record| record.command.as_str())
+            .filter(|command| history.duplicates || seen.insert(*command))
+            .collect();
+        for command in commands.into_iter().rev() {
+            let _ = context.history.push(command.into());
         }
     }
 
@@ -282,10 +496,43 @@ impl<'a> InteractiveShell<'a> {
         }
     }
 
+    /// Register the completion specs of the `completions` configuration directory
+    fn load_completions(project_dir: &BaseDirectories, shell: &mut Shell) {
+        let mut files = project_dir.list_config_files("completions");
+        files.retain(|file| file.extension().map_or(false, |extension| extension == "ion"));
+        files.sort();
+        for file in files {
+            match fs::File::open(&file) {
+                Ok(script) => {
+                    if let Err(err) = shell.execute_command(io::BufReader::new(script)) {
+                        eprintln!("ion: could not load {}: {}", file.display(), err);
+                    }
+                }
+                Err(err) => eprintln!("ion: could not open {}: {}", file.display(), err),
+            }
+        }
+    }
+
     fn exec_single_command(&mut self, command: &str) {
-        let cmd: &str =
-            &designators::expand_designators(&self.context.borrow(), command.trim_end());
         self.terminated.set(true);
+        let expansion =
+            designators::expand_designators(self.history.borrow().records(), command.trim_end());
+        let cmd: &str = match expansion {
+            // Only print the command with the `:p` modifier, and let it be edited from the history
+            Ok(ref expansion) if expansion.print => {
+                println!("{}", expansion.command);
+                let _ = self.context.borrow_mut().history.push(expansion.command.as_ref().into());
+                return;
+            }
+            Ok(ref expansion) => &expansion.command,
+            Err(why) => {
+                eprintln!("ion: {}", why);
+                self.shell.borrow_mut().set_previous_status(Status::FALSE);
+                return;
+            }
+        };
+        let started = SystemTime::now();
+        let cwd = self.shell.borrow().dir_stack().current_dir().to_path_buf();
         {
             let mut shell = self.shell.borrow_mut();
             match shell.on_command(&cmd, true) {
@@ -323,7 +570,9 @@ impl<'a> InteractiveShell<'a> {
                 }
             }
         }
-        self.save_command(&cmd);
+        // A directory run as a command changes to it outside of any pipeline
+        Self::record_directory(&self.directories, &self.shell.borrow());
+        self.save_command(&cmd, started, cwd);
     }
 
     fn exec<T: Fn(&mut Shell<'_>)>(mut self, prep_for_exit: &T) -> ! {
@@ -336,11 +585,7 @@ impl<'a> InteractiveShell<'a> {
             }
             match self.readln(prep_for_exit) {
                 Some(lines) => {
-                    for command in lines
-                        .into_bytes()
-                        .into_iter()
-                        .batching(|bytes| Terminator::new(bytes).terminate())
-                    {
+                    for command in blocks::commands(&lines) {
                         self.exec_single_command(&command);
                     }
                 }
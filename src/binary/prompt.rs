@@ -1,16 +1,101 @@
 use super::InteractiveShell;
 use ion_shell::{
     expansion::{self, Expander},
-    IonError, PipelineError, Shell,
+    IonError, PipelineError, Shell, Value,
 };
 use liner::{KeyBindings::*, Prompt, ViPromptMode, ViStatus};
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    iter,
+};
+use unicode_width::UnicodeWidthStr;
+
+/// The right prompt, drawn at the end of the line of the input as long as they do not overlap
+#[derive(Debug)]
+pub struct RightPrompt {
+    text:    String,
+    /// The columns taken by the right prompt
+    width:   usize,
+    /// The columns taken by the prompt, before the input
+    start:   usize,
+    /// The columns of the terminal
+    columns: usize,
+}
+
+impl RightPrompt {
+    /// Append the right prompt to the input displayed, unless the input reaches it
+    pub fn draw(&self, output: &mut String, buffer: &str, suggestion: Option<&str>) {
+        let input = width(buffer) + suggestion.map_or(0, width);
+        if !buffer.contains('\n') && self.start + input + 1 + self.width <= self.columns {
+            // Save the cursor, move to the column of the right prompt and restore the cursor
+            let column = self.columns - self.width + 1;
+            let _ = write!(output, "\x1b[s\x1b[{}G{}\x1b[u", column, self.text);
+        }
+    }
+}
 
 impl<'a> InteractiveShell<'a> {
+    /// The number of blocks the next line is in
+    fn blocks(&self, shell: &Shell<'_>) -> usize {
+        if self.terminated.get() {
+            shell.block_len()
+        } else {
+            shell.block_len() + 1
+        }
+    }
+
+    /// Generates the right prompt from the `RPROMPT` function or variable, truncated to fit in
+    /// the terminal next to the prompt.
+    pub fn right_prompt(&self, prompt: &Prompt) -> Option<RightPrompt> {
+        let mut shell = self.shell.borrow_mut();
+        if self.blocks(&shell) != 0 {
+            return None;
+        }
+        let text = expand_prompt(&mut shell, "RPROMPT")?;
+        let columns = usize::from(termion::terminal_size().ok()?.0);
+        let mut start = width(prompt.prompt.rsplit('\n').next().unwrap_or_default());
+        // The vi mode indicator is drawn before the prompt
+        if prompt.vi_status.is_some() && !prompt.prompt.contains('\n') {
+            let normal = width(&vi_prompt_indicator(&mut shell, ViPromptMode::Normal));
+            let insert = width(&vi_prompt_indicator(&mut shell, ViPromptMode::Insert));
+            start += normal.max(insert);
+        }
+        let room = columns.checked_sub(start + 1).filter(|&room| room > 0)?;
+        let text = truncate(text.trim_end_matches('\n'), room);
+        let width = width(&text);
+        if width == 0 || text.contains('\n') {
+            return None;
+        }
+        Some(RightPrompt { text, width, start, columns })
+    }
+
+    /// Generates the prompt replacing the prompt of the commands accepted, from the
+    /// `TRANSIENT_PROMPT` function or variable. There is none if neither is defined.
+    pub fn transient_prompt(&self) -> Option<String> {
+        let mut shell = self.shell.borrow_mut();
+        if self.blocks(&shell) != 0 {
+            return None;
+        }
+        expand_prompt(&mut shell, "TRANSIENT_PROMPT")
+    }
+
+    /// The prompt as drawn by Liner, with the vi mode indicator before it. The indicator of the
+    /// insert mode is taken, the mode every line starts in.
+    pub fn displayed_prompt(&self, prompt: &Prompt) -> String {
+        if prompt.vi_status.is_some() {
+            let mut shell = self.shell.borrow_mut();
+            [vi_prompt_indicator(&mut shell, ViPromptMode::Insert), prompt.prompt.clone()].concat()
+        } else {
+            prompt.prompt.clone()
+        }
+    }
+
     /// Generates the prompt that will be used by Liner.
     pub fn prompt(&self) -> Prompt {
         let mut shell = self.shell.borrow_mut();
         let previous_status = shell.previous_status();
-        let blocks = if self.terminated.get() { shell.block_len() } else { shell.block_len() + 1 };
+        let blocks = self.blocks(&shell);
 
         if blocks == 0 {
             let out =
@@ -57,6 +142,108 @@ impl<'a> InteractiveShell<'a> {
     }
 }
 
+/// The number of rows of the terminal taken by the text
+fn rows(text: &str, columns: usize) -> usize {
+    let columns = columns.max(1);
+    text.split('\n').map(|row| ((width(row) + columns - 1) / columns).max(1)).sum()
+}
+
+/// Replace the prompt and the line accepted, above the cursor, by the transient prompt followed
+/// by the line
+pub fn draw_transient(prompt: &str, line: &str, transient: &str) -> io::Result<()> {
+    let rows = rows(&[prompt, line].concat(), usize::from(termion::terminal_size()?.0));
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b[{}A\r\x1b[J{}{}\n", rows, transient, line)?;
+    stdout.flush()
+}
+
+/// Expands the prompt given by the function or the variable `name`, if either is defined
+fn expand_prompt(shell: &mut Shell<'_>, name: &str) -> Option<String> {
+    let is_function = match shell.variables().get(name)? {
+        Value::Function(_) => true,
+        _ => false,
+    };
+    let previous_status = shell.previous_status();
+    let prompt = if is_function {
+        shell.command(name, false).map(|prompt| prompt.to_string()).map_err(|err| err.to_string())
+    } else {
+        shell
+            .variables()
+            .get_str(name)
+            .and_then(|prompt| shell.get_string(&prompt))
+            .map(|prompt| prompt.to_string())
+            .map_err(|err| err.to_string())
+    };
+    shell.set_previous_status(previous_status);
+    prompt.map_err(|err| eprintln!("ion: {} expansion failed: {}", name, err)).ok()
+}
+
+/// The length of the escape sequence at the start of the text, which starts with `ESC`
+fn escape_length(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    match bytes.get(1) {
+        // Control sequences end with a byte from `@` to `~`
+        Some(b'[') => bytes[2..]
+            .iter()
+            .position(|byte| (0x40..=0x7e).contains(byte))
+            .map_or(bytes.len(), |end| end + 3),
+        // Operating system commands, such as the title of the window, end with `BEL` or `ESC \`
+        Some(b']') => match bytes[2..].iter().position(|&byte| byte == 0x07 || byte == 0x1b) {
+            Some(end) if bytes[2 + end] == 0x1b => (end + 4).min(bytes.len()),
+            Some(end) => end + 3,
+            None => bytes.len(),
+        },
+        Some(_) => 1 + text[1..].chars().next().map_or(0, char::len_utf8),
+        None => 1,
+    }
+}
+
+/// Split the text into its characters and its escape sequences, flagged with `true`
+fn segments(text: &str) -> impl Iterator<Item = (bool, &str)> + '_ {
+    let mut rest = text;
+    iter::from_fn(move || {
+        let length = match rest.chars().next()? {
+            '\x1b' => escape_length(rest),
+            character => character.len_utf8(),
+        };
+        let (segment, tail) = rest.split_at(length);
+        rest = tail;
+        Some((segment.starts_with('\x1b'), segment))
+    })
+}
+
+fn is_visible(escape: bool, segment: &str) -> bool {
+    !escape && !segment.starts_with(char::is_control)
+}
+
+/// The number of columns taken by the text, without its escape sequences. Wide characters, as
+/// the CJK ones, take two columns and combining characters none.
+fn width(text: &str) -> usize {
+    segments(text)
+        .filter(|&(escape, segment)| is_visible(escape, segment))
+        .map(|(_, segment)| segment.width())
+        .sum()
+}
+
+/// Truncates the text to the number of columns by replacing its start with an ellipsis, keeping
+/// its escape sequences
+fn truncate(text: &str, columns: usize) -> String {
+    let width = width(text);
+    if width <= columns {
+        return text.into();
+    }
+    let mut skipped = width - columns + 1;
+    let mut output = String::from("…");
+    for (escape, segment) in segments(text) {
+        if skipped > 0 && is_visible(escape, segment) {
+            skipped = skipped.saturating_sub(segment.width());
+        } else {
+            output.push_str(segment);
+        }
+    }
+    output
+}
+
 /// Returns the default indicators for a given mode in absence of a configuration.
 ///
 /// NOTE: This should be made `const` once this functionality is stabilized:
@@ -91,3 +278,22 @@ fn vi_prompt_indicator<'a>(shell: &mut Shell<'a>, mode: ViPromptMode) -> String
             String::new()
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_prompts() {
+        assert_eq!(width("\x1b]0;ion: ~\x07\x1b[1;32mion\x1b[0m:~# "), 7);
+        assert_eq!(width("\x1b]0;ion\x1b\\\x1b[33m12:00"), 5);
+        assert_eq!(truncate("12:00", 5), "12:00");
+        assert_eq!(truncate("\x1b[33m~/projects/ion\x1b[0m", 8), "…\x1b[33mcts/ion\x1b[0m");
+        assert_eq!(truncate("~/projects", 1), "…");
+        assert_eq!(width("日本語> "), 8);
+        assert_eq!(width("e\u{301}cole"), 5);
+        assert_eq!(truncate("~/日本語", 5), "…本語");
+        assert_eq!(rows("\x1b[32m日本語\x1b[0m> echo", 8), 2);
+        assert_eq!(rows("ion\n> echo", 80), 2);
+    }
+}
@@ -1,20 +1,81 @@
-use super::{completer::IonCompleter, InteractiveShell};
+use super::{
+    completer::{Intercept, IonCompleter},
+    highlighter::Highlighter,
+    prompt::draw_transient,
+    suggestions::Suggestions,
+    InteractiveShell,
+};
 use ion_shell::Shell;
-use std::io::ErrorKind;
+use liner::{ColorClosure, Editor, Emacs, KeyBindings, KeyMap, Prompt, Vi};
+use std::{
+    io::{self, ErrorKind, Write},
+    rc::Rc,
+};
+use termion::{input::TermRead, raw::IntoRawMode};
+
+/// How the line editor stopped
+enum Stop {
+    /// The line was accepted
+    Accept(String),
+    /// The line must be drawn again, below the output of a function bound to a key
+    Redraw { buffer: String, cursor: usize },
+}
 
 impl<'a> InteractiveShell<'a> {
     /// Ion's interface to Liner's `read_line` method, which handles everything related to
     /// rendering, controlling, and getting input from the prompt.
     pub fn readln<T: Fn(&mut Shell<'_>)>(&self, prep_for_exit: &T) -> Option<String> {
-        let prompt = self.prompt();
-        let line = self.context.borrow_mut().read_line(
-            prompt,
-            None,
-            &mut IonCompleter::new(&self.shell.borrow()),
-        );
+        if self.history.borrow().share {
+            self.load_shared_history();
+        }
+        let (mut buffer, mut cursor) = (String::new(), 0);
+        let line = loop {
+            let prompt = self.prompt();
+            let right_prompt = self.right_prompt(&prompt);
+            let displayed = self.displayed_prompt(&prompt);
+            let highlighter = Rc::new(Highlighter::new(&self.shell.borrow()));
+            let suggestions = Rc::new(Suggestions::new(
+                &self.history.borrow(),
+                self.shell.borrow().dir_stack().current_dir(),
+            ));
+            let (shown, painter) = (suggestions.clone(), highlighter.clone());
+            let color: ColorClosure = Box::new(move |buffer: &str| {
+                let mut output = painter.highlight(buffer);
+                let suggestion = shown.suggest(buffer);
+                if let Some(suggestion) = suggestion {
+                    painter.suggest(&mut output, suggestion);
+                }
+                if let Some(ref right_prompt) = right_prompt {
+                    right_prompt.draw(&mut output, buffer, suggestion);
+                }
+                output
+            });
+            let mut shell = self.shell.borrow_mut();
+            let mut completer = IonCompleter::new(
+                &mut shell,
+                suggestions,
+                self.specs.clone(),
+                self.directories.clone(),
+                self.bindings.clone(),
+            );
+            match self.read_line(prompt, color, &mut completer, buffer, cursor) {
+                Ok(Stop::Accept(line)) => break Ok((line, highlighter, displayed)),
+                Ok(Stop::Redraw { buffer: text, cursor: position }) => {
+                    buffer = text;
+                    cursor = position;
+                }
+                Err(err) => break Err(err),
+            }
+        };
 
         match line {
-            Ok(line) => {
+            Ok((line, highlighter, displayed)) => {
+                if let Some(transient) = self.transient_prompt() {
+                    let line = highlighter.highlight(&line);
+                    if let Err(err) = draw_transient(&displayed, &line, &transient) {
+                        eprintln!("ion: transient prompt: {}", err);
+                    }
+                }
                 if line.bytes().next() != Some(b'#')
                     && line.bytes().any(|c| !c.is_ascii_whitespace())
                 {
@@ -39,4 +100,65 @@ impl<'a> InteractiveShell<'a> {
             }
         }
     }
+
+    /// Edit the buffer until a line is accepted, as Liner's `read_line` does, with the keys
+    /// handled by the completer first
+    fn read_line(
+        &self,
+        prompt: Prompt,
+        color: ColorClosure,
+        completer: &mut IonCompleter<'_, '_>,
+        buffer: String,
+        cursor: usize,
+    ) -> io::Result<Stop> {
+        let mut context = self.context.borrow_mut();
+        let key_bindings = context.key_bindings;
+        let stdout = io::stdout().into_raw_mode()?;
+        let mut editor =
+            Editor::new_with_init_buffer(stdout, prompt, Some(color), &mut context, buffer)?;
+        editor.move_cursor_to(cursor)?;
+        let stop = match key_bindings {
+            KeyBindings::Emacs => handle_keys(Emacs::new(), editor, completer),
+            KeyBindings::Vi => handle_keys(Vi::new(), editor, completer),
+        };
+        // Undo the edits of the entries of the history browsed, as Liner does after each line
+        context.revert_all_history();
+        stop
+    }
+
+    /// Add the commands run by the other sessions to the history of the line editor
+    fn load_shared_history(&self) {
+        match self.history.borrow_mut().sync() {
+            Ok(records) => {
+                let mut context = self.context.borrow_mut();
+                for record in records {
+                    let _ = context.history.push(record.command.into());
+                }
+            }
+            Err(err) => eprintln!("ion: history sync: {}", err),
+        }
+    }
+}
+
+/// Give the keys to the keymap unless the completer handles them, until a line is accepted
+fn handle_keys<W: Write, M: KeyMap>(
+    mut keymap: M,
+    mut editor: Editor<'_, W>,
+    completer: &mut IonCompleter<'_, '_>,
+) -> io::Result<Stop> {
+    for key in io::stdin().keys() {
+        let key = key?;
+        match completer.intercept(key, &mut editor) {
+            Intercept::Pass => {
+                if keymap.handle_key(key, &mut editor, completer)? {
+                    break;
+                }
+            }
+            Intercept::Consumed => (),
+            Intercept::Redraw { cursor } => {
+                return Ok(Stop::Redraw { buffer: editor.into(), cursor });
+            }
+        }
+    }
+    Ok(Stop::Accept(editor.into()))
 }
@@ -0,0 +1,77 @@
+use super::history::History;
+use std::{collections::HashSet, path::Path};
+
+/// Suggests the rest of the line being typed from the history.
+///
+/// Among the history entries starting with the line, the entries last run in the current
+/// directory are preferred, then the ones which succeeded, then the most recent.
+#[derive(Debug, Default)]
+pub struct Suggestions {
+    /// The history entries, from the most to the least preferred
+    commands: Vec<String>,
+}
+
+impl Suggestions {
+    pub fn new(history: &History, cwd: &Path) -> Self {
+        let mut seen = HashSet::new();
+        let mut commands: Vec<(u8, String)> = history
+            .records()
+            .iter()
+            .rev()
+            // Skip the multi-line commands
+            .filter(|record| !record.command.contains('\n'))
+            .filter(|record| seen.insert(record.command.as_str()))
+            .map(|record| {
+                let rank = u8::from(record.cwd.as_ref().map_or(false, |dir| dir == cwd)) * 2
+                    + u8::from(record.status == Some(0));
+                (rank, record.command.clone())
+            })
+            .collect();
+        // The sort is stable, so the most recent commands stay first for equal ranks
+        commands.sort_by(|a, b| b.0.cmp(&a.0));
+        Suggestions { commands: commands.into_iter().map(|(_, command)| command).collect() }
+    }
+
+    /// The text to append to the line to complete it as the preferred history entry
+    pub fn suggest(&self, line: &str) -> Option<&str> {
+        if line.trim().is_empty() {
+            return None;
+        }
+        self.commands
+            .iter()
+            .find(|command| command.len() > line.len() && command.starts_with(line))
+            .map(|command| &command[line.len()..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::history::Record, *};
+
+    #[test]
+    fn preferred_suggestions() {
+        let mut history = History::default();
+        let runs = [
+            ("git status", None, None),
+            ("git stash", Some("/other"), Some(0)),
+            ("git push", Some("/project"), Some(0)),
+            ("git stash", Some("/project"), Some(1)),
+            ("git pull", Some("/project"), Some(1)),
+        ];
+        for &(command, cwd, status) in &runs {
+            history
+                .push(Record { status, cwd: cwd.map(Into::into), ..Record::from_command(command) })
+                .unwrap();
+        }
+
+        let suggestions = Suggestions::new(&history, Path::new("/project"));
+        assert_eq!(suggestions.suggest("git p"), Some("ush"));
+        assert_eq!(suggestions.suggest("git st"), Some("ash"));
+        assert_eq!(suggestions.suggest("git status"), None);
+        assert_eq!(suggestions.suggest(""), None);
+
+        let suggestions = Suggestions::new(&history, Path::new("/elsewhere"));
+        assert_eq!(suggestions.suggest("git p"), Some("ush"));
+        assert_eq!(suggestions.suggest("git s"), Some("tash"));
+    }
+}
@@ -1,4 +1,4 @@
-use std::{fs, os::unix::fs::PermissionsExt};
+use std::{fs, os::unix::fs::PermissionsExt, path::Path};
 
 use super::Status;
 use crate as ion_shell;
@@ -102,8 +102,8 @@ fn match_flag_argument(flag: char, argument: &str, shell: &Shell<'_>) -> bool {
     match flag {
         'a' => array_var_is_not_empty(argument, shell),
         'b' => binary_is_in_path(argument, shell),
-        'd' => path_is_directory(argument),
-        'f' => path_is_file(argument),
+        'd' => path_is_directory(&shell.dir_stack().resolve(argument)),
+        'f' => path_is_file(&shell.dir_stack().resolve(argument)),
         's' => string_var_is_not_empty(argument, shell),
         _ => false,
     }
@@ -118,12 +118,12 @@ fn match_option_argument(option: &str, argument: &str, shell: &Shell<'_>) -> boo
 }
 
 /// Returns true if the file is a regular file
-fn path_is_file(filepath: &str) -> bool {
+fn path_is_file(filepath: &Path) -> bool {
     fs::metadata(filepath).ok().map_or(false, |metadata| metadata.file_type().is_file())
 }
 
 /// Returns true if the file is a directory
-fn path_is_directory(filepath: &str) -> bool {
+fn path_is_directory(filepath: &Path) -> bool {
     fs::metadata(filepath).ok().map_or(false, |metadata| metadata.file_type().is_dir())
 }
 
@@ -196,9 +196,8 @@ mod tests {
         flow_control::Function,
         parser::lexers::assignments::{KeyBuf, Primitive},
         shell::flow_control::Statement,
-        types,
+        types::{self, Shared},
     };
-    use std::rc::Rc;
 
     #[test]
     fn test_evaluate_arguments() {
@@ -319,11 +318,12 @@ mod tests {
 
         shell.variables_mut().set(
             &name,
-            Value::Function(Rc::new(Function::new(
+            Value::Function(Shared::new(Function::new(
                 Some(description),
                 name.clone(),
                 args,
                 statements,
+                types::Str::new(),
             ))),
         );
 
@@ -468,11 +468,12 @@ mod tests {
 
         shell.variables_mut().set(
             &name,
-            Value::Function(Rc::new(Function::new(
+            Value::Function(Shared::new(Function::new(
                 Some(description),
                 name.clone(),
                 args,
                 statements,
+                types::Str::new(),
             ))),
         );
 
@@ -1,5 +1,7 @@
-use super::{super::types, Value};
-use std::rc::Rc;
+use super::{
+    super::types::{self, Shared},
+    Value,
+};
 
 /// The exit status of a command
 ///
@@ -58,7 +60,7 @@ impl Status {
     pub fn toggle(&mut self) { self.0 = if self.is_success() { 1 } else { 0 }; }
 }
 
-impl<'a> From<Status> for Value<Rc<types::Function<'a>>> {
+impl<'a> From<Status> for Value<Shared<types::Function<'a>>> {
     fn from(status: Status) -> Self { Value::Str(status.into()) }
 }
 
@@ -1,4 +1,4 @@
-//! Contains the `jobs`, `disown`, `bg`, and `fg` commands that manage job
+//! Contains the `jobs`, `disown`, `bg`, `fg`, `kill` and `wait` commands that manage job
 //! control in the shell.
 
 use super::Status;
@@ -6,8 +6,57 @@ use crate::{
     shell::{BackgroundProcess, Shell},
     types,
 };
+use nix::{
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
 use smallvec::SmallVec;
 
+/// Find the job given by a job spec: `%%`, `%+` or `%` for the current job, `%-` for the
+/// previous one, `%N` or `N` for the job N, `%name` for the job whose command starts with name,
+/// and `%?text` for the job whose command contains text. The jobs which exited and whose exit
+/// status was not reported yet are found only if `exited` is set.
+fn find_job(shell: &Shell<'_>, spec: &str, exited: bool) -> Result<usize, String> {
+    let recent = shell.recent_jobs();
+    let jobs = shell.background_jobs();
+    let found = |njob: usize| {
+        jobs.get(njob).map_or(false, |job| job.exists() || (exited && job.status().is_some()))
+    };
+    let recent: Vec<usize> = recent.into_iter().filter(|&njob| found(njob)).collect();
+    let (prefixed, name) = match spec.chars().next() {
+        Some('%') => (true, &spec[1..]),
+        _ => (false, spec),
+    };
+    let njob = match name {
+        "" | "%" | "+" if prefixed => recent.first().cloned(),
+        "-" if prefixed => recent.get(1).cloned(),
+        _ if name.bytes().all(|byte| byte.is_ascii_digit()) && !name.is_empty() => {
+            name.parse().ok().filter(|&njob| found(njob))
+        }
+        _ if prefixed => {
+            let matches: SmallVec<[usize; 4]> = jobs
+                .iter()
+                .enumerate()
+                .filter(|&(njob, _)| found(njob))
+                .filter(|(_, job)| {
+                    if name.starts_with('?') {
+                        job.name().contains(&name[1..])
+                    } else {
+                        job.name().starts_with(name)
+                    }
+                })
+                .map(|(njob, _)| njob)
+                .collect();
+            if matches.len() > 1 {
+                return Err(format!("{}: ambiguous job spec", spec));
+            }
+            matches.first().cloned()
+        }
+        _ => return Err(format!("invalid job spec: '{}'", spec)),
+    };
+    njob.ok_or_else(|| format!("{}: no such job", spec))
+}
+
 /// Disowns given process job IDs, and optionally marks jobs to not receive SIGHUP signals.
 /// The `-a` flag selects all jobs, `-r` selects all running jobs, and `-h` specifies to mark
 /// SIGHUP ignoral.
@@ -26,11 +75,7 @@ pub fn disown(shell: &mut Shell<'_>, args: &[types::Str]) -> Result<(), String>
             "-a" => all_jobs = true,
             "-h" => no_sighup = true,
             "-r" => run_jobs = true,
-            _ => {
-                let jobspec =
-                    arg.parse::<usize>().map_err(|_| format!("invalid jobspec: '{}'", arg))?;
-                collected_jobs.push(jobspec);
-            }
+            _ => collected_jobs.push(find_job(shell, arg, false)?),
         }
     }
 
@@ -99,13 +144,11 @@ pub fn fg(shell: &mut Shell<'_>, args: &[types::Str]) -> Status {
         }
     } else {
         for arg in args {
-            match arg.parse::<usize>() {
+            match find_job(shell, arg, false) {
                 Ok(njob) => {
                     fg_job(shell, njob);
                 }
-                Err(_) => {
-                    return Status::error(format!("ion: fg: {} is not a valid job number", arg))
-                }
+                Err(why) => return Status::error(format!("ion: fg: {}", why)),
             }
         }
         Status::SUCCESS
@@ -135,15 +178,283 @@ pub fn bg(shell: &mut Shell<'_>, args: &[types::Str]) -> Status {
         }
     } else {
         for arg in args {
-            if let Ok(njob) = arg.parse::<usize>() {
-                let status = bg_job(shell, njob);
-                if !status.is_success() {
-                    return status;
+            match find_job(shell, arg, false) {
+                Ok(njob) => {
+                    let status = bg_job(shell, njob);
+                    if !status.is_success() {
+                        return status;
+                    }
                 }
-            } else {
-                return Status::error(format!("ion: bg: {} is not a valid job number", arg));
-            };
+                Err(why) => return Status::error(format!("ion: bg: {}", why)),
+            }
         }
         Status::SUCCESS
     }
 }
+
+/// Parse a signal given by its name, with or without the `SIG` prefix, or by its number. The
+/// signal 0 is `None`: it is not sent, but the processes are checked to exist.
+fn parse_signal(name: &str) -> Result<Option<Signal>, String> {
+    let signal = match name.parse::<i32>() {
+        Ok(0) => return Ok(None),
+        Ok(number) => Signal::from_c_int(number).ok(),
+        Err(_) => {
+            let name = name.to_ascii_uppercase();
+            let name = if name.starts_with("SIG") { name } else { format!("SIG{}", name) };
+            name.parse().ok()
+        }
+    };
+    signal.map(Some).ok_or_else(|| format!("invalid signal: {}", name))
+}
+
+/// Sends a signal, SIGTERM by default, to the jobs given by job specs and to the processes given
+/// by their ID. The signal is given as `-SIGNAL` or `-s SIGNAL`, and `-l` lists the signals.
+pub fn kill(shell: &Shell<'_>, args: &[types::Str]) -> Result<(), String> {
+    let mut args = args.iter().map(types::Str::as_str).peekable();
+    let signal = match args.peek().cloned() {
+        Some("-l") => {
+            for signal in Signal::iterator() {
+                println!("{:>2}) {}", signal as i32, signal);
+            }
+            return Ok(());
+        }
+        Some("-s") => {
+            args.next();
+            parse_signal(args.next().ok_or("-s requires a signal")?)?
+        }
+        Some(arg) if arg.starts_with('-') && arg != "--" && arg.len() > 1 => {
+            args.next();
+            parse_signal(&arg[1..])?
+        }
+        _ => Some(Signal::SIGTERM),
+    };
+    if args.peek() == Some(&"--") {
+        args.next();
+    }
+    if args.peek().is_none() {
+        return Err("must provide jobs or processes to signal".into());
+    }
+
+    for arg in args {
+        if arg.starts_with('%') {
+            let njob = find_job(shell, arg, false)?;
+            let job = shell.background_jobs()[njob].clone();
+            signal::killpg(job.pid(), signal).map_err(|why| format!("{}: {}", arg, why))?;
+            // A stopped job has to be resumed to handle the signal, unless it stops or resumes it,
+            // or is not sent
+            let handled = match signal {
+                Some(Signal::SIGSTOP) | Some(Signal::SIGTSTP) | Some(Signal::SIGCONT) => false,
+                Some(_) => true,
+                None => false,
+            };
+            if !job.is_running() && handled {
+                job.resume();
+            }
+        } else {
+            let pid = arg.parse().map_err(|_| format!("invalid process ID: '{}'", arg))?;
+            signal::kill(Pid::from_raw(pid), signal).map_err(|why| format!("{}: {}", arg, why))?;
+        }
+    }
+    Ok(())
+}
+
+/// Waits for the jobs given by job specs and the processes given by their ID, and returns the
+/// exit status of the last one. With `-n`, only waits until one of them, or of all the jobs,
+/// exits, those which exited without being waited for first, and with `-p VARIABLE`, assigns the
+/// ID of the job waited for to the variable.
+pub fn wait(shell: &mut Shell<'_>, args: &[types::Str]) -> Result<Status, String> {
+    let (mut any, mut variable) = (false, None);
+    let mut jobs: SmallVec<[usize; 16]> = SmallVec::new();
+    let mut args = args.iter().map(types::Str::as_str);
+    while let Some(arg) = args.next() {
+        match arg {
+            "-n" => any = true,
+            "-p" => variable = Some(args.next().ok_or("-p requires a variable")?),
+            _ if arg.starts_with('%') => jobs.push(find_job(shell, arg, true)?),
+            _ => {
+                let pid = arg.parse().map_err(|_| format!("invalid process ID: '{}'", arg))?;
+                // The jobs which exited are kept, with their exit status
+                let njob = shell.background_jobs().iter().position(|job| job.pid().as_raw() == pid);
+                jobs.push(njob.ok_or_else(|| format!("{}: not a child of this shell", arg))?);
+            }
+        }
+    }
+
+    if jobs.is_empty() {
+        if !any {
+            shell.wait_for_background().map_err(|err| err.to_string())?;
+            return Ok(Status::SUCCESS);
+        }
+        jobs = shell
+            .background_jobs()
+            .iter()
+            .enumerate()
+            .filter(|(_, job)| job.exists() || job.status().is_some())
+            .map(|(njob, _)| njob)
+            .collect();
+        if jobs.is_empty() {
+            return Ok(Status::NO_SUCH_COMMAND);
+        }
+    }
+
+    let mut waited = (0, Status::SUCCESS);
+    if any {
+        waited = shell.wait_for_any(&jobs).map_err(|err| err.to_string())?;
+    } else {
+        for &njob in &jobs {
+            waited = shell.wait_for_any(&[njob]).map_err(|err| err.to_string())?;
+        }
+    }
+    let (njob, status) = waited;
+    if let Some(variable) = variable {
+        shell.variables_mut().set(variable, njob.to_string());
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    fn args(args: &[&str]) -> Vec<types::Str> { args.iter().map(|&arg| arg.into()).collect() }
+
+    /// Run the jobs in background, and wait for them to be in a process group of their own
+    fn spawn_jobs(shell: &mut Shell<'_>, jobs: &str) {
+        shell.execute_command(jobs.as_bytes()).unwrap();
+        let pids: Vec<_> = shell.background_jobs().iter().map(BackgroundProcess::pid).collect();
+        for pid in pids {
+            while signal::killpg(pid, None).is_err() {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+
+    #[test]
+    fn parse_signals() {
+        assert_eq!(parse_signal("TERM"), Ok(Some(Signal::SIGTERM)));
+        assert_eq!(parse_signal("sighup"), Ok(Some(Signal::SIGHUP)));
+        assert_eq!(parse_signal("9"), Ok(Some(Signal::SIGKILL)));
+        assert_eq!(parse_signal("0"), Ok(None));
+        assert!(parse_signal("NOPE").is_err());
+        assert!(parse_signal("-1").is_err());
+    }
+
+    #[test]
+    fn wait_for_nothing() {
+        let mut shell = Shell::default();
+        assert_eq!(wait(&mut shell, &args(&[])), Ok(Status::SUCCESS));
+        assert_eq!(wait(&mut shell, &args(&["-n"])), Ok(Status::NO_SUCH_COMMAND));
+        assert!(wait(&mut shell, &args(&["1"])).is_err());
+        assert!(wait(&mut shell, &args(&["-p"])).is_err());
+    }
+
+    #[test]
+    fn wait_for_exited_jobs() {
+        let mut shell = Shell::default();
+        spawn_jobs(&mut shell, "sleep 104 &");
+        shell.execute_command("sh -c 'exit 3' &".as_bytes()).unwrap();
+        while shell.background_jobs()[1].exists() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // The job which exited before the wait is returned first, and only once
+        assert_eq!(wait(&mut shell, &args(&["-n", "-p", "job"])), Ok(Status::from_exit_code(3)));
+        assert_eq!(shell.variables().get_str("job").unwrap().as_str(), "1");
+        kill(&shell, &args(&["-KILL", "%0"])).unwrap();
+        assert_eq!(wait(&mut shell, &args(&["-n", "-p", "job"])), Ok(Status::from_signal(9)));
+        assert_eq!(shell.variables().get_str("job").unwrap().as_str(), "0");
+        assert_eq!(wait(&mut shell, &args(&["-n"])), Ok(Status::NO_SUCH_COMMAND));
+    }
+
+    #[test]
+    fn wait_for_exited_job_specs() {
+        let mut shell = Shell::default();
+        shell.execute_command("sh -c 'exit 4' &\nsh -c 'exit 5' &".as_bytes()).unwrap();
+        while shell.background_jobs().iter().any(BackgroundProcess::exists) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // The jobs which exited are waited for until their exit status is reported
+        assert_eq!(find_job(&shell, "%0", false), Err("%0: no such job".into()));
+        assert_eq!(wait(&mut shell, &args(&["%0"])), Ok(Status::from_exit_code(4)));
+        assert_eq!(wait(&mut shell, &args(&["%+"])), Ok(Status::from_exit_code(5)));
+        assert_eq!(wait(&mut shell, &args(&["%1"])), Err("%1: no such job".into()));
+    }
+
+    #[test]
+    fn drop_old_exit_statuses() {
+        fn exit(shell: &mut Shell<'_>, code: i32) {
+            shell.execute_command(format!("sh -c 'exit {}' &", code).as_bytes()).unwrap();
+            while shell.background_jobs().iter().any(BackgroundProcess::exists) {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+
+        let mut shell = Shell::default();
+        // The exit statuses of 32 jobs are kept at most
+        for code in 0..33 {
+            exit(&mut shell, code);
+        }
+        assert_eq!(shell.background_jobs().len(), 32);
+        assert_eq!(wait(&mut shell, &args(&["%0"])), Ok(Status::from_exit_code(32)));
+        assert_eq!(wait(&mut shell, &args(&["%1"])), Ok(Status::from_exit_code(1)));
+
+        // The ID of a job reported is given to the next one
+        exit(&mut shell, 33);
+        assert_eq!(wait(&mut shell, &args(&["%0"])), Ok(Status::from_exit_code(33)));
+    }
+
+    #[test]
+    fn find_jobs() {
+        let shell = Shell::default();
+        assert_eq!(find_job(&shell, "%+", false), Err("%+: no such job".into()));
+        assert_eq!(find_job(&shell, "0", false), Err("0: no such job".into()));
+        assert_eq!(find_job(&shell, "vim", false), Err("invalid job spec: 'vim'".into()));
+    }
+
+    #[test]
+    fn find_background_jobs() {
+        let mut shell = Shell::default();
+        spawn_jobs(&mut shell, "sleep 101 &\nsleep 102 &\ntail -f /dev/null &");
+        for &spec in &["%%", "%+", "%", "%2", "2", "%tail", "%?null"] {
+            assert_eq!(find_job(&shell, spec, false), Ok(2), "{}", spec);
+        }
+        assert_eq!(find_job(&shell, "%-", false), Ok(1));
+        assert_eq!(find_job(&shell, "%?102", false), Ok(1));
+        assert_eq!(find_job(&shell, "%sleep", false), Err("%sleep: ambiguous job spec".into()));
+        assert_eq!(find_job(&shell, "%?10", false), Err("%?10: ambiguous job spec".into()));
+        assert_eq!(find_job(&shell, "%vim", false), Err("%vim: no such job".into()));
+        assert_eq!(find_job(&shell, "%3", false), Err("%3: no such job".into()));
+
+        // The job killed is not found anymore, and the previous one becomes the current one
+        kill(&shell, &args(&["-KILL", "%2"])).unwrap();
+        let pid = shell.background_jobs()[2].pid().to_string();
+        assert_eq!(wait(&mut shell, &args(&[pid.as_str()])), Ok(Status::from_signal(9)));
+        assert_eq!(find_job(&shell, "%tail", false), Err("%tail: no such job".into()));
+        assert_eq!(find_job(&shell, "%+", false), Ok(1));
+        assert_eq!(find_job(&shell, "%-", false), Ok(0));
+        kill(&shell, &args(&["-KILL", "%0", "%1"])).unwrap();
+    }
+
+    #[test]
+    fn kill_stopped_jobs() {
+        let mut shell = Shell::default();
+        spawn_jobs(&mut shell, "sleep 103 &");
+        kill(&shell, &args(&["-STOP", "%0"])).unwrap();
+        while shell.background_jobs()[0].is_running() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // The signal 0 only checks that the job exists, and does not resume it
+        kill(&shell, &args(&["-0", "%0"])).unwrap();
+        let pid = shell.background_jobs()[0].pid().to_string();
+        kill(&shell, &args(&["-s", "0", pid.as_str()])).unwrap();
+        assert!(!shell.background_jobs()[0].is_running());
+        assert!(kill(&shell, &args(&["-0", &i32::max_value().to_string()])).is_err());
+
+        // The job is resumed to handle the signal
+        kill(&shell, &args(&["-INT", "%0"])).unwrap();
+        assert_eq!(wait(&mut shell, &args(&[pid.as_str()])), Ok(Status::from_signal(2)));
+    }
+}
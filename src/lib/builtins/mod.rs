@@ -12,6 +12,7 @@ mod job_control;
 mod math;
 mod random;
 mod set;
+mod signature;
 mod source;
 mod status;
 mod test;
@@ -28,6 +29,7 @@ pub use self::{
     man_pages::check_help,
     math::builtin_math,
     set::builtin_set,
+    signature::{Flag, Param, Signature},
     source::builtin_source,
     status::builtin_status,
     test::builtin_test,
@@ -48,7 +50,7 @@ use std::{
     fs::File,
     io::{self, BufRead, BufReader},
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    process::Stdio,
 };
 
 const HELP_DESC: &str = "Display helpful information about a given command or list commands if \
@@ -60,7 +62,12 @@ const DISOWN_DESC: &str =
     "Disowning a process removes that process from the shell's background process table.";
 
 /// The type for builtin functions. Builtins have direct access to the shell
+#[cfg(not(feature = "send"))]
 pub type BuiltinFunction<'a> = &'a dyn Fn(&[types::Str], &mut Shell<'_>) -> Status;
+/// The type for builtin functions. Builtins have direct access to the shell, and are shared
+/// between threads with the `send` feature
+#[cfg(feature = "send")]
+pub type BuiltinFunction<'a> = &'a (dyn Fn(&[types::Str], &mut Shell<'_>) -> Status + Sync);
 
 // parses -N or +N patterns
 // required for popd, pushd, dirs
@@ -99,8 +106,9 @@ fn parse_numeric_arg(arg: &str) -> Option<(bool, usize)> {
 /// );
 /// // >> Hello world!
 pub struct BuiltinMap<'a> {
-    fcts: HashMap<&'static str, BuiltinFunction<'a>>,
-    help: HashMap<&'static str, &'static str>,
+    fcts:       HashMap<&'static str, BuiltinFunction<'a>>,
+    help:       HashMap<&'static str, &'static str>,
+    signatures: HashMap<&'static str, &'static Signature>,
 }
 
 impl<'a> Default for BuiltinMap<'a> {
@@ -126,11 +134,21 @@ impl<'a> BuiltinMap<'a> {
     /// Create a new, blank builtin map
     ///
     /// If you have a hint over the number of builtins, with_capacity is probably better
-    pub fn new() -> Self { BuiltinMap { fcts: HashMap::new(), help: HashMap::new() } }
+    pub fn new() -> Self {
+        BuiltinMap {
+            fcts:       HashMap::new(),
+            help:       HashMap::new(),
+            signatures: HashMap::new(),
+        }
+    }
 
     /// Create a new, blank builtin map with a given capacity
     pub fn with_capacity(cap: usize) -> Self {
-        BuiltinMap { fcts: HashMap::with_capacity(cap), help: HashMap::with_capacity(cap) }
+        BuiltinMap {
+            fcts:       HashMap::with_capacity(cap),
+            help:       HashMap::with_capacity(cap),
+            signatures: HashMap::new(),
+        }
     }
 
     /// Check if the given builtin exists
@@ -145,6 +163,21 @@ impl<'a> BuiltinMap<'a> {
     /// Get the function of a given builtin
     pub fn get(&self, func: &str) -> Option<BuiltinFunction<'a>> { self.fcts.get(func).cloned() }
 
+    /// Get the arguments accepted by a given builtin, if it declared them
+    pub fn get_signature(&self, func: &str) -> Option<&'static Signature> {
+        self.signatures.get(func).copied()
+    }
+
+    /// Declare the arguments accepted by a builtin, as generated by the `builtin` attribute
+    pub fn add_signature(
+        &mut self,
+        name: &'static str,
+        signature: &'static Signature,
+    ) -> &mut Self {
+        self.signatures.insert(name, signature);
+        self
+    }
+
     /// Add a new builtin
     pub fn add(
         &mut self,
@@ -170,18 +203,15 @@ impl<'a> BuiltinMap<'a> {
 
     /// Control subrpocesses states
     ///
-    /// Contains `disown`, `bg`, `fg`, `wait`, `isatty`, `jobs`
+    /// Contains `disown`, `bg`, `fg`, `kill`, `wait`, `isatty`, `jobs`
     pub fn with_process_control(&mut self) -> &mut Self {
         self.add("disown", &builtin_disown, DISOWN_DESC)
             .add("bg", &builtin_bg, "Resumes a stopped background process")
             .add("fg", &builtin_fg, "Resumes and sets a background process as the active process")
-            .add(
-                "wait",
-                &builtin_wait,
-                "Waits until all running background processes have completed",
-            )
+            .add("wait", &builtin_wait, "Waits for background jobs to exit")
             .add("isatty", &builtin_isatty, "Returns 0 exit status if the supplied FD is a tty")
             .add("jobs", &builtin_jobs, "Displays all jobs that are attached to the background")
+            .add("kill", &builtin_kill, "Sends a signal to jobs or processes")
     }
 
     /// Utilities concerning the filesystem
@@ -193,7 +223,9 @@ impl<'a> BuiltinMap<'a> {
             .add("popd", &builtin_popd, "Pop a directory from the stack")
             .add("pushd", &builtin_pushd, "Push a directory to the stack")
             .add("dirs", &builtin_dirs, "Display the current directory stack")
+            .add_signature("dirs", &BUILTIN_DIRS_SIGNATURE)
             .add("cd", &builtin_cd, "Change the current directory\n    cd <path>")
+            .add_signature("cd", &BUILTIN_CD_SIGNATURE)
             .add("dir_depth", &builtin_dir_depth, "Set the maximum directory depth")
     }
 
@@ -262,7 +294,7 @@ SYNOPSYS
 DESCRIPTION
     Execute the script literal given in argument and apply env vars diff to the current shell"
 )]
-pub fn source_sh(args: &[types::Str], _shell: &mut Shell<'_>) -> Status {
+pub fn source_sh(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     let arg = match args.get(1) {
         None => return Status::bad_argument("Please pass a shell script as option"),
         Some(arg) => arg,
@@ -272,7 +304,8 @@ pub fn source_sh(args: &[types::Str], _shell: &mut Shell<'_>) -> Status {
         Err(e) => return Status::error(format!("Could not create temp file for source-sh: {}", e)),
     };
     let script = format!("{}\nenv | sort > {}", arg, temp.as_path().display());
-    match Command::new("sh")
+    match shell
+        .child_command("sh")
         .args(&["-c", &script])
         .stdout(Stdio::null())
         .stderr(Stdio::null())
@@ -302,9 +335,8 @@ pub fn source_sh(args: &[types::Str], _shell: &mut Shell<'_>) -> Status {
                         ))
                     }
                 };
-                let prev_val = std::env::var_os(name);
-                if prev_val.as_ref().and_then(|x| x.to_str()) != Some(val) {
-                    std::env::set_var(name, val);
+                if shell.variables().get_env(name) != Some(val) {
+                    shell.variables_mut().set_env(name, val);
                 }
             }
             Status::SUCCESS
@@ -338,15 +370,15 @@ pub fn dir_depth(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     desc = "Change directory.",
     man = "
 SYNOPSIS
-    cd DIRECTORY
+    cd [--] [DIRECTORY]
 
 DESCRIPTION
     Without arguments cd changes the working directory to your home directory.
     With arguments cd changes the working directory to the directory you provided.
 "
 )]
-pub fn cd(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
-    let err = match args.get(1) {
+pub fn cd(dir: Option<String>, shell: &mut Shell<'_>) -> Status {
+    let err = match dir {
         Some(dir) => {
             let dir = dir.as_str();
             if let Some(Value::Array(cdpath)) = shell.variables().get("CDPATH").cloned() {
@@ -370,11 +402,15 @@ pub fn cd(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
                 shell.dir_stack_mut().change_and_push_dir(Path::new(dir))
             }
         }
-        None => shell.dir_stack_mut().switch_to_home_directory(),
+        None => match shell.variables().get_env("HOME").map(PathBuf::from) {
+            Some(home) => shell.dir_stack_mut().change_and_push_dir(&home),
+            None => shell.dir_stack_mut().switch_to_home_directory(),
+        },
     };
 
     match err {
         Ok(()) => {
+            shell.export_dirs();
             if let Some(Value::Function(function)) = shell.variables().get("CD_CHANGE").cloned() {
                 let _ = shell.execute_function(&function, &["ion"]);
             }
@@ -412,13 +448,27 @@ pub fn bool(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     desc = "prints the directory stack",
     man = "
 SYNOPSIS
-    dirs
+    dirs [-c | --clear] [-l | --long] [-p | --print] [-v | --verbose] [+N | -N]
 
 DESCRIPTION
-    dirs prints the current directory stack.
+    dirs prints the current directory stack, or only its Nth directory, counted from the left
+    with +N or from the right with -N.
+
+OPTIONS
+    -c, --clear    Clear the directory stack.
+    -l, --long     Print the absolute paths of the directories.
+    -p, --print    Print a directory per line.
+    -v, --verbose  Print a directory per line, with its position in the stack.
 "
 )]
-pub fn dirs(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
+pub fn dirs(
+    #[flag(short = 'c')] clear: bool,
+    #[flag(short = 'l')] long: bool,
+    #[flag(short = 'p')] print: bool,
+    #[flag(short = 'v')] verbose: bool,
+    position: Option<String>,
+    shell: &mut Shell<'_>,
+) -> Status {
     // converts pbuf to an absolute path if possible
     fn try_abs_path(pbuf: &PathBuf) -> Cow<'_, str> {
         Cow::Owned(
@@ -426,31 +476,13 @@ pub fn dirs(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
         )
     }
 
-    let mut clear = false; // -c
-    let mut abs_pathnames = false; // -l
-    let mut multiline = false; // -p | -v
-    let mut index = false; // -v
-
-    let mut num_arg = None;
-
-    for arg in args.iter().skip(1) {
-        match arg.as_ref() {
-            "-c" => clear = true,
-            "-l" => abs_pathnames = true,
-            "-p" => multiline = true,
-            "-v" => {
-                index = true;
-                multiline = true;
-            }
-            _ => num_arg = Some(arg),
-        }
-    }
+    let multiline = print || verbose;
 
     if clear {
         shell.dir_stack_mut().clear();
     }
 
-    let mapper: fn((usize, &PathBuf)) -> Cow<'_, str> = match (abs_pathnames, index) {
+    let mapper: fn((usize, &PathBuf)) -> Cow<'_, str> = match (long, verbose) {
         // ABS, INDEX
         (true, true) => |(num, x)| Cow::Owned(format!(" {}  {}", num, try_abs_path(x))),
         (true, false) => |(_, x)| try_abs_path(x),
@@ -460,8 +492,8 @@ pub fn dirs(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
 
     let mut iter = shell.dir_stack().dirs();
 
-    if let Some(arg) = num_arg {
-        let num = match parse_numeric_arg(arg.as_ref()) {
+    if let Some(arg) = position {
+        let num = match parse_numeric_arg(&arg) {
             Some((true, num)) => num,
             Some((false, num)) if shell.dir_stack().dirs().count() > num => {
                 shell.dir_stack().dirs().count() - num - 1
@@ -722,19 +754,32 @@ DESCRIPTION
 pub fn false_(args: &[types::Str], _: &mut Shell<'_>) -> Status { Status::FALSE }
 
 #[builtin(
-    desc = "wait for a background job",
+    desc = "wait for background jobs",
     man = "
 SYNOPSIS
-    wait
+    wait [-n] [-p VARIABLE] [JOB | PID...]
 
 DESCRIPTION
-    Wait for the background jobs to finish"
+    Waits for the jobs and the processes to exit, and returns the exit status of the last one.
+    The jobs are given by job specs, as %1, %+ or %name, as for bg. Without JOB or PID, waits
+    for all the background jobs to finish, and returns 0. The exit statuses of the jobs which
+    exited are kept until they are waited for, for the last 32 jobs at most.
+
+OPTIONS
+    -n           Wait until any one of the jobs, or of all the background jobs, exits, and return
+                 its exit status, or 127 if there is no job. The jobs which already exited
+                 and were not waited for are returned first.
+    -p VARIABLE  Assign the ID of the job waited for to VARIABLE.
+
+EXAMPLES
+    sleep 2 &
+    wait %sleep
+    wait -n -p finished"
 )]
 pub fn wait(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
-    if let Err(err) = shell.wait_for_background() {
-        Status::error(err.to_string())
-    } else {
-        Status::SUCCESS
+    match job_control::wait(shell, &args[1..]) {
+        Ok(status) => status,
+        Err(err) => Status::error(format!("ion: wait: {}", err)),
     }
 }
 
@@ -756,10 +801,19 @@ pub fn jobs(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     desc = "sends jobs to background",
     man = "
 SYNOPSIS
-    bg PID
+    bg [JOB...]
 
 DESCRIPTION
-    bg sends the job to the background resuming it if it has stopped."
+    bg sends the jobs to the background resuming them if they have stopped. Without JOB, the
+    current job is resumed.
+
+JOBS
+    A job is given by its number, as listed by jobs, or by a job spec:
+    %N          The job number N.
+    %+, %%, %   The current job, the last one sent to the background.
+    %-          The previous job.
+    %name       The job whose command starts with name.
+    %?text      The job whose command contains text."
 )]
 pub fn bg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     job_control::bg(shell, &args[1..])
@@ -769,10 +823,11 @@ pub fn bg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     desc = "bring job to the foreground",
     man = "
 SYNOPSIS
-    fg PID
+    fg [JOB...]
 
 DESCRIPTION
-    fg brings the specified job to foreground resuming it if it has stopped."
+    fg brings the specified job to foreground resuming it if it has stopped. Without JOB, the
+    current job is brought to the foreground. The jobs are given as for bg."
 )]
 pub fn fg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     job_control::fg(shell, &args[1..])
@@ -782,7 +837,7 @@ pub fn fg(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     desc = "disown processes",
     man = "
 SYNOPSIS
-    disown [ --help | -r | -h | -a ][PID...]
+    disown [ --help | -r | -h | -a ][JOB...]
 
 DESCRIPTION
     Disowning a process removes that process from the shell's background process table.
@@ -800,6 +855,38 @@ pub fn disown(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     }
 }
 
+#[builtin(
+    desc = "send a signal to jobs or processes",
+    man = "
+SYNOPSIS
+    kill [-s SIGNAL | -SIGNAL] JOB | PID...
+    kill -l
+
+DESCRIPTION
+    Sends SIGNAL, or SIGTERM by default, to the jobs and the processes. The jobs are given by job
+    specs, as %1, %+ or %name, as for bg. A stopped job is resumed after any signal other than
+    SIGSTOP, SIGTSTP and SIGCONT, so that it handles the signal. The signal 0 is not sent, but
+    kill fails if the jobs or the processes do not exist.
+
+OPTIONS
+    -s SIGNAL  The signal to send, given by its name, with or without the SIG prefix, or its
+               number.
+    -SIGNAL    The same as -s SIGNAL.
+    -l         List the signals.
+
+EXAMPLES
+    kill %1
+    kill -HUP %vim
+    kill -s INT %?server 1234
+    kill -0 1234"
+)]
+pub fn kill(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
+    match job_control::kill(shell, &args[1..]) {
+        Ok(()) => Status::SUCCESS,
+        Err(err) => Status::error(format!("ion: kill: {}", err)),
+    }
+}
+
 #[builtin(
     desc = "get help for builtins",
     man = "
@@ -17,7 +17,7 @@ enum PositionalArgs {
     desc = "Set or unset values of shell options and positional parameters.",
     man = "
 SYNOPSIS
-    set [ --help ] [-e | +e] [- | --] [STRING]...
+    set [ --help ] [-e | +e] [-u | +u] [-o | +o OPTION] [- | --] [STRING]...
 
 DESCRIPTION
     Shell options may be set using the '-' character, and unset using the '+' character.
@@ -25,6 +25,13 @@ DESCRIPTION
 OPTIONS
     -e  Exit immediately if a command exits with a non-zero status.
 
+    -u  Treat references to unset variables, arrays and map keys as errors.
+
+    -o  Set the shell option given as argument. Supported options are:
+            pipefail    The status of a pipeline is the one of its last failing command,
+                        or zero if all the commands succeeded. The status of each command
+                        is always available in the @PIPESTATUS array.
+
     --  Following arguments will be set as positional arguments in the shell.
         If no argument are supplied, arguments will be unset.
 
@@ -51,6 +58,23 @@ pub fn set(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
             }
             "-e" => shell.opts_mut().err_exit = true,
             "+e" => shell.opts_mut().err_exit = false,
+            "-u" => shell.opts_mut().no_unset = true,
+            "+u" => shell.opts_mut().no_unset = false,
+            "-o" | "+o" => {
+                let enable = arg.as_str() == "-o";
+                match args_iter.next().map(types::Str::as_str) {
+                    Some("pipefail") => shell.opts_mut().pipe_fail = enable,
+                    Some(option) => {
+                        return Status::bad_argument(format!(
+                            "set: option '{}' is not recognized",
+                            option
+                        ))
+                    }
+                    None => {
+                        return Status::bad_argument(format!("set: {} requires an option", arg))
+                    }
+                }
+            }
             _ => {
                 return Status::bad_argument(format!(
                     "set: argument '{}' is not recognized. Try adding `--` before it to pass it \
@@ -0,0 +1,45 @@
+/// The arguments accepted by a builtin, as declared with the `builtin` attribute
+///
+/// This is used to complete the flags and arguments of builtins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Signature {
+    /// The boolean flags of the builtin
+    pub flags:  &'static [Flag],
+    /// The positional parameters of the builtin, in order
+    pub params: &'static [Param],
+}
+
+/// A boolean flag of a builtin, set with `--long` or `-s`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Flag {
+    /// The long name of the flag, without the leading dashes
+    pub long:  &'static str,
+    /// The short name of the flag, if any
+    pub short: Option<char>,
+}
+
+/// A positional parameter of a builtin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Param {
+    /// The name of the parameter, as shown in error messages
+    pub name:     &'static str,
+    /// The type the argument is parsed to
+    pub kind:     &'static str,
+    /// The parameter may be omitted
+    pub optional: bool,
+    /// The parameter collects all the remaining arguments
+    pub variadic: bool,
+}
+
+impl Signature {
+    /// Find the flag matching the given argument, either `--long` or `-s`
+    pub fn flag(&self, arg: &str) -> Option<&Flag> {
+        if arg.starts_with("--") {
+            self.flags.iter().find(|flag| flag.long == &arg[2..])
+        } else if arg.starts_with('-') && arg.chars().count() == 2 {
+            self.flags.iter().find(|flag| flag.short == arg.chars().nth(1))
+        } else {
+            None
+        }
+    }
+}
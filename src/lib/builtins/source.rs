@@ -17,7 +17,7 @@ DESCRIPTION
 pub fn source(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
     match args.get(1) {
         Some(argument) => {
-            if let Ok(file) = File::open(argument.as_str()) {
+            if let Ok(file) = File::open(shell.dir_stack().resolve(argument.as_str())) {
                 if let Err(why) = shell.execute_command(file) {
                     Status::error(format!("ion: {}", why))
                 } else {
@@ -119,15 +119,16 @@ EXAMPLES
 AUTHOR
     Written by Michael Murphy."#
 )]
-pub fn test(args: &[types::Str], _: &mut Shell<'_>) -> Status {
-    match evaluate_arguments(&args[1..]) {
+pub fn test(args: &[types::Str], shell: &mut Shell<'_>) -> Status {
+    match evaluate_arguments(&args[1..], shell.dir_stack().current_dir()) {
         Ok(true) => Status::TRUE,
         Ok(false) => Status::FALSE,
         Err(why) => Status::error(why),
     }
 }
 
-fn evaluate_arguments(arguments: &[types::Str]) -> Result<bool, types::Str> {
+/// Evaluate the expression, resolving relative file operands against `cwd`
+fn evaluate_arguments(arguments: &[types::Str], cwd: &Path) -> Result<bool, types::Str> {
     match arguments.first() {
         Some(s) if s.starts_with('-') && s[1..].starts_with(char::is_alphabetic) => {
             // Access the second character in the flag string: this will be type of the
@@ -137,7 +138,7 @@ fn evaluate_arguments(arguments: &[types::Str]) -> Result<bool, types::Str> {
                 arguments.get(1).map_or(Ok(true), {
                     |arg|
                     // Match the correct function to the associated flag
-                    Ok(match_flag_argument(flag, arg))
+                    Ok(match_flag_argument(flag, arg, cwd))
                 })
             })
         }
@@ -150,7 +151,7 @@ fn evaluate_arguments(arguments: &[types::Str]) -> Result<bool, types::Str> {
                     let right_arg = arguments
                         .get(2)
                         .ok_or_else(|| types::Str::from("parse error: condition expected"))?;
-                    evaluate_expression(arg, operator, right_arg)
+                    evaluate_expression(arg, operator, right_arg, cwd)
                 },
             )
         }
@@ -161,13 +162,18 @@ fn evaluate_arguments(arguments: &[types::Str]) -> Result<bool, types::Str> {
     }
 }
 
-fn evaluate_expression(first: &str, operator: &str, second: &str) -> Result<bool, types::Str> {
+fn evaluate_expression(
+    first: &str,
+    operator: &str,
+    second: &str,
+    cwd: &Path,
+) -> Result<bool, types::Str> {
     match operator {
         "=" | "==" => Ok(first == second),
         "!=" => Ok(first != second),
-        "-ef" => Ok(files_have_same_device_and_inode_numbers(first, second)),
-        "-nt" => Ok(file_is_newer_than(first, second)),
-        "-ot" => Ok(file_is_newer_than(second, first)),
+        "-ef" => Ok(files_have_same_device_and_inode_numbers(&cwd.join(first), &cwd.join(second))),
+        "-nt" => Ok(file_is_newer_than(&cwd.join(first), &cwd.join(second))),
+        "-ot" => Ok(file_is_newer_than(&cwd.join(second), &cwd.join(first))),
         _ => {
             let (left, right) = parse_integers(first, second)?;
             match operator {
@@ -184,7 +190,7 @@ fn evaluate_expression(first: &str, operator: &str, second: &str) -> Result<bool
 }
 
 /// Exits SUCCESS if both files have the same device and inode numbers
-fn files_have_same_device_and_inode_numbers(first: &str, second: &str) -> bool {
+fn files_have_same_device_and_inode_numbers(first: &Path, second: &Path) -> bool {
     // Obtain the device and inode of the first file or return FAILED
     get_dev_and_inode(first).map_or(false, |left| {
         // Obtain the device and inode of the second file or return FAILED
@@ -197,12 +203,12 @@ fn files_have_same_device_and_inode_numbers(first: &str, second: &str) -> bool {
 }
 
 /// Obtains the device and inode numbers of the file specified
-fn get_dev_and_inode(filename: &str) -> Option<(u64, u64)> {
+fn get_dev_and_inode(filename: &Path) -> Option<(u64, u64)> {
     fs::metadata(filename).map(|file| (file.dev(), file.ino())).ok()
 }
 
 /// Exits SUCCESS if the first file is newer than the second file.
-fn file_is_newer_than(first: &str, second: &str) -> bool {
+fn file_is_newer_than(first: &Path, second: &Path) -> bool {
     // Obtain the modified file time of the first file or return FAILED
     get_modified_file_time(first).map_or(false, |left| {
         // Obtain the modified file time of the second file or return
@@ -217,7 +223,7 @@ fn file_is_newer_than(first: &str, second: &str) -> bool {
 }
 
 /// Obtain the time the file was last modified as a `SystemTime` type.
-fn get_modified_file_time(filename: &str) -> Option<SystemTime> {
+fn get_modified_file_time(filename: &Path) -> Option<SystemTime> {
     fs::metadata(filename).ok().and_then(|file| file.modified().ok())
 }
 
@@ -241,26 +247,28 @@ fn parse_integers(left: &str, right: &str) -> Result<(Option<isize>, Option<isiz
 
 /// Matches flag arguments to their respective functionaity when the `-`
 /// character is detected.
-fn match_flag_argument(flag: char, argument: &str) -> bool {
+fn match_flag_argument(flag: char, argument: &str, cwd: &Path) -> bool {
+    let path = cwd.join(argument);
+    let file = path.as_path();
     // TODO: Implement missing flags
     match flag {
-        'b' => file_is_block_device(argument),
-        'c' => file_is_character_device(argument),
-        'd' => file_is_directory(argument),
-        'e' => file_exists(argument),
-        'f' => file_is_regular(argument),
-        //'g' => file_is_set_group_id(argument),
-        // 'G' => file_is_owned_by_effective_group_id(argument),
-        'h' | 'L' => file_is_symlink(argument),
-        //'k' => file_has_sticky_bit(argument),
-        // 'O' => file_is_owned_by_effective_user_id(argument),
-        // 'p' => file_is_named_pipe(argument),
-        'r' => file_has_read_permission(argument),
-        's' => file_size_is_greater_than_zero(argument),
-        'S' => file_is_socket(argument),
-        //'t' => file_descriptor_is_opened_on_a_terminal(argument),
-        'w' => file_has_write_permission(argument),
-        'x' => file_has_execute_permission(argument),
+        'b' => file_is_block_device(file),
+        'c' => file_is_character_device(file),
+        'd' => file_is_directory(file),
+        'e' => file_exists(file),
+        'f' => file_is_regular(file),
+        //'g' => file_is_set_group_id(file),
+        // 'G' => file_is_owned_by_effective_group_id(file),
+        'h' | 'L' => file_is_symlink(file),
+        //'k' => file_has_sticky_bit(file),
+        // 'O' => file_is_owned_by_effective_user_id(file),
+        // 'p' => file_is_named_pipe(file),
+        'r' => file_has_read_permission(file),
+        's' => file_size_is_greater_than_zero(file),
+        'S' => file_is_socket(file),
+        //'t' => file_descriptor_is_opened_on_a_terminal(file),
+        'w' => file_has_write_permission(file),
+        'x' => file_has_execute_permission(file),
         'n' => !argument.is_empty(),
         'z' => argument.is_empty(),
         _ => true,
@@ -268,7 +276,7 @@ fn match_flag_argument(flag: char, argument: &str) -> bool {
 }
 
 /// Exits SUCCESS if the file size is greather than zero.
-fn file_size_is_greater_than_zero(filepath: &str) -> bool {
+fn file_size_is_greater_than_zero(filepath: &Path) -> bool {
     fs::metadata(filepath).ok().map_or(false, |metadata| metadata.len() > 0)
 }
 
@@ -276,7 +284,7 @@ fn file_size_is_greater_than_zero(filepath: &str) -> bool {
 /// Rust currently does not have a higher level abstraction for obtaining non-standard file modes.
 /// To extract the permissions from the mode, the bitwise AND operator will be used and compared
 /// with the respective read bits.
-fn file_has_read_permission(filepath: &str) -> bool {
+fn file_has_read_permission(filepath: &Path) -> bool {
     const USER: u32 = 0b1_0000_0000;
     const GROUP: u32 = 0b10_0000;
     const GUEST: u32 = 0b100;
@@ -293,7 +301,7 @@ fn file_has_read_permission(filepath: &str) -> bool {
 /// Rust currently does not have a higher level abstraction for obtaining non-standard file modes.
 /// To extract the permissions from the mode, the bitwise AND operator will be used and compared
 /// with the respective write bits.
-fn file_has_write_permission(filepath: &str) -> bool {
+fn file_has_write_permission(filepath: &Path) -> bool {
     const USER: u32 = 0b1000_0000;
     const GROUP: u32 = 0b10000;
     const GUEST: u32 = 0b10;
@@ -312,7 +320,7 @@ fn file_has_write_permission(filepath: &str) -> bool {
 /// with the respective execute bits.
 /// Note: This function is 1:1 the same as `src/builtins/exists.rs:file_has_execute_permission`
 /// If you change the following function, please also update the one in src/builtins/exists.rs
-fn file_has_execute_permission(filepath: &str) -> bool {
+fn file_has_execute_permission(filepath: &Path) -> bool {
     const USER: u32 = 0b100_0000;
     const GROUP: u32 = 0b1000;
     const GUEST: u32 = 0b1;
@@ -326,41 +334,41 @@ fn file_has_execute_permission(filepath: &str) -> bool {
 }
 
 /// Exits SUCCESS if the file argument is a socket
-fn file_is_socket(filepath: &str) -> bool {
+fn file_is_socket(filepath: &Path) -> bool {
     fs::metadata(filepath).ok().map_or(false, |metadata| metadata.file_type().is_socket())
 }
 
 /// Exits SUCCESS if the file argument is a block device
-fn file_is_block_device(filepath: &str) -> bool {
+fn file_is_block_device(filepath: &Path) -> bool {
     fs::metadata(filepath).ok().map_or(false, |metadata| metadata.file_type().is_block_device())
 }
 
 /// Exits SUCCESS if the file argument is a character device
-fn file_is_character_device(filepath: &str) -> bool {
+fn file_is_character_device(filepath: &Path) -> bool {
     fs::metadata(filepath).ok().map_or(false, |metadata| metadata.file_type().is_char_device())
 }
 
 /// Exits SUCCESS if the file exists
-fn file_exists(filepath: &str) -> bool { Path::new(filepath).exists() }
+fn file_exists(filepath: &Path) -> bool { filepath.exists() }
 
 /// Exits SUCCESS if the file is a regular file
-fn file_is_regular(filepath: &str) -> bool {
+fn file_is_regular(filepath: &Path) -> bool {
     fs::metadata(filepath).ok().map_or(false, |metadata| metadata.file_type().is_file())
 }
 
 /// Exits SUCCESS if the file is a directory
-fn file_is_directory(filepath: &str) -> bool {
+fn file_is_directory(filepath: &Path) -> bool {
     fs::metadata(filepath).ok().map_or(false, |metadata| metadata.file_type().is_dir())
 }
 
 /// Exits SUCCESS if the file is a symbolic link
-fn file_is_symlink(filepath: &str) -> bool {
+fn file_is_symlink(filepath: &Path) -> bool {
     fs::symlink_metadata(filepath).ok().map_or(false, |metadata| metadata.file_type().is_symlink())
 }
 
 #[test]
 fn test_empty_str() {
-    let eval = |args: Vec<types::Str>| evaluate_arguments(&args);
+    let eval = |args: Vec<types::Str>| evaluate_arguments(&args, Path::new("."));
     assert_eq!(eval(vec!["".into()]), Ok(false));
     assert_eq!(eval(vec!["c".into(), "=".into(), "".into()]), Ok(false));
 }
@@ -369,79 +377,79 @@ fn test_empty_str() {
 fn test_integers_arguments() {
     fn vec_string(args: &[&str]) -> Vec<types::Str> { args.iter().map(|s| (*s).into()).collect() }
     // Equal To
-    assert_eq!(evaluate_arguments(&vec_string(&["10", "-eq", "10"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["10", "-eq", "5"])), Ok(false));
-    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-eq", "-10"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-eq", "10"])), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["10", "-eq", "10"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["10", "-eq", "5"]), Path::new(".")), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-eq", "-10"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-eq", "10"]), Path::new(".")), Ok(false));
 
     // Greater Than or Equal To
-    assert_eq!(evaluate_arguments(&vec_string(&["10", "-ge", "10"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["10", "-ge", "5"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["5", "-ge", "10"])), Ok(false));
-    assert_eq!(evaluate_arguments(&vec_string(&["-9", "-ge", "-10"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-ge", "-10"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-ge", "10"])), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["10", "-ge", "10"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["10", "-ge", "5"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["5", "-ge", "10"]), Path::new(".")), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["-9", "-ge", "-10"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-ge", "-10"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-ge", "10"]), Path::new(".")), Ok(false));
 
     // Less Than or Equal To
-    assert_eq!(evaluate_arguments(&vec_string(&["5", "-le", "5"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["5", "-le", "10"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["10", "-le", "5"])), Ok(false));
-    assert_eq!(evaluate_arguments(&vec_string(&["-11", "-le", "-10"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-le", "-10"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["10", "-le", "-10"])), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["5", "-le", "5"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["5", "-le", "10"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["10", "-le", "5"]), Path::new(".")), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["-11", "-le", "-10"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-le", "-10"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["10", "-le", "-10"]), Path::new(".")), Ok(false));
 
     // Less Than
-    assert_eq!(evaluate_arguments(&vec_string(&["5", "-lt", "10"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["10", "-lt", "5"])), Ok(false));
-    assert_eq!(evaluate_arguments(&vec_string(&["-11", "-lt", "-10"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["10", "-lt", "-10"])), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["5", "-lt", "10"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["10", "-lt", "5"]), Path::new(".")), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["-11", "-lt", "-10"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["10", "-lt", "-10"]), Path::new(".")), Ok(false));
 
     // Greater Than
-    assert_eq!(evaluate_arguments(&vec_string(&["10", "-gt", "5"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["5", "-gt", "10"])), Ok(false));
-    assert_eq!(evaluate_arguments(&vec_string(&["-9", "-gt", "-10"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-gt", "10"])), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["10", "-gt", "5"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["5", "-gt", "10"]), Path::new(".")), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["-9", "-gt", "-10"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-gt", "10"]), Path::new(".")), Ok(false));
 
     // Not Equal To
-    assert_eq!(evaluate_arguments(&vec_string(&["10", "-ne", "5"])), Ok(true));
-    assert_eq!(evaluate_arguments(&vec_string(&["5", "-ne", "5"])), Ok(false));
-    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-ne", "-10"])), Ok(false));
-    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-ne", "10"])), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["10", "-ne", "5"]), Path::new(".")), Ok(true));
+    assert_eq!(evaluate_arguments(&vec_string(&["5", "-ne", "5"]), Path::new(".")), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-ne", "-10"]), Path::new(".")), Ok(false));
+    assert_eq!(evaluate_arguments(&vec_string(&["-10", "-ne", "10"]), Path::new(".")), Ok(true));
 }
 
 #[test]
 fn test_file_exists() {
-    assert_eq!(file_exists("testing/empty_file"), true);
-    assert_eq!(file_exists("this-does-not-exist"), false);
+    assert_eq!(file_exists(Path::new("testing/empty_file")), true);
+    assert_eq!(file_exists(Path::new("this-does-not-exist")), false);
 }
 
 #[test]
 fn test_file_is_regular() {
-    assert_eq!(file_is_regular("testing/empty_file"), true);
-    assert_eq!(file_is_regular("testing"), false);
+    assert_eq!(file_is_regular(Path::new("testing/empty_file")), true);
+    assert_eq!(file_is_regular(Path::new("testing")), false);
 }
 
 #[test]
 fn test_file_is_directory() {
-    assert_eq!(file_is_directory("testing"), true);
-    assert_eq!(file_is_directory("testing/empty_file"), false);
+    assert_eq!(file_is_directory(Path::new("testing")), true);
+    assert_eq!(file_is_directory(Path::new("testing/empty_file")), false);
 }
 
 #[test]
 #[cfg(not(target_os = "redox"))]
 fn test_file_is_symlink() {
-    assert_eq!(file_is_symlink("testing/symlink"), true);
-    assert_eq!(file_is_symlink("testing/empty_file"), false);
+    assert_eq!(file_is_symlink(Path::new("testing/symlink")), true);
+    assert_eq!(file_is_symlink(Path::new("testing/empty_file")), false);
 }
 
 #[test]
 fn test_file_has_execute_permission() {
-    assert_eq!(file_has_execute_permission("testing/executable_file"), true);
-    assert_eq!(file_has_execute_permission("testing/empty_file"), false);
+    assert_eq!(file_has_execute_permission(Path::new("testing/executable_file")), true);
+    assert_eq!(file_has_execute_permission(Path::new("testing/empty_file")), false);
 }
 
 #[test]
 fn test_file_size_is_greater_than_zero() {
-    assert_eq!(file_size_is_greater_than_zero("testing/file_with_text"), true);
-    assert_eq!(file_size_is_greater_than_zero("testing/empty_file"), false);
+    assert_eq!(file_size_is_greater_than_zero(Path::new("testing/file_with_text")), true);
+    assert_eq!(file_size_is_greater_than_zero(Path::new("testing/empty_file")), false);
 }
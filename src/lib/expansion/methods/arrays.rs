@@ -7,6 +7,10 @@ use crate::types::{self, Args};
 use std::char;
 use unicode_segmentation::UnicodeSegmentation;
 
+/// The names of the methods returning an array, such as `@split(string)`
+pub const METHODS: &[&str] =
+    &["bytes", "chars", "graphemes", "keys", "lines", "reverse", "split", "split_at", "values"];
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct ArrayMethod<'a> {
     method:    &'a str,
@@ -300,4 +304,16 @@ mod test {
         let method = ArrayMethod::new("reverse", "@ARRAY", Pattern::StringPattern("3"), None);
         assert_eq!(method.handle_as_array(&mut DummyExpander).unwrap(), args!["c", "b", "a"]);
     }
+
+    #[test]
+    fn listed_methods_exist() {
+        for &method in METHODS {
+            let method = ArrayMethod::new(method, "$FOO", Pattern::Whitespace, None);
+            if let Err(Error::MethodError(MethodError::InvalidArrayMethod(method))) =
+                method.handle_as_array(&mut DummyExpander)
+            {
+                panic!("{} is not an array method", method)
+            }
+        }
+    }
 }
@@ -2,7 +2,10 @@ mod arrays;
 mod strings;
 
 use self::strings::unescape;
-pub use self::{arrays::ArrayMethod, strings::StringMethod};
+pub use self::{
+    arrays::{ArrayMethod, METHODS as ARRAY_METHODS},
+    strings::{StringMethod, METHODS as STRING_METHODS},
+};
 
 use super::Expander;
 use crate::{parser::lexers::ArgumentSplitter, types};
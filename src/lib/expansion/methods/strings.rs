@@ -76,6 +76,31 @@ fn escape(input: &str) -> String {
     output
 }
 
+/// The names of the methods returning a string, such as `$basename(path)`
+pub const METHODS: &[&str] = &[
+    "basename",
+    "escape",
+    "extension",
+    "filename",
+    "find",
+    "join",
+    "len",
+    "len_bytes",
+    "or",
+    "parent",
+    "regex_replace",
+    "repeat",
+    "replace",
+    "replacen",
+    "reverse",
+    "to_lowercase",
+    "to_uppercase",
+    "trim",
+    "trim_end",
+    "trim_start",
+    "unescape",
+];
+
 /// Represents a method that operates on and returns a string
 #[derive(Debug, PartialEq, Clone)]
 pub struct StringMethod<'a> {
@@ -811,4 +836,17 @@ mod test {
         method.handle(&mut output, &mut DummyExpander).unwrap();
         assert_eq!(&*output, "FOOBAR");
     }
+
+    #[test]
+    fn listed_methods_exist() {
+        for &method in METHODS {
+            let mut output = types::Str::new();
+            let method = StringMethod { method, variable: "$FOO", pattern: "", selection: None };
+            if let Err(Error::MethodError(MethodError::InvalidScalarMethod(method))) =
+                method.handle(&mut output, &mut DummyExpander)
+            {
+                panic!("{} is not a string method", method)
+            }
+        }
+    }
 }
@@ -9,7 +9,7 @@ mod words;
 use self::braces::BraceToken;
 pub use self::{
     loops::ForValueExpression,
-    methods::MethodError,
+    methods::{MethodError, ARRAY_METHODS, STRING_METHODS},
     words::{unescape, Select, SelectWithSize, WordIterator, WordToken},
 };
 use crate::{
@@ -19,11 +19,12 @@ use crate::{
 };
 use auto_enums::auto_enum;
 use err_derive::Error;
-use glob::glob;
+use glob::{glob, Pattern};
 use itertools::Itertools;
 use std::{
     error,
     fmt::{self, Write},
+    path::Path,
     str,
 };
 use unicode_segmentation::UnicodeSegmentation;
@@ -59,9 +60,15 @@ pub enum Error<T: fmt::Debug + error::Error + fmt::Display + 'static> {
     /// The environment variable is not set
     #[error(display = "environment variable '{}' is not set", _0)]
     UnknownEnv(String),
+    /// The field of the `git::` namespace does not exist
+    #[error(display = "'{}' is not a field of the git namespace", _0)]
+    UnknownGitField(String),
     /// Variable is not defined
     #[error(display = "Variable does not exist")]
     VarNotFound,
+    /// An unset variable was referenced while the `no_unset` option is enabled
+    #[error(display = "variable '{}' is not set", _0)]
+    UnsetVariable(String),
 
     /// Failed to fetch the user home directory
     #[error(display = "Could not fetch the user home directory")]
@@ -136,6 +143,8 @@ pub trait Expander: Sized {
     fn map_keys(&self, _name: &str) -> Result<Args, Self::Error>;
     /// Iterating upon key-value maps.
     fn map_values(&self, _name: &str) -> Result<Args, Self::Error>;
+    /// The directory relative globs are matched in, if not the working directory of the process.
+    fn current_dir(&self) -> Option<&Path> { None }
     /// Get a string that exists in the shell.
     fn get_string(&mut self, value: &str) -> Result<types::Str, Self::Error> {
         Ok(self.expand_string(value)?.join(" ").into())
@@ -486,11 +495,27 @@ trait ExpanderInternal: Expander {
         let expanded: types::Str = if tilde { self.tilde(&concat)? } else { concat };
 
         if do_glob {
-            match glob(&expanded) {
+            // Relative globs are matched in the working directory of the expander, and the
+            // matches are given relative to it
+            let cwd = self.current_dir().filter(|_| !expanded.starts_with('/'));
+            let pattern = match cwd {
+                Some(cwd) => format!("{}/{}", Pattern::escape(&cwd.to_string_lossy()), expanded),
+                None => expanded.to_string(),
+            };
+            let prefix = if expanded.starts_with("./") { "./" } else { "" };
+            match glob(&pattern) {
                 Ok(var) => {
                     let prev_size = expanded_words.len();
-                    expanded_words
-                        .extend(var.filter_map(|path| path.ok()?.to_str().map(Into::into)));
+                    expanded_words.extend(var.filter_map(|path| {
+                        let path = path.ok()?;
+                        match cwd {
+                            Some(cwd) => {
+                                let path = path.strip_prefix(cwd).ok()?.to_str()?;
+                                Some(format!("{}{}", prefix, path).into())
+                            }
+                            None => path.to_str().map(Into::into),
+                        }
+                    }));
                     if expanded_words.len() == prev_size {
                         expanded_words.push(expanded);
                     }
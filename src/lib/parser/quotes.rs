@@ -118,6 +118,10 @@ impl<I: Iterator<Item = u8>> Terminator<I> {
         }
     }
 
+    /// Whether the statement read is complete, rather than cut by the end of the input, as in
+    /// an unterminated quote
+    pub fn is_terminated(&self) -> bool { self.terminated }
+
     fn handle_char(&mut self, character: u8, prev_whitespace: bool) -> Option<u8> {
         match character {
             b'\'' => {
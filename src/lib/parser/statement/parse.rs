@@ -121,6 +121,7 @@ pub fn parse<'a>(code: &str, builtins: &BuiltinMap<'a>) -> super::Result<'a> {
                 name:        name.into(),
                 args:        collect_arguments(args)?,
                 statements:  Vec::new(),
+                source:      types::Str::new(),
             })
         }
         _ if cmd.starts_with("time ") => {
@@ -257,6 +258,7 @@ mod tests {
             name:        "bob".into(),
             args:        Vec::default(),
             statements:  Vec::default(),
+            source:      types::Str::new(),
         };
         assert_eq!(correct_parse, parsed_if);
 
@@ -278,6 +280,7 @@ mod tests {
                 KeyBuf { name: "b".into(), kind: Primitive::Str },
             ],
             statements:  Vec::default(),
+            source:      types::Str::new(),
         };
         assert_eq!(correct_parse, parsed_if);
 
@@ -294,6 +297,7 @@ mod tests {
                 KeyBuf { name: "b".into(), kind: Primitive::Str },
             ],
             statements:  vec![],
+            source:      types::Str::new(),
         };
         assert_eq!(correct_parse, parsed_if);
         let parsed_if =
@@ -11,6 +11,7 @@ enum LogicalOp {
     None,
 }
 
+/// A statement, with the logical operator joining it to the previous one
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum StatementVariant<'a> {
     And(&'a str),
@@ -7,11 +7,10 @@ use crate::{
     builtins::Status,
     parser::lexers::assignments::{Key, Operator, Primitive},
     shell::{flow_control::Function, Value, Variables},
+    types::Shared,
 };
 use std::{
-    env,
     io::{self, BufWriter, Write},
-    rc::Rc,
     result::Result,
 };
 use types_rs::{EuclDiv, Modifications, OpError, Pow};
@@ -57,14 +56,14 @@ impl<'b> Shell<'b> {
                             // TODO: handle operators here in the same way as local
                             .and_then(|rhs| match &rhs {
                                 Value::Array(_) if operator == Operator::Equal => {
-                                    env::set_var(key.name, format!("{}", rhs));
+                                    self.variables.set_env(key.name, format!("{}", rhs));
                                     Ok(())
                                 }
                                 Value::Array(_) => Err("arithmetic operators on array \
                                                         expressions aren't supported yet."
                                     .to_string()),
                                 Value::Str(_) => {
-                                    env::set_var(&key.name, &format!("{}", rhs));
+                                    self.variables.set_env(key.name, format!("{}", rhs));
                                     Ok(())
                                 }
                                 _ => Err(format!(
@@ -83,7 +82,7 @@ impl<'b> Shell<'b> {
             }
             ExportAction::LocalExport(ref key) => match self.variables.get_str(key) {
                 Ok(var) => {
-                    env::set_var(key, &*var);
+                    self.variables.set_env(key.as_str(), var.as_str());
                     Status::SUCCESS
                 }
                 Err(_) => {
@@ -93,7 +92,7 @@ impl<'b> Shell<'b> {
             ExportAction::List => {
                 let stdout = io::stdout();
                 let mut stdout = stdout.lock();
-                for (key, val) in env::vars() {
+                for (key, val) in self.variables.env_vars() {
                     let _ = writeln!(stdout, "{} = \"{}\"", key, val);
                 }
                 Status::SUCCESS
@@ -105,7 +104,7 @@ impl<'b> Shell<'b> {
     pub(crate) fn calculate<'a>(
         &mut self,
         actions: AssignmentActions<'a>,
-    ) -> Result<Vec<(Key<'a>, Value<Rc<Function<'b>>>)>, String> {
+    ) -> Result<Vec<(Key<'a>, Value<Shared<Function<'b>>>)>, String> {
         let mut backup: Vec<_> = Vec::with_capacity(4);
         for action in actions {
             let Action(key, operator, expression) = action.map_err(|e| e.to_string())?;
@@ -187,9 +186,9 @@ impl<'b> Shell<'b> {
 // parsed
 fn apply<'a>(
     op: Operator,
-    lhs: &Value<Rc<Function<'a>>>,
-    rhs: Value<Rc<Function<'a>>>,
-) -> Result<Value<Rc<Function<'a>>>, OpError> {
+    lhs: &Value<Shared<Function<'a>>>,
+    rhs: Value<Shared<Function<'a>>>,
+) -> Result<Value<Shared<Function<'a>>>, OpError> {
     match op {
         Operator::Add => lhs + rhs,
         Operator::Divide => lhs / rhs,
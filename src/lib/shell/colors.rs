@@ -1,6 +1,6 @@
 use crate::expansion;
 use itertools::Itertools;
-use std::fmt;
+use std::{fmt, iter};
 
 #[derive(Debug)]
 struct StaticMap {
@@ -119,6 +119,15 @@ impl fmt::Display for Colors {
 }
 
 impl Colors {
+    /// The names of the colors and attributes, as used in the `c::` namespace. Colors may
+    /// also be given as 256 colors or true colors.
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        iter::once("reset")
+            .chain(ATTRIBUTES.keys.iter().copied())
+            .chain(COLORS.keys.iter().copied())
+            .chain(BG_COLORS.keys.iter().copied())
+    }
+
     /// If no matches were made, then this will attempt to parse the variable as either a
     /// 24-bit true color color, or one of 256 colors. It supports both hexadecimal and
     /// decimals.
@@ -235,6 +244,13 @@ mod test {
         assert_eq!(Some(0), hex_char_to_u8_range('0'));
     }
 
+    #[test]
+    fn listed_names_are_colors() {
+        for name in Colors::names() {
+            assert!(Colors::collect::<IonError>(name).is_ok(), "{} is not a color", name);
+        }
+    }
+
     #[test]
     fn set_multiple_color_attributes() {
         let expected =
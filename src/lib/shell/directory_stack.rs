@@ -3,8 +3,7 @@ use err_derive::Error;
 use redox_users::All;
 use std::{
     collections::VecDeque,
-    env::{self, set_current_dir},
-    io,
+    env, fs, io,
     path::{Component, Path, PathBuf},
 };
 #[cfg(not(target_os = "redox"))]
@@ -26,28 +25,27 @@ pub enum DirStackError {
     NoOtherDir,
 }
 
-fn set_current_dir_ion(dir: &Path) -> Result<(), DirStackError> {
-    set_current_dir(dir).map_err(|cause| DirStackError::DirChangeFailure {
-        cause,
-        dir: dir.to_string_lossy().into(),
-    })?;
-
-    env::set_var(
-        "OLDPWD",
-        env::var("PWD")
-            .ok()
-            .and_then(|pwd| if pwd.is_empty() { None } else { Some(pwd) })
-            .unwrap_or_else(|| "?".into()),
-    );
-
-    env::set_var("PWD", dir.to_str().unwrap_or("?"));
-    Ok(())
+/// Check that the shell can change its working directory to `dir`
+fn check_dir(dir: &Path) -> Result<(), DirStackError> {
+    let failure =
+        |cause| DirStackError::DirChangeFailure { cause, dir: dir.to_string_lossy().into() };
+    match fs::metadata(dir) {
+        Ok(ref metadata) if metadata.is_dir() => Ok(()),
+        Ok(_) => Err(failure(io::Error::new(io::ErrorKind::Other, "not a directory"))),
+        Err(cause) => Err(failure(cause)),
+    }
 }
 
+/// The directory stack of a shell, which also holds its working directory.
+///
+/// The working directory is kept by the shell rather than the process, so that several shells
+/// can run in the same process. It is applied to the commands spawned by the shell.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DirectoryStack {
     dirs:      VecDeque<PathBuf>, // The top is always the current directory
     max_depth: Option<usize>,
+    current:   PathBuf,
+    previous:  Option<PathBuf>,
 }
 
 impl Default for DirectoryStack {
@@ -96,12 +94,27 @@ impl DirectoryStack {
     }
 
     // sets current_dir to the element referred by index
-    pub fn set_current_dir_by_index(&self, index: usize) -> Result<(), DirStackError> {
-        let dir = self.dirs.get(index).ok_or_else(|| DirStackError::OutOfRange { index })?;
+    pub fn set_current_dir_by_index(&mut self, index: usize) -> Result<(), DirStackError> {
+        let dir = self.dirs.get(index).ok_or_else(|| DirStackError::OutOfRange { index })?.clone();
 
-        set_current_dir_ion(dir)
+        self.set_current_dir(dir)
     }
 
+    fn set_current_dir(&mut self, dir: PathBuf) -> Result<(), DirStackError> {
+        check_dir(&dir)?;
+        self.previous = Some(std::mem::replace(&mut self.current, dir));
+        Ok(())
+    }
+
+    /// The working directory of the shell
+    pub fn current_dir(&self) -> &Path { &self.current }
+
+    /// The directory the shell was in before the last directory change, if any
+    pub fn previous_dir(&self) -> Option<&Path> { self.previous.as_ref().map(PathBuf::as_path) }
+
+    /// Resolve a path relative to the working directory of the shell
+    pub fn resolve<P: AsRef<Path>>(&self, path: P) -> PathBuf { self.current.join(path) }
+
     pub fn dir_from_bottom(&self, num: usize) -> Option<&PathBuf> {
         self.dirs.get(self.dirs.len() - num)
     }
@@ -128,21 +141,17 @@ impl DirectoryStack {
 
     pub fn change_and_push_dir(&mut self, dir: &Path) -> Result<(), DirStackError> {
         let new_dir = self.normalize_path(dir);
-        set_current_dir_ion(&new_dir)?;
+        self.set_current_dir(new_dir.clone())?;
         self.push_dir(new_dir);
         Ok(())
     }
 
-    fn get_previous_dir(&self) -> Option<String> {
-        env::var("OLDPWD").ok().filter(|pwd| !pwd.is_empty() && pwd != "?")
-    }
-
     pub fn switch_to_previous_directory(&mut self) -> Result<(), DirStackError> {
-        let prev = self.get_previous_dir().ok_or(DirStackError::NoPreviousDir)?;
+        let prev = self.previous.clone().ok_or(DirStackError::NoPreviousDir)?;
 
         self.popd(0);
-        println!("{}", prev);
-        self.change_and_push_dir(Path::new(&prev))
+        println!("{}", prev.display());
+        self.change_and_push_dir(&prev)
     }
 
     pub fn switch_to_home_directory(&mut self) -> Result<(), DirStackError> {
@@ -190,17 +199,31 @@ impl DirectoryStack {
 
     pub fn clear(&mut self) { self.dirs.truncate(1) }
 
+    /// Replace the working directory and the stack, as when restoring a snapshot of a shell
+    pub(crate) fn restore(
+        &mut self,
+        current: PathBuf,
+        dirs: VecDeque<PathBuf>,
+    ) -> Result<(), DirStackError> {
+        self.set_current_dir(current)?;
+        self.dirs = dirs;
+        if let Some(max_depth) = self.max_depth {
+            self.dirs.truncate(max_depth);
+        }
+        Ok(())
+    }
+
     /// Create a new `DirectoryStack` containing the current working directory,
     /// if available.
     pub fn new() -> Self {
         let mut dirs: VecDeque<PathBuf> = VecDeque::new();
-        if let Ok(curr_dir) = env::current_dir() {
-            env::set_var("PWD", curr_dir.to_str().unwrap_or("?"));
-            dirs.push_front(curr_dir);
+        let current = if let Ok(curr_dir) = env::current_dir() {
+            dirs.push_front(curr_dir.clone());
+            curr_dir
         } else {
             eprintln!("ion: failed to get current directory when building directory stack");
-            env::set_var("PWD", "?");
-        }
-        Self { dirs, max_depth: None }
+            PathBuf::from("?")
+        };
+        Self { dirs, max_depth: None, current, previous: None }
     }
 }
@@ -1,5 +1,5 @@
 use super::{
-    flow_control::{Block, Case, ElseIf, Function, IfMode, Statement},
+    flow_control::{Block, Case, ElseIf, ExportAction, Function, IfMode, LocalAction, Statement},
     pipe_exec::PipelineError,
     signals, Shell,
 };
@@ -10,14 +10,14 @@ use crate::{
         pipelines::{PipeItem, Pipeline},
         Expander, ForValueExpression,
     },
-    parser::{parse_and_validate, StatementSplitter, Terminator},
+    parser::{parse_and_validate, StatementSplitter, StatementVariant, Terminator},
     shell::{IonError, Job, Value},
-    types,
+    types::{self, Shared},
 };
 use err_derive::Error;
 use itertools::Itertools;
 use nix::unistd::Pid;
-use std::{rc::Rc, time::SystemTime};
+use std::time::SystemTime;
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Condition {
@@ -346,12 +346,22 @@ impl<'a> Shell<'a> {
 
     /// Executes a single statement
     pub fn execute_statement(&mut self, statement: &Statement<'a>) -> Result {
+        self.check_limits()?;
         match statement {
             Statement::Let(action) => {
+                if let LocalAction::Assign(keys, ..) = action {
+                    self.check_assignment(keys)?;
+                }
                 self.previous_status = self.local(action);
                 self.variables.set("?", self.previous_status);
             }
             Statement::Export(action) => {
+                match action {
+                    ExportAction::Assign(keys, ..) | ExportAction::LocalExport(keys) => {
+                        self.check_assignment(keys)?
+                    }
+                    ExportAction::List => (),
+                }
                 self.previous_status = self.export(action);
                 self.variables.set("?", self.previous_status);
             }
@@ -362,6 +372,7 @@ impl<'a> Shell<'a> {
                 }
             }
             Statement::For { variables, values, statements } => {
+                self.check_variables(variables.iter().map(|var| var.as_str()))?;
                 let condition = self.execute_for(variables, values, statements)?;
                 if condition != Condition::NoOp {
                     return Ok(condition);
@@ -374,14 +385,16 @@ impl<'a> Shell<'a> {
                     return Ok(condition);
                 }
             }
-            Statement::Function { name, args, statements, description } => {
+            Statement::Function { name, args, statements, description, source } => {
+                self.check_variables(args.iter().map(|arg| arg.name.as_str()))?;
                 self.variables.set(
                     name,
-                    Value::Function(Rc::new(Function::new(
+                    Value::Function(Shared::new(Function::new(
                         description.clone(),
                         name.clone(),
                         args.to_vec(),
                         statements.to_vec(),
+                        source.clone(),
                     ))),
                 );
             }
@@ -570,7 +583,9 @@ impl<'a> Shell<'a> {
             // Go through all of the statements and build up the block stack
             // When block is done return statement for execution.
             for statement in StatementSplitter::new(&stmt) {
-                let statement = parse_and_validate(statement?, &self.builtins)?;
+                let code = statement?;
+                let mut statement = parse_and_validate(code, &self.builtins)?;
+                record_source(&mut self.flow_control, &mut statement, code);
                 if let Some(stm) = Self::insert_statement(&mut self.flow_control, statement)? {
                     self.execute_statement(&stm)?;
                 }
@@ -585,6 +600,62 @@ impl<'a> Shell<'a> {
 
         Ok(())
     }
+
+    /// Parse the source of a single function definition, as given by `Function::source`.
+    ///
+    /// Returns `None` if the source is anything else than one complete function definition.
+    pub(super) fn parse_function(
+        &self,
+        source: &str,
+    ) -> std::result::Result<Option<Statement<'a>>, IonError> {
+        let mut block = Block::new();
+        let mut function = None;
+        for stmt in source.bytes().batching(|cmd| Terminator::new(cmd).terminate()) {
+            for statement in StatementSplitter::new(&stmt) {
+                if function.is_some() {
+                    return Ok(None);
+                }
+                let code = statement?;
+                let mut statement = parse_and_validate(code, &self.builtins)?;
+                record_source(&mut block, &mut statement, code);
+                function = Self::insert_statement(&mut block, statement)?;
+            }
+        }
+
+        match function {
+            Some(Statement::Function { .. }) if block.is_empty() => Ok(function),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Append the code of a statement to the source of the functions being defined
+fn record_source<'a>(
+    block: &mut Block<'a>,
+    statement: &mut Statement<'a>,
+    code: StatementVariant<'_>,
+) {
+    let functions = block.iter_mut().chain(std::iter::once(statement));
+    for function in functions {
+        if let Statement::Function { ref mut source, .. } = function {
+            match code {
+                StatementVariant::Default(code) => {
+                    if !source.is_empty() {
+                        source.push('\n');
+                    }
+                    source.push_str(code);
+                }
+                StatementVariant::And(code) => {
+                    source.push_str(" && ");
+                    source.push_str(code);
+                }
+                StatementVariant::Or(code) => {
+                    source.push_str(" || ");
+                    source.push_str(code);
+                }
+            }
+        }
+    }
 }
 
 /// Expand a pipeline containing aliases. As aliases can split the pipeline by having logical
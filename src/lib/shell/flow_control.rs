@@ -125,6 +125,8 @@ pub enum Statement<'a> {
         args:        Vec<KeyBuf>,
         /// The statements in the function
         statements:  Block<'a>,
+        /// The source code of the definition, from `fn` to `end`
+        source:      types::Str,
     },
     /// for loop
     For {
@@ -231,6 +233,7 @@ pub struct Function<'a> {
     name:        types::Str,
     args:        Vec<KeyBuf>,
     statements:  Block<'a>,
+    source:      types::Str,
 }
 
 /// Error during function execution
@@ -271,6 +274,7 @@ impl<'a> Function<'a> {
             })
             .collect::<Result<SmallVec<[_; 8]>, _>>()?;
 
+        shell.enter_function()?;
         let index = shell
             .variables
             .index_scope_for_var(&self.name)
@@ -289,19 +293,24 @@ impl<'a> Function<'a> {
 
         shell.variables.pop_scope();
         shell.variables.append_scopes(temporary);
+        shell.leave_function();
         res.map(|_| ())
     }
 
     /// Get the function's description
     pub fn description(&self) -> Option<&types::Str> { self.description.as_ref() }
 
+    /// Get the source code of the function definition, or an empty string if it is unknown
+    pub fn source(&self) -> &str { &self.source }
+
     /// Create a new function
     pub const fn new(
         description: Option<types::Str>,
         name: types::Str,
         args: Vec<KeyBuf>,
         statements: Vec<Statement<'a>>,
+        source: types::Str,
     ) -> Self {
-        Self { description, name, args, statements }
+        Self { description, name, args, statements, source }
     }
 }
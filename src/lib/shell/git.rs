@@ -0,0 +1,986 @@
+use inflate::InflateStream;
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    convert::TryFrom,
+    ffi::OsStr,
+    fs::{self, File},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileExt, MetadataExt},
+    },
+    path::{Path, PathBuf},
+    slice,
+};
+
+/// The fields of the `git::` namespace
+pub const FIELDS: &[&str] = &["ahead", "behind", "branch", "dirty", "root", "stash_count"];
+
+/// The size of an entry of the index without its path
+const ENTRY_SIZE: usize = 62;
+
+/// The largest object decoded. The commits and trees read are far smaller, so that larger sizes
+/// are taken as corrupt data rather than reserved.
+const MAX_OBJECT_SIZE: usize = 64 << 20;
+
+/// The longest chain of deltas followed, which is the longest git writes
+const MAX_DELTA_DEPTH: usize = 4095;
+
+/// The identifier of an object, the SHA-1 digest of its content
+type Oid = [u8; 20];
+
+/// The status of the repository of the working directory, read from its `.git` directory without
+/// running git. The fields are read as they are expanded, and kept until the cache is cleared.
+#[derive(Debug, Default)]
+pub struct GitCache(RefCell<Option<Cached>>);
+
+impl GitCache {
+    /// The value of a field of the `git::` namespace for the repository of the directory. The
+    /// fields are empty outside of a repository.
+    pub fn get(&self, dir: &Path, field: &str) -> Option<String> {
+        if !FIELDS.contains(&field) {
+            return None;
+        }
+        let mut cache = self.0.borrow_mut();
+        if cache.as_ref().map_or(true, |cached| cached.dir != dir) {
+            let repository = Repository::find(dir);
+            *cache = Some(Cached { dir: dir.into(), repository, fields: HashMap::new() });
+        }
+        cache.as_mut().map(|cached| cached.get(field))
+    }
+
+    /// Forget the fields read, for the repository may have changed
+    pub fn clear(&self) { self.0.replace(None); }
+}
+
+#[derive(Debug)]
+struct Cached {
+    dir:        PathBuf,
+    repository: Option<Repository>,
+    fields:     HashMap<&'static str, String>,
+}
+
+impl Cached {
+    fn get(&mut self, field: &str) -> String {
+        if let Some(value) = self.fields.get(field) {
+            return value.clone();
+        }
+        let repository = match self.repository {
+            Some(ref repository) => repository,
+            None => return String::new(),
+        };
+        let fields = match field {
+            "root" => vec![("root", repository.root.to_string_lossy().into_owned())],
+            "branch" => vec![("branch", repository.branch().unwrap_or_default())],
+            "dirty" => {
+                let dirty = repository.is_dirty().map(|dirty| if dirty { "1" } else { "0" });
+                vec![("dirty", dirty.unwrap_or_default().into())]
+            }
+            // Both are counted at once
+            "ahead" | "behind" => match repository.ahead_behind() {
+                Some((ahead, behind)) => {
+                    vec![("ahead", ahead.to_string()), ("behind", behind.to_string())]
+                }
+                None => vec![("ahead", String::new()), ("behind", String::new())],
+            },
+            _ => vec![("stash_count", repository.stash_count().to_string())],
+        };
+        self.fields.extend(fields);
+        self.fields.get(field).cloned().unwrap_or_default()
+    }
+}
+
+/// A repository, found from a directory of its work tree
+#[derive(Debug)]
+struct Repository {
+    /// The top directory of the work tree
+    root:       PathBuf,
+    /// The directory of the `HEAD` and the index of the work tree
+    git_dir:    PathBuf,
+    /// The directory of the objects, references and configuration, shared by the work trees
+    common_dir: PathBuf,
+    packs:      Vec<Pack>,
+}
+
+impl Repository {
+    fn find(dir: &Path) -> Option<Self> {
+        let (root, git_dir) = dir.ancestors().find_map(|root| {
+            let dot_git = root.join(".git");
+            if dot_git.is_dir() {
+                return Some((root, dot_git));
+            }
+            // The work trees and submodules link to their git directory
+            let link = fs::read_to_string(&dot_git).ok()?;
+            if link.starts_with("gitdir:") {
+                Some((root, root.join(link["gitdir:".len()..].trim())))
+            } else {
+                None
+            }
+        })?;
+        let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+            Ok(common_dir) => git_dir.join(common_dir.trim()),
+            Err(_) => git_dir.clone(),
+        };
+        let packs = fs::read_dir(common_dir.join("objects/pack"))
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension() == Some(OsStr::new("idx")))
+                    .filter_map(|path| {
+                        let index = File::open(&path).ok()?;
+                        let pack = File::open(path.with_extension("pack")).ok()?;
+                        Some(Pack { index, pack })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(Repository { root: root.into(), git_dir, common_dir, packs })
+    }
+
+    /// The content of a file of the work tree's git directory, or else of the common directory
+    fn read(&self, name: &str) -> Option<String> {
+        fs::read_to_string(self.git_dir.join(name))
+            .or_else(|_| fs::read_to_string(self.common_dir.join(name)))
+            .ok()
+    }
+
+    /// Resolve a reference, such as `HEAD` or `refs/heads/master`, to the commit it points to
+    fn resolve(&self, name: &str) -> Option<Oid> {
+        let mut name = name.to_string();
+        // Follow the symbolic references a few times at most
+        for _ in 0..5 {
+            match self.read(&name) {
+                Some(ref content) if content.starts_with("ref:") => {
+                    name = content["ref:".len()..].trim().into()
+                }
+                Some(content) => return parse_oid(content.trim()),
+                None => return self.packed_ref(&name),
+            }
+        }
+        None
+    }
+
+    fn packed_ref(&self, name: &str) -> Option<Oid> {
+        let refs = fs::read_to_string(self.common_dir.join("packed-refs")).ok()?;
+        refs.lines().find_map(|line| {
+            let mut fields = line.splitn(2, ' ');
+            let oid = fields.next()?;
+            if fields.next()? == name {
+                parse_oid(oid)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The branch checked out, or the abbreviated commit if the head is detached
+    fn branch(&self) -> Option<String> {
+        let head = fs::read_to_string(self.git_dir.join("HEAD")).ok()?;
+        let head = head.trim();
+        Some(if head.starts_with("ref:") {
+            let name = head["ref:".len()..].trim();
+            name.trim_start_matches("refs/heads/").into()
+        } else {
+            head.chars().take(7).collect()
+        })
+    }
+
+    /// The reference of the upstream of the branch, as set in the configuration
+    fn upstream(&self, branch: &str) -> Option<String> {
+        let config = fs::read_to_string(self.common_dir.join("config")).ok()?;
+        let section = format!("[branch \"{}\"]", branch);
+        let (mut remote, mut merge, mut inside) = (None, None, false);
+        for line in config.lines().map(str::trim) {
+            if line.starts_with('[') {
+                inside = line == section;
+            } else if inside {
+                let mut pair = line.splitn(2, '=').map(str::trim);
+                match (pair.next(), pair.next()) {
+                    (Some("remote"), Some(value)) => remote = Some(value),
+                    (Some("merge"), Some(value)) => merge = Some(value),
+                    _ => (),
+                }
+            }
+        }
+        let merge = merge?;
+        match remote? {
+            "." => Some(merge.into()),
+            remote => {
+                Some(format!("refs/remotes/{}/{}", remote, merge.trim_start_matches("refs/heads/")))
+            }
+        }
+    }
+
+    fn stash_count(&self) -> usize {
+        fs::read_to_string(self.common_dir.join("logs/refs/stash"))
+            .map_or(0, |log| log.lines().count())
+    }
+
+    fn object(&self, oid: &Oid) -> Option<Vec<u8>> { self.object_at_depth(oid, 0) }
+
+    /// Read the content of an object, from the loose objects or the packs. The depth counts the
+    /// deltas followed to reach it.
+    fn object_at_depth(&self, oid: &Oid, depth: usize) -> Option<Vec<u8>> {
+        let hex = to_hex(oid);
+        let path = self.common_dir.join("objects").join(&hex[..2]).join(&hex[2..]);
+        match File::open(path) {
+            Ok(file) => {
+                let mut stream = Inflater::new(&file, 0);
+                // The object starts with its type and size, ended by a null byte
+                let header = loop {
+                    if let Some(end) = stream.output.iter().position(|&byte| byte == 0) {
+                        break end;
+                    } else if stream.output.len() > 32 {
+                        return None;
+                    }
+                    stream.fill(stream.output.len() + 1)?;
+                };
+                let size = std::str::from_utf8(&stream.output[..header])
+                    .ok()?
+                    .splitn(2, ' ')
+                    .nth(1)?
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|&size| size <= MAX_OBJECT_SIZE)?;
+                stream.fill(header + 1 + size)?;
+                stream.output.get(header + 1..header + 1 + size).map(<[u8]>::to_vec)
+            }
+            Err(_) => self.packs.iter().find_map(|pack| {
+                pack.object_at(self, pack.offset(oid)?, depth)
+            }),
+        }
+    }
+
+    fn commit(&self, oid: &Oid) -> Option<Commit> { Commit::parse(&self.object(oid)?) }
+
+    /// The files of a tree and its subtrees, with their mode and object
+    fn files(
+        &self,
+        tree: &Oid,
+        prefix: &[u8],
+        files: &mut HashMap<Vec<u8>, (u32, Oid)>,
+    ) -> Option<()> {
+        let data = self.object(tree)?;
+        let mut rest = data.as_slice();
+        // Each entry is the mode and the name, separated by a space and ended by a null byte,
+        // followed by the object
+        while !rest.is_empty() {
+            let end = rest.iter().position(|&byte| byte == 0)?;
+            let (header, tail) = rest.split_at(end);
+            let mut oid = [0; 20];
+            oid.copy_from_slice(tail.get(1..21)?);
+            rest = &tail[21..];
+            let space = header.iter().position(|&byte| byte == b' ')?;
+            let mode = std::str::from_utf8(&header[..space]).ok()?;
+            let mode = u32::from_str_radix(mode, 8).ok()?;
+            let path = [prefix, &header[space + 1..]].concat();
+            if mode == 0o40000 {
+                self.files(&oid, &[&path[..], b"/"].concat(), files)?;
+            } else {
+                files.insert(path, (mode, oid));
+            }
+        }
+        Some(())
+    }
+
+    /// Whether the files of the work tree or the index differ from the commit checked out.
+    /// Files not tracked are ignored.
+    fn is_dirty(&self) -> Option<bool> {
+        let index = match fs::read(self.git_dir.join("index")) {
+            Ok(index) => Index::parse(&index)?,
+            Err(_) => Index::default(),
+        };
+        // Without any commit, every file staged is a change
+        let tree = match self.resolve("HEAD") {
+            Some(head) => Some(self.commit(&head)?.tree),
+            None => None,
+        };
+
+        // The index caches the tree it matches, which is the tree of the commit without any
+        // change staged. Otherwise the files staged are compared with the tree.
+        if index.tree.is_none() || index.tree != tree {
+            let mut files = HashMap::new();
+            if let Some(tree) = tree {
+                self.files(&tree, b"", &mut files)?;
+            }
+            if files.len() != index.entries.len()
+                || index.entries.iter().any(|entry| {
+                    entry.stage != 0 || files.get(&entry.path) != Some(&(entry.mode, entry.oid))
+                })
+            {
+                return Some(true);
+            }
+        }
+
+        Some(index.entries.iter().any(|entry| entry.is_modified(&self.root)))
+    }
+
+    /// The number of commits ahead and behind the upstream of the branch
+    fn ahead_behind(&self) -> Option<(usize, usize)> {
+        const LOCAL: u8 = 1;
+        const UPSTREAM: u8 = 2;
+        const BOTH: u8 = LOCAL | UPSTREAM;
+
+        let upstream = self.upstream(&self.branch()?)?;
+        let (local, upstream) = (self.resolve("HEAD")?, self.resolve(&upstream)?);
+        let mut commits = HashMap::new();
+        let mut flags = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        let mut pending = vec![(local, LOCAL), (upstream, UPSTREAM)];
+        // Walk the commits from the most recent, flagging them with the side they are reachable
+        // from, until the remaining ones are reachable from both
+        loop {
+            for (oid, flag) in pending.drain(..) {
+                let flags = flags.entry(oid).or_insert(0);
+                if *flags & flag != flag {
+                    *flags |= flag;
+                    if !commits.contains_key(&oid) {
+                        commits.insert(oid, self.commit(&oid)?);
+                    }
+                    queue.push((commits[&oid].time, oid));
+                }
+            }
+            if queue.iter().all(|(_, oid)| flags[oid] == BOTH) {
+                break;
+            }
+            let (_, oid) = queue.pop()?;
+            let flag = flags[&oid];
+            pending.extend(commits[&oid].parents.iter().map(|&parent| (parent, flag)));
+        }
+        let count = |side| flags.values().filter(|&&flag| flag == side).count();
+        Some((count(LOCAL), count(UPSTREAM)))
+    }
+}
+
+/// A pack of objects, with its index
+#[derive(Debug)]
+struct Pack {
+    index: File,
+    pack:  File,
+}
+
+/// The base an object of a pack is a delta of
+enum Base {
+    Offset(u64),
+    Object(Oid),
+}
+
+impl Pack {
+    fn read_u32(&self, offset: u64) -> Option<u32> {
+        let mut bytes = [0; 4];
+        self.index.read_exact_at(&mut bytes, offset).ok()?;
+        Some(u32::from_be_bytes(bytes))
+    }
+
+    /// The offset of an object in the pack, from the version 2 index
+    fn offset(&self, oid: &Oid) -> Option<u64> {
+        let mut header = [0; 8];
+        self.index.read_exact_at(&mut header, 0).ok()?;
+        if header != *b"\xfftOc\0\0\0\x02" {
+            return None;
+        }
+        // The fan-out table gives the number of objects whose first byte is lower or equal
+        let first = u64::from(oid[0]);
+        let mut low = if first == 0 { 0 } else { self.read_u32(8 + 4 * (first - 1))? };
+        let mut high = self.read_u32(8 + 4 * first)?;
+        let count = u64::from(self.read_u32(8 + 4 * 255)?);
+        let mut id = [0; 20];
+        while low < high {
+            let middle = low + (high - low) / 2;
+            self.index.read_exact_at(&mut id, 8 + 1024 + 20 * u64::from(middle)).ok()?;
+            match id.cmp(oid) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => {
+                    // The offsets follow the objects and their checksums
+                    let offsets = 8 + 1024 + 24 * count;
+                    let offset = self.read_u32(offsets + 4 * u64::from(middle))?;
+                    if offset & 0x8000_0000 == 0 {
+                        return Some(u64::from(offset));
+                    }
+                    // The large offsets are in a table of 64-bit offsets
+                    let large = offsets + 4 * count + 8 * u64::from(offset & 0x7fff_ffff);
+                    let mut bytes = [0; 8];
+                    self.index.read_exact_at(&mut bytes, large).ok()?;
+                    return Some(u64::from_be_bytes(bytes));
+                }
+            }
+        }
+        None
+    }
+
+    /// Read the object at an offset of the pack, applying its deltas
+    fn object_at(&self, repository: &Repository, offset: u64, depth: usize) -> Option<Vec<u8>> {
+        if depth > MAX_DELTA_DEPTH {
+            return None;
+        }
+        let mut header = [0; 32];
+        let length = self.pack.read_at(&mut header, offset).ok()?;
+        let mut bytes = header[..length].iter();
+        // The type and the size of the object
+        let mut byte = *bytes.next()?;
+        let kind = (byte >> 4) & 7;
+        let mut size = u64::from(byte & 0x0f);
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = *bytes.next()?;
+            size |= u64::from(byte & 0x7f).checked_shl(shift)?;
+            shift += 7;
+        }
+        let size = usize::try_from(size).ok().filter(|&size| size <= MAX_OBJECT_SIZE)?;
+        let base = match kind {
+            // The base precedes the delta
+            6 => {
+                let distance = varint(&mut bytes).filter(|&distance| distance > 0)?;
+                Some(Base::Offset(offset.checked_sub(distance)?))
+            }
+            7 => {
+                let mut oid = [0; 20];
+                for byte in &mut oid {
+                    *byte = *bytes.next()?;
+                }
+                Some(Base::Object(oid))
+            }
+            _ => None,
+        };
+        let mut stream = Inflater::new(&self.pack, offset + (length - bytes.len()) as u64);
+        stream.fill(size)?;
+        let data = stream.output;
+        match base {
+            Some(Base::Offset(base)) => {
+                apply_delta(&self.object_at(repository, base, depth + 1)?, &data)
+            }
+            Some(Base::Object(base)) => {
+                apply_delta(&repository.object_at_depth(&base, depth + 1)?, &data)
+            }
+            None => Some(data),
+        }
+    }
+}
+
+/// The data inflated from the zlib stream at an offset of a file
+struct Inflater<'a> {
+    file:   &'a File,
+    offset: u64,
+    stream: InflateStream,
+    output: Vec<u8>,
+}
+
+impl<'a> Inflater<'a> {
+    fn new(file: &'a File, offset: u64) -> Self {
+        Inflater { file, offset, stream: InflateStream::from_zlib(), output: Vec::new() }
+    }
+
+    /// Inflate the stream until the given size at least is reached, failing if it ends before
+    fn fill(&mut self, size: usize) -> Option<()> {
+        self.output.reserve(size.saturating_sub(self.output.len()));
+        let mut buffer = [0; 4096];
+        while self.output.len() < size {
+            let length = self.file.read_at(&mut buffer, self.offset).ok()?;
+            let mut input = &buffer[..length];
+            if input.is_empty() {
+                return None;
+            }
+            while !input.is_empty() && self.output.len() < size {
+                let (read, data) = self.stream.update(input).ok()?;
+                if read == 0 && data.is_empty() {
+                    return None;
+                }
+                self.output.extend_from_slice(data);
+                input = &input[read..];
+                self.offset += read as u64;
+            }
+        }
+        Some(())
+    }
+}
+
+/// A commit, with the time it was committed
+#[derive(Debug)]
+struct Commit {
+    tree:    Oid,
+    parents: Vec<Oid>,
+    time:    i64,
+}
+
+impl Commit {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let mut commit = Commit { tree: [0; 20], parents: Vec::new(), time: 0 };
+        // The headers end with an empty line, followed by the message
+        for line in String::from_utf8_lossy(data).lines().take_while(|line| !line.is_empty()) {
+            if line.starts_with("tree ") {
+                commit.tree = parse_oid(&line["tree ".len()..])?;
+            } else if line.starts_with("parent ") {
+                commit.parents.push(parse_oid(&line["parent ".len()..])?);
+            } else if line.starts_with("committer ") {
+                // The committer is followed by the time and the time zone
+                commit.time = line.rsplitn(3, ' ').nth(1)?.parse().ok()?;
+            }
+        }
+        Some(commit)
+    }
+}
+
+/// The files staged in the index
+#[derive(Debug, Default)]
+struct Index {
+    entries: Vec<Entry>,
+    /// The tree matching the whole index, if it is cached
+    tree:    Option<Oid>,
+}
+
+/// A file of the index, with the metadata it had when it was staged
+#[derive(Debug)]
+struct Entry {
+    path:          Vec<u8>,
+    mode:          u32,
+    oid:           Oid,
+    mtime:         (u32, u32),
+    size:          u32,
+    stage:         u16,
+    /// Whether the file is assumed unchanged or is not checked out
+    skip_worktree: bool,
+}
+
+impl Index {
+    /// Parse the versions 2 to 4 of the index, and the tree it caches
+    fn parse(data: &[u8]) -> Option<Self> {
+        let u32_at = |offset: usize| {
+            data.get(offset..offset + 4)
+                .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        };
+        if !data.starts_with(b"DIRC") {
+            return None;
+        }
+        let version = u32_at(4)?;
+        let count = u32_at(8)? as usize;
+        // The count is not trusted to reserve more entries than the data can hold
+        let mut entries = Vec::with_capacity(count.min(data.len() / ENTRY_SIZE));
+        let mut offset = 12;
+        let mut previous: Vec<u8> = Vec::new();
+        for _ in 0..count {
+            let start = offset;
+            let mut oid = [0; 20];
+            oid.copy_from_slice(data.get(start + 40..start + 60)?);
+            let flags = u16::from_be_bytes([*data.get(start + 60)?, *data.get(start + 61)?]);
+            offset += ENTRY_SIZE;
+            let mut skip_worktree = flags & 0x8000 != 0;
+            if version >= 3 && flags & 0x4000 != 0 {
+                skip_worktree |= data.get(offset)? & 0x40 != 0;
+                offset += 2;
+            }
+            let path = if version >= 4 {
+                // The paths are compressed: the end of the previous path is replaced
+                let mut bytes = data.get(offset..)?.iter();
+                let strip = varint(&mut bytes)?;
+                offset = data.len() - bytes.len();
+                let end = offset + data.get(offset..)?.iter().position(|&byte| byte == 0)?;
+                let kept = previous.len().checked_sub(usize::try_from(strip).ok()?)?;
+                let path = [&previous[..kept], &data[offset..end]].concat();
+                offset = end + 1;
+                path
+            } else {
+                let end = offset + data.get(offset..)?.iter().position(|&byte| byte == 0)?;
+                let path = data[offset..end].to_vec();
+                // The entries are padded with null bytes to a multiple of 8 bytes
+                offset = start + ((end - start + 8) & !7);
+                path
+            };
+            previous = path.clone();
+            entries.push(Entry {
+                path,
+                mode: u32_at(start + 24)?,
+                oid,
+                mtime: (u32_at(start + 8)?, u32_at(start + 12)?),
+                size: u32_at(start + 36)?,
+                stage: (flags >> 12) & 3,
+                skip_worktree,
+            });
+        }
+
+        // The extensions follow the entries, before the checksum of the index
+        let mut tree = None;
+        while offset + 8 <= data.len().saturating_sub(20) {
+            let size = u32_at(offset + 4)? as usize;
+            let extension = data.get(offset + 8..)?.get(..size)?;
+            // The cached trees start with the root: its path, its number of entries, or -1 if it
+            // is not valid, and its number of subtrees, followed by the tree
+            if data[offset..].starts_with(b"TREE") && extension.first() == Some(&0) {
+                let end = extension.iter().position(|&byte| byte == b'\n')?;
+                let counts = String::from_utf8_lossy(&extension[1..end]);
+                if counts.split(' ').next()?.parse::<i64>().ok()? >= 0 {
+                    let mut oid = [0; 20];
+                    oid.copy_from_slice(extension.get(end + 1..end + 21)?);
+                    tree = Some(oid);
+                }
+            }
+            offset += 8 + size;
+        }
+        Some(Index { entries, tree })
+    }
+}
+
+impl Entry {
+    /// Whether the file of the work tree differs from the one staged, by its type, size or time
+    /// of modification. Git smudges the entries of the files changed in the second the index was
+    /// written, so that they differ too.
+    fn is_modified(&self, root: &Path) -> bool {
+        // Submodules are ignored
+        if self.skip_worktree || self.mode == 0o160000 {
+            return false;
+        }
+        let metadata = match fs::symlink_metadata(root.join(OsStr::from_bytes(&self.path))) {
+            Ok(metadata) => metadata,
+            Err(_) => return true,
+        };
+        let mode = if metadata.file_type().is_symlink() {
+            0o120000
+        } else if metadata.is_dir() {
+            0o040000
+        } else if metadata.mode() & 0o111 != 0 {
+            0o100755
+        } else {
+            0o100644
+        };
+        // The nanoseconds are not recorded by every build of git
+        let (seconds, nanoseconds) = self.mtime;
+        mode != self.mode
+            || metadata.len() as u32 != self.size
+            || metadata.mtime() as u32 != seconds
+            || (nanoseconds != 0 && metadata.mtime_nsec() as u32 != nanoseconds)
+    }
+}
+
+/// Read the variable-length integers of the offsets of deltas and of the paths of the index,
+/// failing if they overflow
+fn varint(bytes: &mut slice::Iter<u8>) -> Option<u64> {
+    let mut byte = *bytes.next()?;
+    let mut value = u64::from(byte & 0x7f);
+    while byte & 0x80 != 0 {
+        byte = *bytes.next()?;
+        value = value.checked_add(1)?.checked_mul(0x80)? | u64::from(byte & 0x7f);
+    }
+    Some(value)
+}
+
+/// Read the variable-length integers of the sizes in deltas, failing if they overflow
+fn size_varint(bytes: &mut slice::Iter<u8>) -> Option<usize> {
+    let (mut value, mut shift) = (0, 0);
+    loop {
+        let byte = *bytes.next()?;
+        value |= usize::from(byte & 0x7f).checked_shl(shift)?;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+}
+
+/// Rebuild an object from its base and a delta of instructions copying parts of the base or
+/// inserting new data
+fn apply_delta(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = delta.iter();
+    if size_varint(&mut bytes)? != base.len() {
+        return None;
+    }
+    let size = size_varint(&mut bytes).filter(|&size| size <= MAX_OBJECT_SIZE)?;
+    let mut output = Vec::with_capacity(size);
+    while let Some(&instruction) = bytes.next() {
+        if instruction & 0x80 != 0 {
+            // The flags give the bytes of the offset and the size of the part copied
+            let (mut start, mut length) = (0, 0);
+            for bit in 0..4 {
+                if instruction & (1 << bit) != 0 {
+                    start |= usize::from(*bytes.next()?) << (8 * bit);
+                }
+            }
+            for bit in 0..3 {
+                if instruction & (0x10 << bit) != 0 {
+                    length |= usize::from(*bytes.next()?) << (8 * bit);
+                }
+            }
+            if length == 0 {
+                length = 0x10000;
+            }
+            output.extend_from_slice(base.get(start..start.checked_add(length)?)?);
+        } else if instruction != 0 {
+            let data = bytes.as_slice();
+            let length = usize::from(instruction);
+            output.extend_from_slice(data.get(..length)?);
+            bytes = data[length..].iter();
+        } else {
+            return None;
+        }
+        if output.len() > size {
+            return None;
+        }
+    }
+    if output.len() == size {
+        Some(output)
+    } else {
+        None
+    }
+}
+
+fn parse_oid(hex: &str) -> Option<Oid> {
+    if hex.len() != 40 {
+        return None;
+    }
+    let mut oid = [0; 20];
+    for (index, byte) in oid.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(2 * index..2 * index + 2)?, 16).ok()?;
+    }
+    Some(oid)
+}
+
+fn to_hex(oid: &Oid) -> String { oid.iter().map(|byte| format!("{:02x}", byte)).collect() }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt, process::Command};
+
+    /// Run git in the directory, with a configuration of its own
+    fn git(dir: &Path, args: &[&str]) -> String { String::from_utf8(output(dir, args)).unwrap() }
+
+    fn output(dir: &Path, args: &[&str]) -> Vec<u8> {
+        let output = Command::new("git")
+            .args(&["-c", "user.name=ion", "-c", "user.email=ion@localhost"])
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("HOME", dir)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?}: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output.stdout
+    }
+
+    /// Write a file of the work tree and commit it
+    fn commit(dir: &Path, path: &str, content: &str) {
+        fs::write(dir.join(path), content).unwrap();
+        git(dir, &["add", path]);
+        git(dir, &["commit", "-q", "-m", path]);
+    }
+
+    fn paths(repository: &Repository) -> Vec<String> {
+        let index = Index::parse(&fs::read(repository.git_dir.join("index")).unwrap()).unwrap();
+        index.entries.iter().map(|entry| String::from_utf8_lossy(&entry.path).into()).collect()
+    }
+
+    #[test]
+    fn read_repositories() {
+        let temp = mktemp::Temp::new_dir().unwrap();
+        let root = temp.as_path().join("repository");
+        fs::create_dir_all(root.join("src/shell")).unwrap();
+        fs::write(root.join("README"), "ion\n").unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(root.join("src/shell/mod.rs"), "mod flow;\n").unwrap();
+        fs::write(root.join("run.sh"), "cargo run\n").unwrap();
+        fs::set_permissions(root.join("run.sh"), Permissions::from_mode(0o755)).unwrap();
+        git(&root, &["init", "-q"]);
+        git(&root, &["checkout", "-q", "-b", "topic"]);
+        git(&root, &["add", "-A"]);
+        git(&root, &["commit", "-q", "-m", "Add the sources"]);
+
+        let repository = Repository::find(&root.join("src/shell")).unwrap();
+        assert_eq!(repository.root, root);
+        assert_eq!(repository.branch(), Some("topic".into()));
+        assert_eq!(repository.stash_count(), 0);
+        assert_eq!(repository.is_dirty(), Some(false));
+        let files = ["README", "run.sh", "src/main.rs", "src/shell/mod.rs"];
+        assert_eq!(paths(&repository), files);
+        let index = Index::parse(&fs::read(root.join(".git/index")).unwrap()).unwrap();
+        let modes: Vec<_> = index.entries.iter().map(|entry| entry.mode).collect();
+        assert_eq!(modes, [0o100644, 0o100755, 0o100644, 0o100644]);
+
+        // The paths of the version 4 are compressed
+        git(&root, &["update-index", "--index-version", "4"]);
+        assert_eq!(paths(&repository), files);
+        assert_eq!(repository.is_dirty(), Some(false));
+
+        // The files staged differ from the commit checked out
+        fs::write(root.join("src/shell/flow.rs"), "").unwrap();
+        git(&root, &["add", "src/shell/flow.rs"]);
+        assert_eq!(repository.is_dirty(), Some(true));
+        git(&root, &["rm", "-q", "--cached", "src/shell/flow.rs"]);
+        assert_eq!(repository.is_dirty(), Some(false));
+
+        fs::write(root.join("src/main.rs"), "fn main() { println!(\"ion\") }\n").unwrap();
+        assert_eq!(repository.is_dirty(), Some(true));
+        git(&root, &["add", "src/main.rs"]);
+        assert_eq!(repository.is_dirty(), Some(true));
+        git(&root, &["stash", "-q"]);
+        assert_eq!(repository.stash_count(), 1);
+        assert_eq!(repository.is_dirty(), Some(false));
+        fs::remove_file(root.join("README")).unwrap();
+        assert_eq!(repository.is_dirty(), Some(true));
+
+        // The files not checked out are flagged in the version 3
+        git(&root, &["update-index", "--index-version", "3"]);
+        git(&root, &["update-index", "--skip-worktree", "README"]);
+        assert_eq!(paths(&repository), files);
+        assert_eq!(repository.is_dirty(), Some(false));
+
+        git(&root, &["checkout", "-q", "--detach"]);
+        let commit = git(&root, &["rev-parse", "--short=7", "HEAD"]);
+        assert_eq!(repository.branch(), Some(commit.trim().into()));
+
+        // A work tree shares the stashes of its repository
+        let tree = temp.as_path().join("tree");
+        git(&root, &["worktree", "add", "-q", "-b", "feature", tree.to_str().unwrap()]);
+        let repository = Repository::find(&tree).unwrap();
+        assert_eq!(repository.root, tree);
+        assert_eq!(repository.branch(), Some("feature".into()));
+        assert_eq!(repository.stash_count(), 1);
+        assert_eq!(repository.is_dirty(), Some(false));
+    }
+
+    #[test]
+    fn cache_fields() {
+        let temp = mktemp::Temp::new_dir().unwrap();
+        let root = temp.as_path();
+        git(root, &["init", "-q"]);
+        git(root, &["checkout", "-q", "-b", "main"]);
+
+        let cache = GitCache::default();
+        assert_eq!(cache.get(root, "branch"), Some("main".into()));
+        assert_eq!(cache.get(root, "dirty"), Some("0".into()));
+        assert_eq!(cache.get(root, "stash_count"), Some("0".into()));
+        // There is no upstream
+        assert_eq!(cache.get(root, "ahead"), Some("".into()));
+        assert_eq!(cache.get(root, "behind"), Some("".into()));
+        assert_eq!(cache.get(root, "unknown"), None);
+        git(root, &["checkout", "-q", "-b", "other"]);
+        assert_eq!(cache.get(root, "branch"), Some("main".into()));
+        cache.clear();
+        assert_eq!(cache.get(root, "branch"), Some("other".into()));
+        assert_eq!(cache.get(&std::env::temp_dir(), "branch"), Some("".into()));
+    }
+
+    #[test]
+    fn count_ahead_and_behind() {
+        let temp = mktemp::Temp::new_dir().unwrap();
+        let origin = temp.as_path().join("origin");
+        fs::create_dir(&origin).unwrap();
+        git(&origin, &["init", "-q"]);
+        git(&origin, &["checkout", "-q", "-b", "main"]);
+        commit(&origin, "README", "ion\n");
+        git(temp.as_path(), &["clone", "-q", "origin", "clone"]);
+        let root = temp.as_path().join("clone");
+
+        let cache = GitCache::default();
+        let counts = || {
+            cache.clear();
+            (cache.get(&root, "ahead").unwrap(), cache.get(&root, "behind").unwrap())
+        };
+        assert_eq!(counts(), ("0".into(), "0".into()));
+        commit(&root, "README", "ion shell\n");
+        commit(&root, "LICENSE", "MIT\n");
+        assert_eq!(counts(), ("2".into(), "0".into()));
+        commit(&origin, "TODO", "\n");
+        git(&root, &["fetch", "-q"]);
+        assert_eq!(counts(), ("2".into(), "1".into()));
+
+        // The references and objects are packed
+        git(&root, &["gc", "-q"]);
+        assert!(root.join(".git/packed-refs").exists());
+        assert_eq!(counts(), ("2".into(), "1".into()));
+        assert_eq!(cache.get(&root, "dirty"), Some("0".into()));
+        fs::write(root.join("LICENSE"), "MIT or Apache-2.0\n").unwrap();
+        git(&root, &["add", "LICENSE"]);
+        cache.clear();
+        assert_eq!(cache.get(&root, "dirty"), Some("1".into()));
+        git(&root, &["commit", "-q", "-m", "Change the license"]);
+
+        // The upstream may be a local branch
+        git(&root, &["checkout", "-q", "-b", "topic", "--track", "main"]);
+        assert_eq!(counts(), ("0".into(), "0".into()));
+        commit(&root, "TODO", "Nothing\n");
+        assert_eq!(counts(), ("1".into(), "0".into()));
+        git(&root, &["checkout", "-q", "-b", "alone"]);
+        assert_eq!(counts(), ("".into(), "".into()));
+    }
+
+    #[test]
+    fn decode_objects() {
+        let temp = mktemp::Temp::new_dir().unwrap();
+        let root = temp.as_path();
+        git(root, &["init", "-q"]);
+        fs::create_dir(root.join("src")).unwrap();
+        // Similar files and trees, which are packed as deltas of each other
+        let mut lines: Vec<_> = (0..200).map(|line| format!("line {}\n", line)).collect();
+        for version in 0..10 {
+            lines[version * 20] = format!("version {}\n", version);
+            for file in 0..20 {
+                fs::write(root.join(format!("src/{}.rs", file)), lines.concat()).unwrap();
+            }
+            git(root, &["add", "-A"]);
+            commit(root, "VERSION", &version.to_string());
+        }
+
+        let check = || {
+            let repository = Repository::find(root).unwrap();
+            let objects = git(root, &["rev-list", "--objects", "--all"]);
+            for line in objects.lines() {
+                let hex = &line[..40];
+                let kind = git(root, &["cat-file", "-t", hex]);
+                let content = output(root, &["cat-file", kind.trim(), hex]);
+                assert_eq!(repository.object(&parse_oid(hex).unwrap()), Some(content));
+            }
+            assert_eq!(repository.is_dirty(), Some(false));
+        };
+        check();
+        git(root, &["repack", "-q", "-a", "-d", "-f"]);
+        let pack = fs::read_dir(root.join(".git/objects/pack"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension() == Some(OsStr::new("idx")))
+            .unwrap();
+        let pack = git(root, &["verify-pack", "-v", pack.to_str().unwrap()]);
+        assert!(pack.contains("chain length = 1"));
+        check();
+        // The bases of the deltas are given by their identifier rather than their offset
+        git(root, &["-c", "repack.useDeltaBaseOffset=false", "repack", "-q", "-a", "-d", "-f"]);
+        check();
+    }
+
+    #[test]
+    fn reject_corrupt_indexes() {
+        // An index claiming more entries than it holds
+        let mut index = b"DIRC\0\0\0\x02\xff\xff\xff\xff".to_vec();
+        index.extend_from_slice(&[0; 100]);
+        assert!(Index::parse(&index).is_none());
+        assert!(Index::parse(b"DIRC\0\0").is_none());
+
+        assert_eq!(varint(&mut [0x81, 0x00].iter()), Some(0x100));
+        let mut overflowing = [0xff; 10];
+        overflowing[9] = 0x7f;
+        assert_eq!(varint(&mut overflowing.iter()), None);
+        assert_eq!(varint(&mut [0x80].iter()), None);
+    }
+
+    #[test]
+    fn reject_corrupt_deltas() {
+        // A copy of the base followed by an insertion
+        assert_eq!(apply_delta(b"ion", b"\x03\x05\x90\x03\x02sh"), Some(b"ionsh".to_vec()));
+        // The size of the base differs
+        assert_eq!(apply_delta(b"io", b"\x03\x05\x90\x03\x02sh"), None);
+        // The size of the object is larger than any decoded
+        assert_eq!(apply_delta(b"", b"\x00\xff\xff\xff\xff\x0f"), None);
+        // The copies go beyond the base, or beyond the size of the object
+        assert_eq!(apply_delta(b"ion", b"\x03\x03\x90\x04"), None);
+        assert_eq!(apply_delta(b"ion", b"\x03\x03\x90\x03\x90\x03"), None);
+        assert_eq!(apply_delta(b"ion", b"\x03\x03\x00"), None);
+
+        assert_eq!(size_varint(&mut [0x80, 0x01].iter()), Some(0x80));
+        assert_eq!(size_varint(&mut [0xff; 12].iter()), None);
+    }
+}
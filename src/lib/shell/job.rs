@@ -1,10 +1,10 @@
-use super::{IonError, Shell};
+use super::{InProcessCommand, IonError, Shell};
 use crate::{
     builtins::BuiltinFunction,
     expansion::{self, pipelines::RedirectFrom, Expander},
     types, Value,
 };
-use std::{fmt, fs::File, str};
+use std::{fmt, fs::File, rc::Rc, str};
 
 #[derive(Clone)]
 pub struct Job<'a> {
@@ -72,12 +72,12 @@ fn expand_arg(arg: &str, shell: &mut Shell<'_>) -> expansion::Result<types::Args
 /// This represents a job that has been processed and expanded to be run
 /// as part of some pipeline
 pub struct RefinedJob<'a> {
-    pub stdin:       Option<File>,
-    pub stdout:      Option<File>,
-    pub stderr:      Option<File>,
-    pub args:        types::Args,
-    pub var:         Variant<'a>,
-    pub redirection: RedirectFrom,
+    pub(crate) stdin:       Option<File>,
+    pub(crate) stdout:      Option<File>,
+    pub(crate) stderr:      Option<File>,
+    pub(crate) args:        types::Args,
+    pub(crate) var:         Variant<'a>,
+    pub(crate) redirection: RedirectFrom,
 }
 
 pub enum Variant<'a> {
@@ -87,6 +87,8 @@ pub enum Variant<'a> {
     Builtin { main: BuiltinFunction<'a> },
     /// Functions can act as commands too!
     Function,
+    /// A command provided by the command resolver
+    InProcess { command: Rc<dyn InProcessCommand> },
     /// Represents redirection into stdin from more than one source
     Cat { sources: Vec<File> },
     Tee {
@@ -160,11 +162,40 @@ impl TeeItem {
 }
 
 impl<'a> RefinedJob<'a> {
+    /// The name of the command to run (its first arg)
     pub fn command(&self) -> &types::Str { &self.args[0] }
 
+    /// The expanded arguments of the command, including the command name
     pub const fn args(&self) -> &types::Args { &self.args }
 
-    pub fn stderr(&mut self, file: File) {
+    /// Whether the command is an external program
+    pub fn is_external(&self) -> bool {
+        if let Variant::External = self.var {
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the command is a builtin
+    pub fn is_builtin(&self) -> bool {
+        if let Variant::Builtin { .. } = self.var {
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the command is an Ion function
+    pub fn is_function(&self) -> bool {
+        if let Variant::Function = self.var {
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn stderr(&mut self, file: File) {
         if let Variant::Cat { .. } = self.var {
             return;
         }
@@ -172,18 +203,26 @@ impl<'a> RefinedJob<'a> {
         self.stderr = Some(file);
     }
 
-    pub fn needs_forking(&self) -> bool {
+    pub(crate) fn needs_forking(&self) -> bool {
+        match self.var {
+            Variant::Function | Variant::Builtin { .. } | Variant::InProcess { .. } => false,
+            _ => true,
+        }
+    }
+
+    /// Whether the job is a command of the pipeline rather than an internal cat or tee job
+    pub(crate) fn is_stage(&self) -> bool {
         match self.var {
-            Variant::Function | Variant::Builtin { .. } => false,
+            Variant::Cat { .. } | Variant::Tee { .. } => false,
             _ => true,
         }
     }
 
-    pub fn stdout(&mut self, file: File) { self.stdout = Some(file); }
+    pub(crate) fn stdout(&mut self, file: File) { self.stdout = Some(file); }
 
-    pub fn stdin(&mut self, file: File) { self.stdin = Some(file); }
+    pub(crate) fn stdin(&mut self, file: File) { self.stdin = Some(file); }
 
-    pub fn tee(
+    pub(crate) fn tee(
         tee_out: Option<TeeItem>,
         tee_err: Option<TeeItem>,
         redirection: RedirectFrom,
@@ -198,7 +237,7 @@ impl<'a> RefinedJob<'a> {
         }
     }
 
-    pub fn cat(sources: Vec<File>, redirection: RedirectFrom) -> Self {
+    pub(crate) fn cat(sources: Vec<File>, redirection: RedirectFrom) -> Self {
         Self {
             stdin: None,
             stdout: None,
@@ -209,11 +248,11 @@ impl<'a> RefinedJob<'a> {
         }
     }
 
-    pub const fn function(args: types::Args, redirection: RedirectFrom) -> Self {
+    pub(crate) const fn function(args: types::Args, redirection: RedirectFrom) -> Self {
         Self { stdin: None, stdout: None, stderr: None, args, var: Variant::Function, redirection }
     }
 
-    pub fn builtin(
+    pub(crate) fn builtin(
         main: BuiltinFunction<'a>,
         args: types::Args,
         redirection: RedirectFrom,
@@ -228,7 +267,7 @@ impl<'a> RefinedJob<'a> {
         }
     }
 
-    pub const fn external(args: types::Args, redirection: RedirectFrom) -> Self {
+    pub(crate) const fn external(args: types::Args, redirection: RedirectFrom) -> Self {
         Self { stdin: None, stdout: None, stderr: None, args, var: Variant::External, redirection }
     }
 }
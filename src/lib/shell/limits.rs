@@ -0,0 +1,178 @@
+use super::{IonError, Shell};
+use err_derive::Error;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// A handle to abort a script running in a shell from another thread
+///
+/// The shell checks the handle between statements, so a running external command is waited for
+/// before the script is aborted. A request made while no script is running is dropped when the
+/// next one starts.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// Request the shell to stop at the next statement boundary
+    pub fn interrupt(&self) { self.0.store(true, Ordering::SeqCst); }
+
+    /// Consume a pending interrupt request
+    fn take(&self) -> bool { self.0.swap(false, Ordering::SeqCst) }
+}
+
+/// Limits on the resources a single call to `execute_command` or `execute_function` may use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Budget {
+    /// The maximum number of statements to execute
+    pub max_statements: Option<u64>,
+    /// The maximum wall time to run for
+    pub max_duration:   Option<Duration>,
+    /// The maximum depth of nested function calls
+    pub max_depth:      Option<usize>,
+}
+
+/// A limit of the budget was exceeded
+#[derive(Debug, Error, Clone, PartialEq, Eq, Hash)]
+pub enum BudgetError {
+    /// Too many statements were executed
+    #[error(display = "executed more than {} statements", _0)]
+    Statements(u64),
+    /// The script ran for too long
+    #[error(display = "ran for more than {:?}", _0)]
+    Duration(Duration),
+    /// Functions were nested too deeply
+    #[error(display = "function calls nested more than {} levels deep", _0)]
+    Depth(usize),
+}
+
+/// The resources used since the start of the outermost call
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Usage {
+    statements: u64,
+    started:    Instant,
+    depth:      usize,
+}
+
+impl Usage {
+    fn new() -> Self { Self { statements: 0, started: Instant::now(), depth: 0 } }
+}
+
+impl<'a> Shell<'a> {
+    /// Get a handle that aborts the running script at the next statement boundary. The handle
+    /// can be sent to other threads.
+    pub fn interrupt_handle(&self) -> InterruptHandle { self.interrupt.clone() }
+
+    /// Limit the resources used by each call to `execute_command` and `execute_function`
+    pub fn set_budget(&mut self, budget: Budget) { self.budget = budget; }
+
+    /// The resource limits currently in effect
+    pub const fn budget(&self) -> &Budget { &self.budget }
+
+    /// Run `func` with a fresh resource usage, unless it is nested in a call already accounted.
+    /// The interrupts requested before the outermost call are discarded.
+    pub(crate) fn with_budget<T>(&mut self, func: impl FnOnce(&mut Self) -> T) -> T {
+        if self.usage.is_some() {
+            return func(self);
+        }
+        self.interrupt.take();
+        self.usage = Some(Usage::new());
+        let result = func(self);
+        self.usage = None;
+        result
+    }
+
+    /// Account for a statement about to be executed, failing if the script was interrupted or
+    /// went over its budget
+    pub(crate) fn check_limits(&mut self) -> Result<(), IonError> {
+        if self.interrupt.take() {
+            return Err(IonError::Interrupted);
+        }
+        let (budget, usage) = match self.usage {
+            Some(ref mut usage) => (&self.budget, usage),
+            None => return Ok(()),
+        };
+
+        usage.statements += 1;
+        match (budget.max_statements, budget.max_duration) {
+            (Some(max), _) if usage.statements > max => {
+                Err(IonError::BudgetExceeded(BudgetError::Statements(max)))
+            }
+            (_, Some(max)) if usage.started.elapsed() > max => {
+                Err(IonError::BudgetExceeded(BudgetError::Duration(max)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Account for a function call, failing if functions are nested deeper than the budget allows
+    pub(crate) fn enter_function(&mut self) -> Result<(), IonError> {
+        if let Some(ref mut usage) = self.usage {
+            match self.budget.max_depth {
+                Some(max) if usage.depth >= max => {
+                    return Err(IonError::BudgetExceeded(BudgetError::Depth(max)))
+                }
+                _ => usage.depth += 1,
+            }
+        }
+        Ok(())
+    }
+
+    /// Account for the return of a function call
+    pub(crate) fn leave_function(&mut self) {
+        if let Some(ref mut usage) = self.usage {
+            usage.depth -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn interrupted_script_aborts() {
+        let mut shell = Shell::default();
+        let handle = shell.interrupt_handle();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            handle.interrupt();
+        });
+        match shell.execute_command("while true; end".as_bytes()) {
+            Err(IonError::Interrupted) => (),
+            other => panic!("expected an interruption, got {:?}", other),
+        }
+        assert!(shell.execute_command("true".as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn interrupt_between_scripts() {
+        let mut shell = Shell::default();
+        shell.interrupt_handle().interrupt();
+        assert!(shell.execute_command("true; true".as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn statement_budget() {
+        let mut shell = Shell::default();
+        shell.set_budget(Budget { max_statements: Some(10), ..Budget::default() });
+        match shell.execute_command("for i in 0..100; true; end".as_bytes()) {
+            Err(IonError::BudgetExceeded(BudgetError::Statements(10))) => (),
+            other => panic!("expected the statement budget to be exceeded, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn depth_budget() {
+        let mut shell = Shell::default();
+        shell.set_budget(Budget { max_depth: Some(8), ..Budget::default() });
+        match shell.execute_command("fn recurse; recurse; end; recurse".as_bytes()) {
+            Err(IonError::BudgetExceeded(BudgetError::Depth(8))) => (),
+            other => panic!("expected the depth budget to be exceeded, got {:?}", other),
+        }
+    }
+}
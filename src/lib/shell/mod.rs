@@ -4,28 +4,37 @@ mod directory_stack;
 mod flow;
 /// The various blocks
 pub mod flow_control;
+mod git;
 mod job;
+mod limits;
 mod pipe_exec;
 mod shell_expand;
 mod signals;
+mod snapshot;
 pub(crate) mod sys;
 /// Variables for the shell
 pub mod variables;
 
-pub(crate) use self::job::{Job, RefinedJob};
+pub(crate) use self::job::Job;
 use self::{
     directory_stack::DirectoryStack,
     flow_control::{Block, Function, FunctionError, Statement},
-    pipe_exec::foreground,
+    git::GitCache,
+    limits::Usage,
+    pipe_exec::{create_pipe, foreground},
     sys::NULL_PATH,
     variables::Variables,
 };
 pub use self::{
+    colors::Colors,
     flow::BlockError,
+    job::RefinedJob,
+    limits::{Budget, BudgetError, InterruptHandle},
     pipe_exec::{
         job_control::{BackgroundEvent, BackgroundProcess},
-        PipelineError,
+        CommandIo, CommandResolver, Decision, InProcessCommand, PipelineError, RestrictedError,
     },
+    snapshot::SnapshotError,
     variables::Value,
 };
 use crate::{
@@ -39,6 +48,7 @@ use crate::{
         lexers::{Key, Primitive},
         Error as ParseError, Terminator,
     },
+    types::Shared,
 };
 use err_derive::Error;
 use itertools::Itertools;
@@ -49,10 +59,13 @@ use nix::{
 use std::{
     convert::TryFrom,
     fs::File,
+    io::{self, Read},
     mem,
     ops::{Deref, DerefMut},
-    rc::Rc,
+    path::PathBuf,
+    process::Command,
     sync::{atomic::Ordering, Arc, Mutex},
+    thread,
     time::SystemTime,
 };
 
@@ -77,6 +90,12 @@ pub enum IonError {
     /// Could not properly expand to a pipeline
     #[error(display = "expansion error: {}", _0)]
     ExpansionError(#[error(cause)] ExpansionError<IonError>),
+    /// The execution was aborted through an interrupt handle
+    #[error(display = "execution was interrupted")]
+    Interrupted,
+    /// The execution went over its budget
+    #[error(display = "budget exceeded: {}", _0)]
+    BudgetExceeded(#[error(cause)] BudgetError),
 }
 
 impl From<ParseError> for IonError {
@@ -103,11 +122,31 @@ impl From<ExpansionError<IonError>> for IonError {
 #[derive(Debug, Clone, Hash, Default)]
 pub struct Options {
     /// Exit from the shell on the first error.
-    pub err_exit: bool,
+    pub err_exit:    bool,
     /// Do not execute any commands given to the shell.
-    pub no_exec:  bool,
+    pub no_exec:     bool,
     /// If set, denotes that this shell is running as a background job.
-    pub grab_tty: bool,
+    pub grab_tty:    bool,
+    /// Treat references to unset variables, arrays and map keys as expansion errors.
+    pub no_unset:    bool,
+    /// Use the status of the last failing command as the status of a pipeline.
+    pub pipe_fail:   bool,
+    /// Forbid commands containing '/', output redirections, `exec`, and modifications of `PATH`,
+    /// `CDPATH`, `ENV` and `SHELL`.
+    pub restricted:  bool,
+    /// The directories from which scripts may be sourced in restricted mode.
+    pub source_dirs: Vec<PathBuf>,
+}
+
+/// The captured output of a script, as returned by `Shell::capture`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    /// Everything written to the standard output
+    pub stdout: String,
+    /// Everything written to the standard error
+    pub stderr: String,
+    /// The exit status of the script
+    pub status: Status,
 }
 
 /// The shell structure is a megastructure that manages all of the state of the shell throughout
@@ -127,8 +166,8 @@ pub struct Shell<'a> {
     /// When a command is executed, the final result of that command is stored
     /// here.
     previous_status:    Status,
-    /// The job ID of the previous command sent to the background.
-    previous_job:       usize,
+    /// The job IDs of the commands sent to the background, from the least recent one
+    job_order:          Vec<usize>,
     /// Contains all the options relative to the shell
     opts:               Options,
     /// Contains information on all of the active background processes that are being managed
@@ -137,6 +176,14 @@ pub struct Shell<'a> {
     /// When the `fg` command is run, this will be used to communicate with the specified
     /// background process.
     foreground_signals: Arc<foreground::Signals>,
+    /// Set from other threads to abort the running script.
+    interrupt:          InterruptHandle,
+    /// The resource limits of each script execution.
+    budget:             Budget,
+    /// The resources used by the script being executed, if any.
+    usage:              Option<Usage>,
+    /// The status of the git repository of the working directory, for the `git::` namespace.
+    git:                GitCache,
 
     // Callbacks
     /// Custom callback for each command call
@@ -145,6 +192,10 @@ pub struct Shell<'a> {
     pre_command:      Option<PreCommandCallback<'a>>,
     /// Custom callback when a background event occurs
     background_event: Option<BackgroundEventCallback>,
+    /// Custom policy deciding whether each command may run
+    command_policy:   Option<CommandPolicy<'a>>,
+    /// Provides commands implemented in Rust, run without spawning a process
+    command_resolver: Option<BoxedCommandResolver<'a>>,
 
     // Default std pipes
     stdin:  Option<File>,
@@ -152,12 +203,37 @@ pub struct Shell<'a> {
     stderr: Option<File>,
 }
 
+/// A callback that is executed when a background event occurs
+pub type BackgroundEventCallback = Arc<dyn Fn(usize, Pid, BackgroundEvent) + Send + Sync>;
+
 /// A callback that is executed after each pipeline is run
+#[cfg(not(feature = "send"))]
 pub type OnCommandCallback<'a> = Box<dyn Fn(&Shell<'_>, std::time::Duration) + 'a>;
 /// A callback that is executed before each pipeline is run
+#[cfg(not(feature = "send"))]
 pub type PreCommandCallback<'a> = Box<dyn Fn(&Shell<'_>, &Pipeline<RefinedJob<'_>>) + 'a>;
-/// A callback that is executed when a background event occurs
-pub type BackgroundEventCallback = Arc<dyn Fn(usize, Pid, BackgroundEvent) + Send + Sync>;
+/// A policy that is consulted before running each external command, function or builtin
+#[cfg(not(feature = "send"))]
+pub type CommandPolicy<'a> = Box<dyn Fn(&RefinedJob<'_>) -> Decision + 'a>;
+/// Provides commands implemented in Rust to the shell
+#[cfg(not(feature = "send"))]
+pub type BoxedCommandResolver<'a> = Box<dyn CommandResolver + 'a>;
+
+// With the `send` feature, the callbacks must be `Send` so that the shell can be moved to
+// other threads.
+
+/// A callback that is executed after each pipeline is run
+#[cfg(feature = "send")]
+pub type OnCommandCallback<'a> = Box<dyn Fn(&Shell<'_>, std::time::Duration) + Send + 'a>;
+/// A callback that is executed before each pipeline is run
+#[cfg(feature = "send")]
+pub type PreCommandCallback<'a> = Box<dyn Fn(&Shell<'_>, &Pipeline<RefinedJob<'_>>) + Send + 'a>;
+/// A policy that is consulted before running each external command, function or builtin
+#[cfg(feature = "send")]
+pub type CommandPolicy<'a> = Box<dyn Fn(&RefinedJob<'_>) -> Decision + Send + 'a>;
+/// Provides commands implemented in Rust to the shell
+#[cfg(feature = "send")]
+pub type BoxedCommandResolver<'a> = Box<dyn CommandResolver + Send + 'a>;
 
 impl<'a> Default for Shell<'a> {
     fn default() -> Self { Self::new() }
@@ -196,24 +272,32 @@ impl<'a> Shell<'a> {
         // for this shell to manage its own process group / children / etc.
         signals::block();
 
-        Shell {
+        let mut shell = Shell {
             builtins,
             variables: Variables::default(),
             flow_control: Block::with_capacity(5),
             directory_stack: DirectoryStack::new(),
-            previous_job: !0,
+            job_order: Vec::new(),
             previous_status: Status::SUCCESS,
             opts: Options::default(),
             background: Arc::new(Mutex::new(Vec::new())),
             foreground_signals: Arc::new(foreground::Signals::new()),
+            interrupt: InterruptHandle::default(),
+            budget: Budget::default(),
+            usage: None,
+            git: GitCache::default(),
             on_command: None,
             pre_command: None,
             background_event: None,
+            command_policy: None,
+            command_resolver: None,
 
             stdin: None,
             stdout: None,
             stderr: None,
-        }
+        };
+        shell.export_dirs();
+        shell
     }
 
     /// Replace the default stdin
@@ -235,8 +319,32 @@ impl<'a> Shell<'a> {
     pub const fn dir_stack(&self) -> &DirectoryStack { &self.directory_stack }
 
     /// Mutable access to the directory stack
+    ///
+    /// The `PWD` and `OLDPWD` environment variables are updated after the next pipeline, or
+    /// immediately with `export_dirs`.
     pub fn dir_stack_mut(&mut self) -> &mut DirectoryStack { &mut self.directory_stack }
 
+    /// Export the current and previous directories of the shell as `PWD` and `OLDPWD`
+    pub fn export_dirs(&mut self) {
+        let pwd = self.directory_stack.current_dir().to_string_lossy().into_owned();
+        self.variables.set_env("PWD", pwd);
+        if let Some(oldpwd) = self.directory_stack.previous_dir() {
+            let oldpwd = oldpwd.to_string_lossy().into_owned();
+            self.variables.set_env("OLDPWD", oldpwd);
+        }
+    }
+
+    /// Create a command to spawn, running in the working directory and with the exported
+    /// environment of the shell
+    pub fn child_command<S: AsRef<std::ffi::OsStr>>(&self, program: S) -> Command {
+        let mut command = Command::new(program);
+        command
+            .current_dir(self.directory_stack.current_dir())
+            .env_clear()
+            .envs(self.variables.env_vars());
+        command
+    }
+
     /// Resets the flow control fields to their default values.
     pub fn reset_flow(&mut self) { self.flow_control.clear(); }
 
@@ -251,10 +359,10 @@ impl<'a> Shell<'a> {
     /// A method for executing a function, using `args` as the input.
     pub fn execute_function<S: AsRef<str>>(
         &mut self,
-        function: &Rc<Function<'a>>,
+        function: &Shared<Function<'a>>,
         args: &[S],
     ) -> Result<Status, IonError> {
-        function.clone().execute(self, args)?;
+        self.with_budget(|shell| function.clone().execute(shell, args))?;
         Ok(self.previous_status)
     }
 
@@ -266,13 +374,16 @@ impl<'a> Shell<'a> {
     /// not
     /// terminated, then an error will be returned.
     pub fn execute_command<T: std::io::Read>(&mut self, command: T) -> Result<Status, IonError> {
-        for cmd in command
-            .bytes()
-            .filter_map(Result::ok)
-            .batching(|bytes| Terminator::new(bytes).terminate())
-        {
-            self.on_command(&cmd, true)?;
-        }
+        self.with_budget(|shell| {
+            for cmd in command
+                .bytes()
+                .filter_map(Result::ok)
+                .batching(|bytes| Terminator::new(bytes).terminate())
+            {
+                shell.on_command(&cmd, true)?;
+            }
+            Ok(())
+        })?;
 
         if let Some(block) = self.flow_control.last().map(Statement::to_string) {
             self.previous_status = Status::from_exit_code(1);
@@ -282,11 +393,52 @@ impl<'a> Shell<'a> {
         }
     }
 
+    /// Execute the given script like `execute_command`, capturing the output of its commands
+    /// instead of writing it to the standard output and error of the process.
+    ///
+    /// The output is drained while the script runs, so commands producing large outputs can not
+    /// block on a full pipe. Note that background jobs spawned by the script keep the capture
+    /// open until they exit.
+    pub fn capture<T: std::io::Read>(&mut self, script: T) -> Result<Output, IonError> {
+        fn drain(mut reader: File) -> thread::JoinHandle<io::Result<String>> {
+            thread::spawn(move || {
+                let mut buffer = Vec::with_capacity(1024);
+                reader.read_to_end(&mut buffer)?;
+                Ok(String::from_utf8_lossy(&buffer).into_owned())
+            })
+        }
+
+        fn collect(reader: thread::JoinHandle<io::Result<String>>) -> Result<String, IonError> {
+            reader
+                .join()
+                .unwrap_or_else(|_| {
+                    Err(io::Error::new(io::ErrorKind::Other, "the capturing thread panicked"))
+                })
+                .map_err(|err| PipelineError::CaptureFailed(err).into())
+        }
+
+        let (stdout_reader, stdout_writer) = create_pipe()?;
+        let (stderr_reader, stderr_writer) = create_pipe()?;
+        let (stdout, stderr) = (drain(stdout_reader), drain(stderr_reader));
+
+        let prev_stdout = self.stdout(stdout_writer);
+        let prev_stderr = self.stderr(stderr_writer);
+        let result = self.execute_command(script);
+        // Restoring the previous pipes closes the write ends, so the readers reach the EOF
+        self.stdout(prev_stdout);
+        self.stderr(prev_stderr);
+
+        let (stdout, stderr) = (collect(stdout)?, collect(stderr)?);
+        Ok(Output { stdout, stderr, status: result? })
+    }
+
     /// Executes a pipeline and returns the final exit status of the pipeline.
     pub fn run_pipeline(&mut self, pipeline: &Pipeline<Job<'a>>) -> Result<Status, IonError> {
         let command_start_time = SystemTime::now();
 
         let mut pipeline = pipeline.expand(self)?;
+        self.apply_command_policy(&mut pipeline)?;
+        self.check_restrictions(&pipeline)?;
 
         let null_file =
             if pipeline.pipe == PipeType::Disown { File::open(NULL_PATH).ok() } else { None };
@@ -311,6 +463,11 @@ impl<'a> Shell<'a> {
         if let Some(ref callback) = self.pre_command {
             callback(self, &pipeline);
         }
+        // External commands, such as git itself, may change the repository, while the builtins
+        // run by the prompts share the status read once
+        if pipeline.items.iter().any(|item| item.job.is_external()) {
+            self.git.clear();
+        }
 
         // Don't execute commands when the `-n` flag is passed.
         let exit_status = if self.opts.no_exec {
@@ -322,15 +479,21 @@ impl<'a> Shell<'a> {
         {
             self.execute_pipeline(pipeline).map_err(Into::into)
         } else if let Some(main) = self.builtins.get(pipeline.items[0].command()) {
-            Ok(main(&pipeline.items[0].job.args, self))
+            let status = main(&pipeline.items[0].job.args, self);
+            Ok(self.pipeline_status(&[status]))
         } else if let Some(Value::Function(function)) =
             self.variables.get(&pipeline.items[0].job.args[0]).cloned()
         {
-            function.execute(self, &pipeline.items[0].job.args).map(|_| self.previous_status)
+            function
+                .execute(self, &pipeline.items[0].job.args)
+                .map(|_| self.pipeline_status(&[self.previous_status]))
         } else {
             self.execute_pipeline(pipeline).map_err(Into::into)
         }?;
 
+        // Builtins may have changed the working directory
+        self.export_dirs();
+
         if let Some(ref callback) = self.on_command {
             if let Ok(elapsed_time) = command_start_time.elapsed() {
                 callback(self, elapsed_time);
@@ -344,13 +507,25 @@ impl<'a> Shell<'a> {
         Ok(exit_status)
     }
 
-    /// Get the pid of the last executed job
+    /// Get the ID of the current job, the last one sent to the background which still exists
     pub fn previous_job(&self) -> Option<usize> {
-        if self.previous_job == !0 {
-            None
-        } else {
-            Some(self.previous_job)
-        }
+        let recent = self.recent_jobs();
+        let jobs = self.background_jobs();
+        recent.into_iter().find(|&njob| jobs[njob].exists())
+    }
+
+    /// Get the IDs of the jobs which still exist, or exited and were not reported yet, from the
+    /// last one sent to the background
+    pub fn recent_jobs(&self) -> Vec<usize> {
+        let jobs = self.background_jobs();
+        self.job_order
+            .iter()
+            .rev()
+            .cloned()
+            .filter(|&njob| {
+                jobs.get(njob).map_or(false, |job| job.exists() || job.status().is_some())
+            })
+            .collect()
     }
 
     /// Set the callback to call before each command
@@ -381,6 +556,23 @@ impl<'a> Shell<'a> {
     /// Set the callback to call on each command
     pub fn on_command_mut(&mut self) -> &mut Option<OnCommandCallback<'a>> { &mut self.on_command }
 
+    /// Set the policy deciding whether each command may run, or what to run instead
+    ///
+    /// The policy is consulted before every external command, function and builtin call. Denied
+    /// commands abort the execution with a `PipelineError::CommandDenied` error.
+    pub fn set_command_policy(&mut self, policy: Option<CommandPolicy<'a>>) {
+        self.command_policy = policy;
+    }
+
+    /// Set the resolver providing commands implemented in Rust.
+    ///
+    /// The resolver is consulted for the commands that are neither functions nor builtins,
+    /// before looking them up in the `PATH`. The commands it provides run in the shell process
+    /// when run alone, and in a forked shell inside pipelines.
+    pub fn set_command_resolver(&mut self, resolver: Option<BoxedCommandResolver<'a>>) {
+        self.command_resolver = resolver;
+    }
+
     /// Get access to the builtins
     pub const fn builtins(&self) -> &BuiltinMap<'a> { &self.builtins }
 
@@ -418,7 +610,7 @@ impl<'a> Shell<'a> {
     }
 
     /// Get a function if it exists
-    pub fn get_func<T: AsRef<str>>(&self, f: T) -> Option<Rc<Function<'a>>> {
+    pub fn get_func<T: AsRef<str>>(&self, f: T) -> Option<Shared<Function<'a>>> {
         if let Some(Value::Function(function)) = self.variables().get(f.as_ref()) {
             Some(function.clone())
         } else {
@@ -432,7 +624,7 @@ impl<'a> Shell<'a> {
     /// Get the last command's return code and/or the code for the error
     pub const fn previous_status(&self) -> Status { self.previous_status }
 
-    fn assign(&mut self, key: &Key<'_>, value: Value<Rc<Function<'a>>>) -> Result<(), String> {
+    fn assign(&mut self, key: &Key<'_>, value: Value<Shared<Function<'a>>>) -> Result<(), String> {
         match (&key.kind, &value) {
             (Primitive::Indexed(ref index_name, ref index_kind), Value::Str(_)) => {
                 let index = value_check(self, index_name, index_kind)
@@ -484,3 +676,81 @@ impl<'a> Shell<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_builtins_functions_and_commands() {
+        let mut shell = Shell::default();
+        let output = shell
+            .capture("fn greet; echo hello; end; greet; sh -c 'echo oops >&2; exit 3'".as_bytes())
+            .unwrap();
+        assert_eq!(output.stdout, "hello\n");
+        assert_eq!(output.stderr, "oops\n");
+        assert_eq!(output.status, Status::from_exit_code(3));
+    }
+
+    #[test]
+    fn capture_large_output() {
+        let mut shell = Shell::default();
+        let output = shell.capture("for i in 0..100000; echo $i; end".as_bytes()).unwrap();
+        assert_eq!(output.stdout.lines().count(), 100_000);
+        assert!(output.status.is_success());
+    }
+
+    #[cfg(feature = "send")]
+    #[test]
+    fn scripts_run_concurrently_on_threads() {
+        use crate::{builtins::BuiltinFunction, types};
+        use std::sync::Barrier;
+
+        fn assert_send<T: Send>() {}
+        assert_send::<Shell<'static>>();
+
+        // Each script waits for all the others in `meet`, which deadlocks unless they run at the
+        // same time
+        let barrier: &'static Barrier = Box::leak(Box::new(Barrier::new(4)));
+        let meet = move |_: &[types::Str], _: &mut Shell<'_>| {
+            barrier.wait();
+            Status::SUCCESS
+        };
+        let meet: BuiltinFunction<'static> = Box::leak(Box::new(meet));
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let mut shell = Shell::default();
+                shell.builtins_mut().add("meet", meet, "Wait for the other scripts");
+                shell.execute_command("fn double n; echo $((n * 2)); end".as_bytes()).unwrap();
+                thread::spawn(move || {
+                    let script = format!("meet; let result = $(double {})", i);
+                    shell.execute_command(script.as_bytes()).unwrap();
+                    shell.variables().get_str("result").unwrap().to_string()
+                })
+            })
+            .collect();
+        let results: Vec<_> = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+
+        assert_eq!(results, ["0", "2", "4", "6"]);
+    }
+
+    #[test]
+    fn working_directory_is_per_shell() {
+        let process_dir = std::env::current_dir().unwrap();
+        let mut shell = Shell::default();
+        let output = shell.capture("cd /; pwd; sh -c pwd; echo $PWD".as_bytes()).unwrap();
+        assert_eq!(output.stdout, "/\n/\n/\n");
+        assert_eq!(std::env::current_dir().unwrap(), process_dir);
+        assert_eq!(Shell::default().dir_stack().current_dir(), process_dir.as_path());
+    }
+
+    #[test]
+    fn exports_are_per_shell() {
+        let mut shell = Shell::default();
+        let output =
+            shell.capture("export ION_TEST_EXPORT=value; sh -c 'echo $ION_TEST_EXPORT'".as_bytes());
+        assert_eq!(output.unwrap().stdout, "value\n");
+        assert!(std::env::var_os("ION_TEST_EXPORT").is_none());
+        assert!(Shell::default().variables().get_env("ION_TEST_EXPORT").is_none());
+    }
+}
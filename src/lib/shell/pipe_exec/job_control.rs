@@ -20,6 +20,10 @@ use std::{
     time::Duration,
 };
 
+/// The most exit statuses of jobs kept until they are reported. The oldest one is dropped
+/// beyond, so that the IDs of the jobs do not grow in sessions never waiting for them.
+const MAX_STATUSES: usize = 32;
+
 #[derive(Clone, Copy, Hash, Debug, PartialEq)]
 /// Defines whether the background process is running or stopped.
 pub enum ProcessState {
@@ -64,11 +68,13 @@ pub struct BackgroundProcess {
     ignore_sighup: bool,
     state:         ProcessState,
     name:          String,
+    /// The exit status of the process, once it exited, until it is reported by `wait_for_any`
+    status:        Option<Status>,
 }
 
 impl BackgroundProcess {
     pub(super) const fn new(pid: Pid, state: ProcessState, name: String) -> Self {
-        Self { pid, ignore_sighup: false, state, name }
+        Self { pid, ignore_sighup: false, state, name, status: None }
     }
 
     /// Get the pid associated with the job
@@ -80,6 +86,13 @@ impl BackgroundProcess {
     /// Check if this is in fact a process
     pub fn exists(&self) -> bool { self.state != ProcessState::Empty }
 
+    /// Get the command run by the job
+    pub fn name(&self) -> &str { &self.name }
+
+    /// Get the exit status of the job, if it exited and was not reported yet. The ID of the job is
+    /// not given to another one until then, unless too many statuses are kept.
+    pub const fn status(&self) -> Option<Status> { self.status }
+
     /// Stop capturing information about the process. *This action is irreversible*
     pub fn forget(&mut self) { self.state = ProcessState::Empty }
 
@@ -109,8 +122,15 @@ impl<'a> Shell<'a> {
     }
 
     fn add_to_background(&mut self, job: BackgroundProcess) -> usize {
-        let mut processes = self.background_jobs_mut();
-        if let Some(id) = processes.iter().position(|x| !x.exists()) {
+        let mut processes = self.background.lock().expect("Could not lock the mutex");
+        // The jobs which exited are kept until their exit status is reported, or until there are
+        // too many of them
+        let kept = processes.iter().filter(|x| x.status.is_some()).count();
+        let free = processes.iter().position(|x| !x.exists() && x.status.is_none()).or_else(|| {
+            let exited = |&njob: &usize| processes.get(njob).map_or(false, |x| x.status.is_some());
+            self.job_order.iter().cloned().find(exited).filter(|_| kept >= MAX_STATUSES)
+        });
+        if let Some(id) = free {
             processes[id] = job;
             id
         } else {
@@ -152,8 +172,11 @@ impl<'a> Shell<'a> {
 
                     get_process!(|process| {
                         process.forget();
+                        // The exit status of a job brought to foreground is reported there
                         if fg_was_grabbed {
                             fg.reply_with(exit_status);
+                        } else {
+                            process.status = Some(Status::from_exit_code(exit_status));
                         }
                     });
 
@@ -174,6 +197,9 @@ impl<'a> Shell<'a> {
                     break;
                 }
                 Ok(WaitStatus::Exited(_, status)) => exit_status = status,
+                Ok(WaitStatus::Signaled(_, signal, _)) => {
+                    exit_status = Status::from_signal(signal as i32 as u8).as_os_code()
+                }
                 Ok(WaitStatus::Stopped(..)) => {
                     if !fg_was_grabbed {
                         if let Some(ref callback) = &background_event {
@@ -206,10 +232,11 @@ impl<'a> Shell<'a> {
     /// Send the current job to the background and spawn a thread to update its state
     pub fn send_to_background(&mut self, process: BackgroundProcess) {
         // Add the process to the background list, and mark the job's ID as
-        // the previous job in the shell (in case fg/bg is executed w/ no args).
+        // the current job in the shell (in case fg/bg is executed w/ no args).
         let pid = process.pid();
         let njob = self.add_to_background(process);
-        self.previous_job = njob;
+        self.job_order.retain(|&previous| previous != njob);
+        self.job_order.push(njob);
         if let Some(ref callback) = &self.background_event {
             callback(njob, pid, BackgroundEvent::Added);
         }
@@ -246,10 +273,16 @@ impl<'a> Shell<'a> {
         }
     }
 
-    /// Wait for the job in foreground
-    pub fn watch_foreground(&mut self, group: Pid) -> Result<Status, PipelineError> {
+    /// Wait for the job in foreground, returning the exit status of each of its `stages`, in
+    /// order. The job is sent to the background under `name` if it is stopped.
+    pub fn watch_foreground(
+        &mut self,
+        group: Pid,
+        stages: &[Pid],
+        name: &str,
+    ) -> Result<Vec<Status>, PipelineError> {
         let mut signaled = None;
-        let mut exit_status = Status::SUCCESS;
+        let mut statuses = vec![Status::SUCCESS; stages.len()];
 
         loop {
             match wait::waitpid(Pid::from_raw(-group.as_raw()), Some(WaitPidFlag::WUNTRACED)) {
@@ -258,13 +291,20 @@ impl<'a> Shell<'a> {
                         if let Some(signal) = signaled {
                             break Err(signal);
                         } else {
-                            break Ok(exit_status);
+                            break Ok(statuses);
                         }
                     }
                     err => break Err(PipelineError::WaitPid(err)),
                 },
-                Ok(WaitStatus::Exited(_, status)) => exit_status = Status::from_exit_code(status),
+                Ok(WaitStatus::Exited(pid, status)) => {
+                    if let Some(stage) = stages.iter().position(|&stage| stage == pid) {
+                        statuses[stage] = Status::from_exit_code(status);
+                    }
+                }
                 Ok(WaitStatus::Signaled(pid, signal, core_dumped)) => {
+                    if let Some(stage) = stages.iter().position(|&stage| stage == pid) {
+                        statuses[stage] = Status::from_signal(signal as i32 as u8);
+                    }
                     if signal == signal::Signal::SIGPIPE {
                     } else if core_dumped {
                         signaled = Some(PipelineError::CoreDump(pid));
@@ -281,7 +321,7 @@ impl<'a> Shell<'a> {
                     self.send_to_background(BackgroundProcess::new(
                         pid,
                         ProcessState::Stopped,
-                        "".to_string(),
+                        name.to_string(),
                     ));
                     break Err(PipelineError::Interrupted(pid, signal));
                 }
@@ -291,15 +331,44 @@ impl<'a> Shell<'a> {
     }
 
     /// Waits until all running background tasks have completed, and listens for signals in the
-    /// event that a signal is sent to kill the running tasks.
+    /// event that a signal is sent to kill the running tasks. The exit statuses of the jobs are
+    /// then reported.
     pub fn wait_for_background(&mut self) -> Result<(), PipelineError> {
         while { self.background_jobs().iter().any(BackgroundProcess::is_running) } {
-            if let Some(signal) = signals::SignalHandler.find(|&s| s != Signal::SIGTSTP) {
-                self.background_send(signal).map_err(PipelineError::KillFailed)?;
-                return Err(PipelineError::Interrupted(Pid::this(), signal));
+            self.forward_signals()?;
+            sleep(Duration::from_millis(100));
+        }
+        for job in self.background_jobs_mut().iter_mut() {
+            job.status = None;
+        }
+        Ok(())
+    }
+
+    /// Waits until one of the given background jobs has exited, and returns its ID with its exit
+    /// status, which is reported only once. The signals received interrupt the wait, as for
+    /// `wait_for_background`.
+    pub fn wait_for_any(&mut self, jobs: &[usize]) -> Result<(usize, Status), PipelineError> {
+        loop {
+            {
+                let mut processes = self.background_jobs_mut();
+                let exited = |&&njob: &&usize| processes.get(njob).map_or(true, |p| !p.exists());
+                if let Some(&njob) = jobs.iter().find(exited) {
+                    // A job disowned, or already reported, has no exit status
+                    let status = processes.get_mut(njob).and_then(|p| p.status.take());
+                    return Ok((njob, status.unwrap_or(Status::NO_SUCH_COMMAND)));
+                }
             }
+            self.forward_signals()?;
             sleep(Duration::from_millis(100));
         }
+    }
+
+    /// Sends the signal received, if any, to the background jobs, and returns it as an error
+    fn forward_signals(&self) -> Result<(), PipelineError> {
+        if let Some(signal) = signals::SignalHandler.find(|&s| s != Signal::SIGTSTP) {
+            self.background_send(signal).map_err(PipelineError::KillFailed)?;
+            return Err(PipelineError::Interrupted(Pid::this(), signal));
+        }
         Ok(())
     }
 
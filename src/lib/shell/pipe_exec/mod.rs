@@ -9,10 +9,19 @@ pub mod foreground;
 mod fork;
 pub mod job_control;
 mod pipes;
+mod policy;
+mod resolver;
+mod restricted;
 pub mod streams;
 
-pub use self::{job_control::BackgroundEvent, pipes::create_pipe};
-use self::{job_control::ProcessState, pipes::TeePipe};
+pub use self::{
+    job_control::BackgroundEvent,
+    pipes::create_pipe,
+    policy::Decision,
+    resolver::{CommandIo, CommandResolver, InProcessCommand},
+    restricted::RestrictedError,
+};
+use self::{job_control::ProcessState, pipes::TeePipe, resolver::run_in_process};
 use super::{
     job::{RefinedJob, TeeItem, Variant},
     signals::{self, SignalHandler},
@@ -33,7 +42,8 @@ use std::{
     fs::{File, OpenOptions},
     io::{self, Write},
     os::unix::process::CommandExt,
-    process::{exit, Command, Stdio},
+    path::Path,
+    process::{exit, Stdio},
 };
 
 #[derive(Debug, Error)]
@@ -116,12 +126,24 @@ pub enum PipelineError {
     /// pipeline after it's creation
     #[error(display = "could not kill the processes: {}", _0)]
     KillFailed(#[error(cause)] nix::Error),
+
+    /// The command policy refused to run the command
+    #[error(display = "command '{}' denied: {}", _0, _1)]
+    CommandDenied(types::Str, String),
+
+    /// The pipeline tried to perform an action forbidden in restricted mode
+    #[error(display = "restricted: {}", _0)]
+    Restricted(#[error(cause)] RestrictedError),
 }
 
 impl From<RedirectError> for PipelineError {
     fn from(cause: RedirectError) -> Self { PipelineError::RedirectPipeError(cause) }
 }
 
+impl From<RestrictedError> for PipelineError {
+    fn from(cause: RestrictedError) -> Self { PipelineError::Restricted(cause) }
+}
+
 /// Create an OS pipe and write the contents of a byte slice to one end
 /// such that reading from this pipe will produce the byte slice. Return
 /// A file descriptor representing the read end of the pipe.
@@ -144,9 +166,9 @@ pub fn stdin_of<T: AsRef<str>>(input: &T) -> Result<File, PipelineError> {
 }
 
 impl Input {
-    pub(self) fn get_infile(&self) -> Result<File, PipelineError> {
+    pub(self) fn get_infile(&self, cwd: &Path) -> Result<File, PipelineError> {
         match self {
-            Input::File(ref filename) => match File::open(filename.as_str()) {
+            Input::File(ref filename) => match File::open(cwd.join(filename.as_str())) {
                 Ok(file) => Ok(file),
                 Err(why) => Err(RedirectError::File(filename.to_string(), why).into()),
             },
@@ -182,6 +204,7 @@ fn need_tee(outs: &[Redirection], redirection: RedirectFrom) -> (bool, bool) {
 
 fn do_tee<'a>(
     outputs: &[Redirection],
+    cwd: &Path,
     job: &mut RefinedJob<'a>,
     stdout: &mut dyn FnMut(&mut RefinedJob<'a>, File),
     stderr: &mut dyn FnMut(&mut RefinedJob<'a>, File),
@@ -193,7 +216,7 @@ fn do_tee<'a>(
             .write(true)
             .append(output.append)
             .truncate(!output.append)
-            .open(output.file.as_str())
+            .open(cwd.join(output.file.as_str()))
         {
             Ok(file) => match output.from {
                 RedirectFrom::None => (),
@@ -226,9 +249,10 @@ fn do_tee<'a>(
 }
 
 /// Insert the multiple redirects as pipelines if necessary. Handle both input and output
-/// redirection if necessary.
+/// redirection if necessary, with relative paths resolved against `cwd`.
 fn prepare<'a>(
     pipeline: Pipeline<RefinedJob<'a>>,
+    cwd: &Path,
 ) -> Result<impl IntoIterator<Item = RefinedJob<'a>>, PipelineError> {
     // Real logic begins here
     let mut new_commands =
@@ -238,10 +262,10 @@ fn prepare<'a>(
         let kind = job.redirection;
         match (inputs.len(), prev_kind) {
             (0, _) => {}
-            (1, RedirectFrom::None) => job.stdin(inputs[0].get_infile()?),
+            (1, RedirectFrom::None) => job.stdin(inputs[0].get_infile(cwd)?),
             _ => {
                 new_commands.push(RefinedJob::cat(
-                    inputs.iter().map(Input::get_infile).collect::<Result<_, _>>()?,
+                    inputs.iter().map(|input| input.get_infile(cwd)).collect::<Result<_, _>>()?,
                     RedirectFrom::Stdout,
                 ));
             }
@@ -253,13 +277,21 @@ fn prepare<'a>(
             match need_tee(&outputs, kind) {
                 // No tees
                 (false, false) => {
-                    do_tee(&outputs, &mut job, &mut RefinedJob::stdout, &mut RefinedJob::stderr)?;
+                    do_tee(
+                        &outputs,
+                        cwd,
+                        &mut job,
+                        &mut RefinedJob::stdout,
+                        &mut RefinedJob::stderr,
+                    )?;
                     new_commands.push(job);
                 }
                 // tee stderr
                 (false, true) => {
                     let mut tee = TeeItem::new();
-                    do_tee(&outputs, &mut job, &mut RefinedJob::stdout, &mut |_, f| tee.add(f))?;
+                    do_tee(&outputs, cwd, &mut job, &mut RefinedJob::stdout, &mut |_, f| {
+                        tee.add(f)
+                    })?;
                     let tee = RefinedJob::tee(None, Some(tee), job.redirection);
                     job.redirection = RedirectFrom::Stderr;
                     new_commands.push(job);
@@ -268,7 +300,13 @@ fn prepare<'a>(
                 // tee stdout
                 (true, false) => {
                     let mut tee = TeeItem::new();
-                    do_tee(&outputs, &mut job, &mut |_, f| tee.add(f), &mut RefinedJob::stderr)?;
+                    do_tee(
+                        &outputs,
+                        cwd,
+                        &mut job,
+                        &mut |_, f| tee.add(f),
+                        &mut RefinedJob::stderr,
+                    )?;
                     let tee = RefinedJob::tee(Some(tee), None, job.redirection);
                     job.redirection = RedirectFrom::Stdout;
                     new_commands.push(job);
@@ -278,7 +316,7 @@ fn prepare<'a>(
                 (true, true) => {
                     let mut tee_out = TeeItem::new();
                     let mut tee_err = TeeItem::new();
-                    do_tee(&outputs, &mut job, &mut |_, f| tee_out.add(f), &mut |_, f| {
+                    do_tee(&outputs, cwd, &mut job, &mut |_, f| tee_out.add(f), &mut |_, f| {
                         tee_err.sinks.push(f)
                     })?;
                     let tee = RefinedJob::tee(Some(tee_out), Some(tee_err), job.redirection);
@@ -336,6 +374,24 @@ impl<'b> Shell<'b> {
         Status::SUCCESS
     }
 
+    /// Record the exit status of each stage of a pipeline in the `PIPESTATUS` array, and return
+    /// the status of the whole pipeline.
+    ///
+    /// This is the status of the last stage, unless the `pipe_fail` option is set, in which case
+    /// the status of the last stage that failed is used instead.
+    pub(super) fn pipeline_status(&mut self, statuses: &[Status]) -> Status {
+        self.variables.set(
+            "PIPESTATUS",
+            statuses.iter().map(|&status| Value::Str(status.into())).collect::<types::Array<_>>(),
+        );
+        let last = statuses.last().copied().unwrap_or(Status::SUCCESS);
+        if self.opts.pipe_fail {
+            statuses.iter().rev().find(|status| status.is_failure()).copied().unwrap_or(last)
+        } else {
+            last
+        }
+    }
+
     fn exec_function<S: AsRef<str>>(&mut self, name: &str, args: &[S]) -> Result<Status, IonError> {
         if let Some(Value::Function(function)) = self.variables.get(name).cloned() {
             function.execute(self, args).map(|_| self.previous_status)
@@ -356,6 +412,7 @@ impl<'b> Shell<'b> {
         let code = match job.var {
             Variant::Builtin { main } => Ok(main(job.args(), self)),
             Variant::Function => self.exec_function(job.command(), job.args()),
+            Variant::InProcess { ref command } => Ok(run_in_process(&**command, job.args())),
             _ => panic!("exec job should not be able to be called on Cat or Tee jobs"),
         };
         streams::redirect(&stdin_bk, &Some(stdout_bk), &Some(stderr_bk))?;
@@ -380,8 +437,10 @@ impl<'b> Shell<'b> {
     /// of that job over time.
     pub fn execute_pipeline(
         &mut self,
-        pipeline: Pipeline<RefinedJob<'b>>,
+        mut pipeline: Pipeline<RefinedJob<'b>>,
     ) -> Result<Status, IonError> {
+        self.resolve_commands(&mut pipeline);
+
         // While active, the SIGTTOU signal will be ignored.
         let _sig_ignore = SignalHandler::new();
 
@@ -405,7 +464,9 @@ impl<'b> Shell<'b> {
     ///
     /// This function will panic if called with an empty slice
     fn pipe(&mut self, pipeline: Pipeline<RefinedJob<'b>>) -> Result<Status, IonError> {
-        let mut commands = prepare(pipeline)?.into_iter().peekable();
+        let name = pipeline.to_string();
+        let mut commands =
+            prepare(pipeline, self.directory_stack.current_dir())?.into_iter().peekable();
 
         if let Some(mut parent) = commands.next() {
             if parent.redirection == RedirectFrom::None && !parent.needs_forking() {
@@ -414,9 +475,12 @@ impl<'b> Shell<'b> {
                 let _ = io::stdout().flush();
                 let _ = io::stderr().flush();
 
-                status
+                status.map(|status| self.pipeline_status(&[status]))
             } else {
                 let (mut pgid, mut last_pid, mut current_pid) = (None, None, Pid::this());
+                // The processes spawned for each command of the pipeline, excluding the
+                // internal cat and tee jobs used for multiple redirections.
+                let mut stages = Vec::with_capacity(4);
 
                 // Append jobs until all piped jobs are running
                 for mut child in commands {
@@ -466,7 +530,11 @@ impl<'b> Shell<'b> {
                         }
                     }
 
+                    let is_stage = parent.is_stage();
                     spawn_proc(self, parent, &mut last_pid, &mut current_pid, &mut pgid)?;
+                    if is_stage {
+                        stages.push(current_pid);
+                    }
 
                     last_pid = Some(current_pid);
                     parent = child;
@@ -475,7 +543,11 @@ impl<'b> Shell<'b> {
                     }
                 }
 
+                let is_stage = parent.is_stage();
                 spawn_proc(self, parent, &mut last_pid, &mut current_pid, &mut pgid)?;
+                if is_stage {
+                    stages.push(current_pid);
+                }
                 if self.opts.grab_tty {
                     unistd::tcsetpgrp(nix::libc::STDIN_FILENO, pgid.unwrap())
                         .map_err(PipelineError::TerminalGrabFailed)?;
@@ -483,9 +555,10 @@ impl<'b> Shell<'b> {
                 let _ = signal::killpg(pgid.unwrap(), signal::Signal::SIGCONT);
 
                 // Waits for all of the children of the assigned pgid to finish executing,
-                // returning the exit status of the last process in the queue.
+                // returning the exit status of each stage of the pipeline.
                 // Watch the foreground group, dropping all commands that exit as they exit.
-                let status = self.watch_foreground(pgid.unwrap())?;
+                let statuses = self.watch_foreground(pgid.unwrap(), &stages, &name)?;
+                let status = self.pipeline_status(&statuses);
                 if status == Status::TERMINATED {
                     signal::killpg(pgid.unwrap(), signal::Signal::SIGTERM)
                         .map_err(PipelineError::TerminateJobsError)?;
@@ -511,7 +584,7 @@ fn spawn_proc(
     let RefinedJob { mut var, mut args, stdin, stdout, stderr, redirection } = cmd;
     let pid = match var {
         Variant::External => {
-            let mut command = Command::new(&args[0].as_str());
+            let mut command = shell.child_command(args[0].as_str());
             command.args(args[1..].iter().map(types::Str::as_str));
 
             command.stdin(stdin.map_or_else(Stdio::inherit, Into::into));
@@ -544,6 +617,11 @@ fn spawn_proc(
                 .exec_function(&args[0], &args)
                 .unwrap_or_else(|why| Status::error(format!("{}", why)))
         }),
+        Variant::InProcess { ref command } => {
+            fork_exec_internal(stdout, stderr, stdin, *group, |_, _, _| {
+                run_in_process(&**command, &args)
+            })
+        }
         Variant::Cat { ref mut sources } => {
             fork_exec_internal(stdout, None, stdin, *group, |_, _, mut stdin| {
                 shell.exec_multi_in(sources, &mut stdin)
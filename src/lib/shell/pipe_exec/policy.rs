@@ -0,0 +1,91 @@
+use super::PipelineError;
+use crate::{
+    expansion::pipelines::Pipeline,
+    shell::{job::Variant, RefinedJob, Shell, Value},
+    types,
+};
+
+/// The verdict of a command policy on a command about to be run
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    /// Run the command as is
+    Allow,
+    /// Refuse to run the command, for the given reason
+    Deny(String),
+    /// Run the given arguments instead of the original command
+    Substitute(types::Args),
+}
+
+impl<'a> Shell<'a> {
+    /// Consult the command policy for each command of the pipeline, replacing the jobs that the
+    /// policy substituted
+    pub(crate) fn apply_command_policy(
+        &self,
+        pipeline: &mut Pipeline<RefinedJob<'a>>,
+    ) -> Result<(), PipelineError> {
+        let policy = match self.command_policy {
+            Some(ref policy) => policy,
+            None => return Ok(()),
+        };
+
+        for item in &mut pipeline.items {
+            match policy(&item.job) {
+                Decision::Allow => (),
+                Decision::Deny(reason) => {
+                    return Err(PipelineError::CommandDenied(item.job.command().clone(), reason))
+                }
+                Decision::Substitute(ref args) if args.is_empty() => {
+                    return Err(PipelineError::CommandDenied(
+                        item.job.command().clone(),
+                        "the command policy substituted an empty command".into(),
+                    ))
+                }
+                Decision::Substitute(args) => {
+                    // Resolve the substitute the same way the original command was
+                    item.job.var = if let Some(Value::Function(_)) = self.variables.get(&args[0]) {
+                        Variant::Function
+                    } else if let Some(main) = self.builtins.get(&args[0]) {
+                        Variant::Builtin { main }
+                    } else {
+                        Variant::External
+                    };
+                    item.job.args = args;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::Status;
+
+    #[test]
+    fn denied_command_aborts() {
+        let mut shell = Shell::default();
+        shell.set_command_policy(Some(Box::new(|job: &RefinedJob<'_>| {
+            if job.command() == "false" {
+                Decision::Deny("not allowed".into())
+            } else {
+                Decision::Allow
+            }
+        })));
+        assert!(shell.execute_command("true".as_bytes()).is_ok());
+        assert!(shell.execute_command("false".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn substituted_command_runs() {
+        let mut shell = Shell::default();
+        shell.set_command_policy(Some(Box::new(|job: &RefinedJob<'_>| {
+            if job.command() == "false" {
+                Decision::Substitute(args!["true"])
+            } else {
+                Decision::Allow
+            }
+        })));
+        assert_eq!(shell.execute_command("false".as_bytes()).unwrap(), Status::SUCCESS);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{
+    builtins::Status,
+    expansion::pipelines::Pipeline,
+    shell::{job::Variant, RefinedJob, Shell},
+    types,
+};
+use nix::{libc::STDIN_FILENO, unistd};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::unix::io::FromRawFd,
+    rc::Rc,
+};
+
+/// The standard streams of a command run in the shell's process
+pub struct CommandIo<'a> {
+    /// The input of the command
+    pub stdin:  &'a mut dyn Read,
+    /// The output of the command
+    pub stdout: &'a mut dyn Write,
+    /// The error output of the command
+    pub stderr: &'a mut dyn Write,
+}
+
+/// A command implemented in Rust, run in the shell's process instead of being spawned
+pub trait InProcessCommand {
+    /// Run the command with the given arguments (including the command name), returning its
+    /// exit status
+    fn run(&self, args: &[types::Str], io: &mut CommandIo<'_>) -> Status;
+}
+
+impl<F: Fn(&[types::Str], &mut CommandIo<'_>) -> Status> InProcessCommand for F {
+    fn run(&self, args: &[types::Str], io: &mut CommandIo<'_>) -> Status { self(args, io) }
+}
+
+/// Provides commands to the shell, consulted for the commands that are neither functions nor
+/// builtins before looking them up in the `PATH`
+pub trait CommandResolver {
+    /// Get the implementation of the given command, if it is provided by this resolver
+    fn resolve(&self, command: &str) -> Option<Rc<dyn InProcessCommand>>;
+}
+
+/// Run an in-process command on the standard streams of the process, which were redirected to
+/// the streams of the job
+pub(super) fn run_in_process(command: &dyn InProcessCommand, args: &[types::Str]) -> Status {
+    // The input is read from the descriptor directly: the buffer of `io::stdin` may hold input
+    // read before the descriptor was redirected
+    let mut file = unistd::dup(STDIN_FILENO).ok().map(|fd| unsafe { File::from_raw_fd(fd) });
+    let mut empty = io::empty();
+    let stdin: &mut dyn Read = match file {
+        Some(ref mut file) => file,
+        None => &mut empty,
+    };
+    let (stdout, stderr) = (io::stdout(), io::stderr());
+    let (mut stdout, mut stderr) = (stdout.lock(), stderr.lock());
+    let status =
+        command.run(args, &mut CommandIo { stdin, stdout: &mut stdout, stderr: &mut stderr });
+    let _ = stdout.flush();
+    let _ = stderr.flush();
+    status
+}
+
+impl<'a> Shell<'a> {
+    /// Replace the external commands of the pipeline that the command resolver provides
+    pub(super) fn resolve_commands(&self, pipeline: &mut Pipeline<RefinedJob<'a>>) {
+        let resolver = match self.command_resolver {
+            Some(ref resolver) => resolver,
+            None => return,
+        };
+
+        for item in &mut pipeline.items {
+            if item.job.is_external() {
+                if let Some(command) = resolver.resolve(item.job.command()) {
+                    item.job.var = Variant::InProcess { command };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Resolver;
+
+    impl CommandResolver for Resolver {
+        fn resolve(&self, command: &str) -> Option<Rc<dyn InProcessCommand>> {
+            match command {
+                "greet" => Some(Rc::new(|args: &[types::Str], io: &mut CommandIo<'_>| {
+                    match writeln!(io.stdout, "hello {}", args[1..].join(" ")) {
+                        Ok(_) => Status::SUCCESS,
+                        Err(why) => Status::error(format!("greet: {}", why)),
+                    }
+                })),
+                "upper" => Some(Rc::new(|_: &[types::Str], io: &mut CommandIo<'_>| {
+                    let mut input = String::new();
+                    match io
+                        .stdin
+                        .read_to_string(&mut input)
+                        .and_then(|_| io.stdout.write_all(input.to_uppercase().as_bytes()))
+                    {
+                        Ok(_) => Status::SUCCESS,
+                        Err(why) => Status::error(format!("upper: {}", why)),
+                    }
+                })),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn resolved_commands_run() {
+        let mut shell = Shell::default();
+        shell.set_command_resolver(Some(Box::new(Resolver)));
+        let output = shell.capture("greet world".as_bytes()).unwrap();
+        assert_eq!(output.stdout, "hello world\n");
+        assert!(output.status.is_success());
+    }
+
+    #[test]
+    fn resolved_commands_in_pipelines() {
+        let mut shell = Shell::default();
+        shell.set_command_resolver(Some(Box::new(Resolver)));
+        let output = shell.capture("greet world | upper | cat".as_bytes()).unwrap();
+        assert_eq!(output.stdout, "HELLO WORLD\n");
+    }
+
+    #[test]
+    fn resolved_commands_in_substitutions() {
+        let mut shell = Shell::default();
+        shell.set_command_resolver(Some(Box::new(Resolver)));
+        let output = shell.capture("echo $(greet ion)!".as_bytes()).unwrap();
+        assert_eq!(output.stdout, "hello ion!\n");
+    }
+}
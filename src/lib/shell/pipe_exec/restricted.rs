@@ -0,0 +1,110 @@
+use super::PipelineError;
+use crate::{
+    expansion::pipelines::Pipeline,
+    parser::lexers::assignments::KeyIterator,
+    shell::{RefinedJob, Shell},
+    types,
+};
+use err_derive::Error;
+
+/// Variables that can not be modified in restricted mode
+const PROTECTED_VARIABLES: &[&str] = &["PATH", "CDPATH", "ENV", "SHELL"];
+
+/// Builtins that can not be run in restricted mode
+const FORBIDDEN_BUILTINS: &[&str] = &["exec", "source-sh"];
+
+/// An action that is not permitted while the shell runs in restricted mode
+#[derive(Debug, Error)]
+pub enum RestrictedError {
+    /// Commands may only be looked up in the `PATH`
+    #[error(display = "'{}': command names can not contain '/'", _0)]
+    CommandPath(types::Str),
+    /// The builtin could be used to escape the restrictions
+    #[error(display = "'{}' is not allowed", _0)]
+    ForbiddenBuiltin(types::Str),
+    /// Output redirections are disabled
+    #[error(display = "can not redirect output to '{}'", _0)]
+    OutputRedirection(types::Str),
+    /// Tried to modify a protected variable
+    #[error(display = "can not modify '{}'", _0)]
+    ProtectedVariable(String),
+    /// The file to source is not in an allowed directory
+    #[error(display = "can not source '{}': not in an allowed directory", _0)]
+    Source(types::Str),
+}
+
+impl<'a> Shell<'a> {
+    /// Ensure the pipeline only performs actions permitted in restricted mode
+    pub(crate) fn check_restrictions(
+        &self,
+        pipeline: &Pipeline<RefinedJob<'a>>,
+    ) -> Result<(), PipelineError> {
+        if !self.opts.restricted {
+            return Ok(());
+        }
+
+        for item in &pipeline.items {
+            let command = item.job.command();
+            if command.contains('/') {
+                return Err(RestrictedError::CommandPath(command.clone()).into());
+            }
+            if FORBIDDEN_BUILTINS.contains(&command.as_str()) {
+                return Err(RestrictedError::ForbiddenBuiltin(command.clone()).into());
+            }
+            if let Some(output) = item.outputs.first() {
+                return Err(RestrictedError::OutputRedirection(output.file.clone()).into());
+            }
+
+            let args = &item.job.args()[1..];
+            match command.as_str() {
+                "source" => {
+                    if let Some(file) = args.first() {
+                        self.check_source(file)?;
+                    }
+                }
+                "drop" | "read" => self.check_variables(args.iter().map(|arg| arg.as_str()))?,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Ensure an assignment to the given keys does not modify a protected variable in
+    /// restricted mode
+    pub(crate) fn check_assignment(&self, keys: &str) -> Result<(), PipelineError> {
+        self.check_variables(KeyIterator::new(keys).filter_map(Result::ok).map(|key| key.name))
+    }
+
+    /// Ensure none of the variables to set, such as loop variables or function parameters, is
+    /// a protected variable in restricted mode
+    pub(crate) fn check_variables<'b, I: IntoIterator<Item = &'b str>>(
+        &self,
+        names: I,
+    ) -> Result<(), PipelineError> {
+        if !self.opts.restricted {
+            return Ok(());
+        }
+
+        match names.into_iter().find(|name| PROTECTED_VARIABLES.contains(name)) {
+            Some(name) => Err(RestrictedError::ProtectedVariable(name.into()).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Only files inside of the allowed source directories may be sourced
+    fn check_source(&self, file: &types::Str) -> Result<(), RestrictedError> {
+        let file_path = self.directory_stack.resolve(file.as_str());
+        let allowed = file_path.canonicalize().ok().map_or(false, |path| {
+            self.opts
+                .source_dirs
+                .iter()
+                .filter_map(|dir| dir.canonicalize().ok())
+                .any(|dir| path.starts_with(dir))
+        });
+        if allowed {
+            Ok(())
+        } else {
+            Err(RestrictedError::Source(file.clone()))
+        }
+    }
+}
@@ -2,16 +2,27 @@ use super::{
     pipe_exec::create_pipe, sys::NULL_PATH, variables::Value, IonError, PipelineError, Shell,
 };
 use crate::{
-    expansion::{Error, Expander, Result, Select},
+    expansion::{is_expression, Error, Expander, Result, Select},
     types,
 };
 use nix::unistd::{tcsetpgrp, Pid};
 #[cfg(target_os = "redox")]
 use redox_users::All;
-use std::{env, fs::File, io::Read, iter::FromIterator};
+use std::{fs::File, io::Read, iter::FromIterator, path::Path};
 #[cfg(not(target_os = "redox"))]
 use users::os::unix::UserExt;
 
+impl<'a> Shell<'a> {
+    /// The error to report when the variable `name` does not exist
+    fn unset(&self, name: &str) -> Error<IonError> {
+        if self.opts.no_unset {
+            Error::UnsetVariable(name.into())
+        } else {
+            Error::VarNotFound
+        }
+    }
+}
+
 impl<'a, 'b> Expander for Shell<'b> {
     type Error = IonError;
 
@@ -58,8 +69,17 @@ impl<'a, 'b> Expander for Shell<'b> {
     fn string(&self, name: &str) -> Result<types::Str, Self::Error> {
         if name == "?" {
             Ok(self.previous_status.into())
+        } else if name.starts_with("git::") {
+            let field = &name["git::".len()..];
+            self.git
+                .get(self.directory_stack.current_dir(), field)
+                .map(Into::into)
+                .ok_or_else(|| Error::UnknownGitField(field.into()))
         } else {
-            self.variables().get_str(name).map_err(Into::into)
+            self.variables().get_str(name).map_err(|err| match err {
+                Error::VarNotFound if !is_expression(name) => self.unset(name),
+                err => err,
+            })
         }
     }
 
@@ -116,9 +136,13 @@ impl<'a, 'b> Expander for Shell<'b> {
                     }
                     Ok(array)
                 }
-                Select::Key(key) => {
-                    Ok(args![format!("{}", hmap.get(&*key).unwrap_or(&Value::Str("".into())))])
-                }
+                Select::Key(key) => match hmap.get(&*key) {
+                    Some(value) => Ok(args![format!("{}", value)]),
+                    None if self.opts.no_unset => {
+                        Err(Error::UnsetVariable(format!("{}[{}]", name, key)))
+                    }
+                    None => Ok(args![""]),
+                },
                 Select::Index(index) => {
                     use crate::ranges::Index;
                     Ok(args![format!(
@@ -155,9 +179,13 @@ impl<'a, 'b> Expander for Shell<'b> {
                     }
                     Ok(array)
                 }
-                Select::Key(key) => {
-                    Ok(args![format!("{}", bmap.get(&*key).unwrap_or(&Value::Str("".into())))])
-                }
+                Select::Key(key) => match bmap.get(&*key) {
+                    Some(value) => Ok(args![format!("{}", value)]),
+                    None if self.opts.no_unset => {
+                        Err(Error::UnsetVariable(format!("{}[{}]", name, key)))
+                    }
+                    None => Ok(args![""]),
+                },
                 Select::Index(index) => {
                     use crate::ranges::Index;
                     Ok(args![format!(
@@ -176,7 +204,7 @@ impl<'a, 'b> Expander for Shell<'b> {
                     Err(Error::InvalidIndex(selection.clone(), "btreemap", name.into()))
                 }
             },
-            None => Err(Error::VarNotFound),
+            None => Err(self.unset(name)),
             _ => Err(Error::ScalarAsArray(name.into())),
         }
     }
@@ -190,7 +218,7 @@ impl<'a, 'b> Expander for Shell<'b> {
                 Ok(map.keys().map(|x| x.to_string().into()).collect())
             }
             Some(_) => Err(Error::NotAMap(name.into())),
-            None => Err(Error::VarNotFound),
+            None => Err(self.unset(name)),
         }
     }
 
@@ -203,10 +231,12 @@ impl<'a, 'b> Expander for Shell<'b> {
                 Ok(map.values().map(|x| x.to_string().into()).collect())
             }
             Some(_) => Err(Error::NotAMap(name.into())),
-            None => Err(Error::VarNotFound),
+            None => Err(self.unset(name)),
         }
     }
 
+    fn current_dir(&self) -> Option<&Path> { Some(self.directory_stack.current_dir()) }
+
     fn tilde(&self, input: &str) -> Result<types::Str, Self::Error> {
         // Only if the first character is a tilde character will we perform expansions
         if !input.starts_with('~') {
@@ -217,7 +247,7 @@ impl<'a, 'b> Expander for Shell<'b> {
         let (tilde_prefix, rest) = input[1..].split_at(separator.unwrap_or(input.len() - 1));
 
         match tilde_prefix {
-            "+" => Ok(env::var("PWD").unwrap_or_else(|_| "?".into()).into()),
+            "+" => Ok(self.directory_stack.current_dir().to_string_lossy().as_ref().into()),
             "-" => Ok(self.variables.get_str("OLDPWD")?),
             _ => {
                 let (neg, tilde_num) = if tilde_prefix.starts_with('+') {
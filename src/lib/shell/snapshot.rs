@@ -0,0 +1,410 @@
+//! Serialization of the state of a shell to a stable text format.
+//!
+//! A snapshot starts with the `ion-snapshot 1` header, followed by one record per line:
+//!
+//! ```text
+//! env "NAME" "value"
+//! let "name" VALUE
+//! alias "name" "replacement"
+//! fn "name" "source"
+//! cwd "/current/directory"
+//! dir "/directory/in/the/stack"
+//! ```
+//!
+//! Strings are quoted, with `\\`, `\"`, `\n`, `\r` and `\t` escapes. A `VALUE` is either a
+//! string, an array of values (`["a" "b"]`), a hashmap (`hmap{"key" VALUE}`) or a btreemap
+//! (`bmap{"key" VALUE}`). Records are sorted by name, so identical states give identical
+//! snapshots.
+use super::{
+    directory_stack::DirStackError,
+    flow_control::{Function, Statement},
+    IonError, Shell, Value,
+};
+use crate::types::{self, Shared};
+use err_derive::Error;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    iter::Peekable,
+    path::PathBuf,
+    str::Chars,
+};
+
+const HEADER: &str = "ion-snapshot 1";
+
+/// The variables describing the process running the shell, which are not part of snapshots
+const PROCESS_VARIABLES: &[&str] = &["PID", "UID", "EUID"];
+
+/// An error restoring a snapshot
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    /// The snapshot does not start with a known header
+    #[error(display = "not a shell snapshot, or of an unsupported version")]
+    UnsupportedFormat,
+    /// A record of the snapshot is malformed
+    #[error(display = "invalid snapshot at line {}: {}", line, reason)]
+    Syntax {
+        /// The line of the malformed record
+        line:   usize,
+        /// What is wrong with the record
+        reason: &'static str,
+    },
+    /// The source of a function is not a single function definition
+    #[error(display = "invalid definition for function '{}'", _0)]
+    InvalidFunction(types::Str),
+    /// The source of a function failed to parse
+    #[error(display = "failed to parse function '{}': {}", _0, _1)]
+    Function(types::Str, #[error(cause)] IonError),
+    /// The working directory could not be restored
+    #[error(display = "failed to restore the directory stack: {}", _0)]
+    DirStack(#[error(cause)] DirStackError),
+}
+
+/// The state read from a snapshot, before being applied to the shell
+struct State<T> {
+    env:       Vec<(types::Str, types::Str)>,
+    variables: Vec<(types::Str, Value<T>)>,
+    functions: Vec<(types::Str, types::Str)>,
+    cwd:       Option<PathBuf>,
+    dirs:      VecDeque<PathBuf>,
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            _ => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Encode a value, if it only contains strings, arrays and maps
+fn encode<T>(value: &Value<T>) -> Option<String> {
+    fn encode_map<'b, T: 'b>(
+        entries: impl Iterator<Item = (&'b types::Str, &'b Value<T>)>,
+    ) -> Option<String> {
+        let entries = entries
+            .map(|(key, value)| Some(format!("{} {}", quote(key), encode(value)?)))
+            .collect::<Option<Vec<_>>>()?;
+        Some(entries.join(" "))
+    }
+
+    Some(match value {
+        Value::Str(string) => quote(string),
+        Value::Array(array) => {
+            format!("[{}]", array.iter().map(encode).collect::<Option<Vec<_>>>()?.join(" "))
+        }
+        Value::HashMap(map) => {
+            let sorted: BTreeMap<_, _> = map.iter().collect();
+            format!("hmap{{{}}}", encode_map(sorted.into_iter())?)
+        }
+        Value::BTreeMap(map) => format!("bmap{{{}}}", encode_map(map.iter())?),
+        Value::Alias(_) | Value::Function(_) | Value::None => return None,
+    })
+}
+
+fn skip_spaces(chars: &mut Peekable<Chars<'_>>) {
+    while chars.peek() == Some(&' ') {
+        chars.next();
+    }
+}
+
+/// Consume the `close` delimiter if it is the next character
+fn closes(chars: &mut Peekable<Chars<'_>>, close: char) -> bool {
+    skip_spaces(chars);
+    if chars.peek() == Some(&close) {
+        chars.next();
+        true
+    } else {
+        false
+    }
+}
+
+fn decode_str(chars: &mut Peekable<Chars<'_>>) -> Result<types::Str, &'static str> {
+    skip_spaces(chars);
+    if chars.next() != Some('"') {
+        return Err("expected a quoted string");
+    }
+    let mut string = types::Str::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(string),
+            Some('\\') => string.push(match chars.next() {
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some(character @ '"') | Some(character @ '\\') => character,
+                _ => return Err("invalid escape sequence"),
+            }),
+            Some(character) => string.push(character),
+            None => return Err("unterminated string"),
+        }
+    }
+}
+
+fn decode_value<T>(chars: &mut Peekable<Chars<'_>>) -> Result<Value<T>, &'static str> {
+    fn expect(chars: &mut Peekable<Chars<'_>>, word: &str) -> Result<(), &'static str> {
+        if word.chars().all(|expected| chars.next() == Some(expected)) {
+            Ok(())
+        } else {
+            Err("expected a value")
+        }
+    }
+
+    skip_spaces(chars);
+    match chars.peek() {
+        Some('"') => decode_str(chars).map(Value::Str),
+        Some('[') => {
+            chars.next();
+            let mut array = types::Array::new();
+            while !closes(chars, ']') {
+                array.push(decode_value(chars)?);
+            }
+            Ok(Value::Array(array))
+        }
+        Some('h') => {
+            expect(chars, "hmap{")?;
+            let mut map = types::HashMap::new();
+            while !closes(chars, '}') {
+                let key = decode_str(chars)?;
+                map.insert(key, decode_value(chars)?);
+            }
+            Ok(Value::HashMap(map))
+        }
+        Some('b') => {
+            expect(chars, "bmap{")?;
+            let mut map = types::BTreeMap::new();
+            while !closes(chars, '}') {
+                let key = decode_str(chars)?;
+                map.insert(key, decode_value(chars)?);
+            }
+            Ok(Value::BTreeMap(map))
+        }
+        _ => Err("expected a value"),
+    }
+}
+
+impl<T> State<T> {
+    fn parse(snapshot: &str) -> Result<Self, SnapshotError> {
+        let mut lines = snapshot.lines().enumerate();
+        if lines.next().map(|(_, header)| header.trim_end()) != Some(HEADER) {
+            return Err(SnapshotError::UnsupportedFormat);
+        }
+
+        let mut state = State {
+            env:       Vec::new(),
+            variables: Vec::new(),
+            functions: Vec::new(),
+            cwd:       None,
+            dirs:      VecDeque::new(),
+        };
+        for (number, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+            state
+                .parse_record(line)
+                .map_err(|reason| SnapshotError::Syntax { line: number + 1, reason })?;
+        }
+        Ok(state)
+    }
+
+    fn parse_record(&mut self, line: &str) -> Result<(), &'static str> {
+        let mut chars = line.chars().peekable();
+        let kind: String = chars.by_ref().take_while(|&character| character != ' ').collect();
+        match kind.as_str() {
+            "env" => self.env.push((decode_str(&mut chars)?, decode_str(&mut chars)?)),
+            "let" => self.variables.push((decode_str(&mut chars)?, decode_value(&mut chars)?)),
+            "alias" => {
+                let name = decode_str(&mut chars)?;
+                let alias = types::Alias(decode_str(&mut chars)?);
+                self.variables.push((name, Value::Alias(alias)));
+            }
+            "fn" => self.functions.push((decode_str(&mut chars)?, decode_str(&mut chars)?)),
+            "cwd" => self.cwd = Some(decode_str(&mut chars)?.as_str().into()),
+            "dir" => self.dirs.push_back(decode_str(&mut chars)?.as_str().into()),
+            _ => return Err("unknown record"),
+        }
+
+        skip_spaces(&mut chars);
+        if chars.next().is_some() {
+            Err("unexpected characters at the end of the record")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a> Shell<'a> {
+    /// Serialize the variables, arrays, maps, aliases and functions visible from the current
+    /// scope, along with the exported environment and the directory stack, to a stable text
+    /// format.
+    ///
+    /// Functions are saved as their source code, so functions created from Rust without any
+    /// source are left out, as are the `PID`, `UID` and `EUID` variables of the process.
+    pub fn snapshot(&self) -> String {
+        let mut records = vec![HEADER.to_string()];
+        for (name, value) in self.variables.env_vars() {
+            records.push(format!("env {} {}", quote(name), quote(value)));
+        }
+        for (name, value) in self.variables.visible() {
+            match value {
+                Value::Alias(alias) => {
+                    records.push(format!("alias {} {}", quote(name), quote(alias)))
+                }
+                Value::Function(function) if !function.source().is_empty() => {
+                    records.push(format!("fn {} {}", quote(name), quote(function.source())))
+                }
+                _ if PROCESS_VARIABLES.contains(&name.as_str()) => (),
+                _ => {
+                    if let Some(value) = encode(value) {
+                        records.push(format!("let {} {}", quote(name), value));
+                    }
+                }
+            }
+        }
+        let cwd = self.directory_stack.current_dir().to_string_lossy();
+        records.push(format!("cwd {}", quote(&cwd)));
+        for dir in self.directory_stack.dirs() {
+            records.push(format!("dir {}", quote(&dir.to_string_lossy())));
+        }
+
+        let mut snapshot = records.join("\n");
+        snapshot.push('\n');
+        snapshot
+    }
+
+    /// Restore the state saved by `snapshot` into the current scope.
+    ///
+    /// The snapshot is entirely validated before the shell is modified. Variables which are not
+    /// in the snapshot are kept.
+    pub fn restore(&mut self, snapshot: &str) -> Result<(), SnapshotError> {
+        let state: State<Shared<Function<'a>>> = State::parse(snapshot)?;
+        let functions = state
+            .functions
+            .into_iter()
+            .map(|(name, source)| match self.parse_function(&source) {
+                Ok(Some(Statement::Function {
+                    name: defined,
+                    description,
+                    args,
+                    statements,
+                    source,
+                })) => {
+                    if defined == name {
+                        Ok((name, Function::new(description, defined, args, statements, source)))
+                    } else {
+                        Err(SnapshotError::InvalidFunction(name))
+                    }
+                }
+                Ok(_) => Err(SnapshotError::InvalidFunction(name)),
+                Err(why) => Err(SnapshotError::Function(name, why)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Nothing can fail once the directory is changed, so the shell is either fully
+        // restored or left untouched
+        if let Some(cwd) = state.cwd {
+            self.directory_stack.restore(cwd, state.dirs).map_err(SnapshotError::DirStack)?;
+        }
+        for (name, value) in state.env {
+            self.variables.set_env(name.as_str(), value.as_str());
+        }
+        for (name, value) in state.variables {
+            self.variables.set(&name, value);
+        }
+        for (name, function) in functions {
+            self.variables.set(&name, Value::Function(Shared::new(function)));
+        }
+        self.export_dirs();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_round_trip() {
+        let mut shell = Shell::default();
+        shell
+            .execute_command(
+                r#"
+let string = "quoted \"text\" and\ttab"
+let array = [one "two words" three]
+let hmap:hmap[str] = [first=1 second=2]
+let bmap:bmap[str] = [b=2 a=1]
+alias ll = 'ls -l'
+fn greet name --says hello
+    if test $name = world && true
+        echo "hello $name"
+    end
+end
+export SNAPSHOT_EXPORT = exported
+cd /
+"#
+                .as_bytes(),
+            )
+            .unwrap();
+        let snapshot = shell.snapshot();
+
+        let mut restored = Shell::default();
+        restored.restore(&snapshot).unwrap();
+        for name in &["string", "array", "hmap", "bmap", "ll"] {
+            assert_eq!(restored.variables().get(name), shell.variables().get(name));
+        }
+        assert_eq!(restored.variables().get_env("SNAPSHOT_EXPORT"), Some("exported"));
+        assert_eq!(restored.dir_stack().current_dir(), PathBuf::from("/").as_path());
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.capture("greet world".as_bytes()).unwrap().stdout, "hello world\n");
+    }
+
+    #[test]
+    fn function_sources_can_not_run_commands() {
+        let snapshot = format!("{}\nfn \"f\" \"fn f\\nend\\nlet injected = 1\"\n", HEADER);
+        let mut shell = Shell::default();
+        match shell.restore(&snapshot) {
+            Err(SnapshotError::InvalidFunction(ref name)) if name == "f" => (),
+            other => panic!("expected an invalid function, got {:?}", other),
+        }
+        assert!(shell.variables().get("injected").is_none());
+    }
+
+    #[test]
+    fn failed_restores_leave_the_shell_untouched() {
+        let mut shell = Shell::default();
+        let cwd = shell.dir_stack().current_dir().to_path_buf();
+        let snapshot =
+            format!("{}\nlet \"kept\" \"no\"\ncwd \"/\"\nfn \"f\" \"fn g\\nend\"\n", HEADER);
+        match shell.restore(&snapshot) {
+            Err(SnapshotError::InvalidFunction(ref name)) if name == "f" => (),
+            other => panic!("expected an invalid function, got {:?}", other),
+        }
+        let snapshot = format!("{}\nlet \"kept\" \"no\"\ncwd \"/nonexistent/directory\"\n", HEADER);
+        match shell.restore(&snapshot) {
+            Err(SnapshotError::DirStack(_)) => (),
+            other => panic!("expected a directory error, got {:?}", other),
+        }
+        assert!(shell.variables().get("kept").is_none());
+        assert_eq!(shell.dir_stack().current_dir(), cwd.as_path());
+    }
+
+    #[test]
+    fn malformed_snapshots() {
+        let mut shell = Shell::default();
+        match shell.restore("let \"a\" \"b\"\n") {
+            Err(SnapshotError::UnsupportedFormat) => (),
+            other => panic!("expected an unsupported format, got {:?}", other),
+        }
+        match shell.restore(&format!("{}\nlet \"a\" [\"b\"\n", HEADER)) {
+            Err(SnapshotError::Syntax { line: 2, .. }) => (),
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+        assert!(shell.variables().get("a").is_none());
+    }
+}
@@ -2,22 +2,26 @@ use super::{colors::Colors, flow_control::Function};
 use crate::{
     expansion,
     shell::IonError,
-    types::{self, Array},
+    types::{self, Array, Shared},
 };
 use nix::unistd::{geteuid, gethostname, getpid, getuid};
 use scopes::{Namespace, Scope, Scopes};
-use std::{env, ffi::CStr, rc::Rc};
+use std::{collections::BTreeMap, env, ffi::CStr};
 use unicode_segmentation::UnicodeSegmentation;
 
 /// Contain a dynamically-typed variable value
 pub use types_rs::Value;
-/// A structure containing dynamically-typed values organised in scopes
-pub struct Variables<'a>(Scopes<types::Str, Value<Rc<Function<'a>>>>);
+/// A structure containing dynamically-typed values organised in scopes, and the environment
+/// exported to the commands run by the shell
+pub struct Variables<'a> {
+    scopes: Scopes<types::Str, Value<Shared<Function<'a>>>>,
+    env:    BTreeMap<String, String>,
+}
 
 impl<'a> Variables<'a> {
     /// Get all strings
     pub fn string_vars(&self) -> impl Iterator<Item = (&types::Str, &types::Str)> {
-        self.0.scopes().flat_map(|map| {
+        self.scopes.scopes().flat_map(|map| {
             map.iter().filter_map(|(key, val)| {
                 if let types_rs::Value::Str(val) = val {
                     Some((key, val))
@@ -30,7 +34,7 @@ impl<'a> Variables<'a> {
 
     /// Get all aliases
     pub fn aliases(&self) -> impl Iterator<Item = (&types::Str, &types::Str)> {
-        self.0.scopes().rev().flat_map(|map| {
+        self.scopes.scopes().rev().flat_map(|map| {
             map.iter().filter_map(|(key, possible_alias)| {
                 if let types_rs::Value::Alias(alias) = possible_alias {
                     Some((key, &**alias))
@@ -42,8 +46,8 @@ impl<'a> Variables<'a> {
     }
 
     /// Get all the functions
-    pub fn functions(&self) -> impl Iterator<Item = (&types::Str, &Rc<Function<'a>>)> {
-        self.0.scopes().rev().flat_map(|map| {
+    pub fn functions(&self) -> impl Iterator<Item = (&types::Str, &Shared<Function<'a>>)> {
+        self.scopes.scopes().rev().flat_map(|map| {
             map.iter().filter_map(|(key, val)| {
                 if let types_rs::Value::Function(val) = val {
                     Some((key, val))
@@ -55,8 +59,10 @@ impl<'a> Variables<'a> {
     }
 
     /// Get all the array values
-    pub fn arrays(&self) -> impl Iterator<Item = (&types::Str, &types::Array<Rc<Function<'a>>>)> {
-        self.0.scopes().rev().flat_map(|map| {
+    pub fn arrays(
+        &self,
+    ) -> impl Iterator<Item = (&types::Str, &types::Array<Shared<Function<'a>>>)> {
+        self.scopes.scopes().rev().flat_map(|map| {
             map.iter().filter_map(|(key, val)| {
                 if let types_rs::Value::Array(val) = val {
                     Some((key, val))
@@ -67,40 +73,68 @@ impl<'a> Variables<'a> {
         })
     }
 
+    /// Get all the values visible from the current scope, sorted by name
+    pub(crate) fn visible(&self) -> BTreeMap<&types::Str, &Value<Shared<Function<'a>>>> {
+        let mut visible = BTreeMap::new();
+        for scope in self.scopes.scopes() {
+            for (key, val) in scope.iter() {
+                visible.entry(key).or_insert(val);
+            }
+        }
+        visible
+    }
+
+    /// Get all the variables exported to the environment of commands
+    pub fn env_vars(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.env.iter().map(|(key, val)| (key.as_str(), val.as_str()))
+    }
+
+    /// Get a variable exported to the environment of commands
+    pub fn get_env(&self, name: &str) -> Option<&str> { self.env.get(name).map(String::as_str) }
+
+    /// Export a variable to the environment of the commands run by this shell. The environment
+    /// of the shell process itself is left untouched.
+    pub fn set_env<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) {
+        self.env.insert(name.into(), value.into());
+    }
+
+    /// Remove a variable from the environment of commands
+    pub fn remove_env(&mut self, name: &str) -> Option<String> { self.env.remove(name) }
+
     /// Create a new scope. If namespace is true, variables won't be droppable across the scope
     /// boundary
-    pub fn new_scope(&mut self, namespace: bool) { self.0.new_scope(namespace) }
+    pub fn new_scope(&mut self, namespace: bool) { self.scopes.new_scope(namespace) }
 
     /// Exit the current scope
-    pub fn pop_scope(&mut self) { self.0.pop_scope() }
+    pub fn pop_scope(&mut self) { self.scopes.pop_scope() }
 
     pub(crate) fn pop_scopes<'b>(
         &'b mut self,
         index: usize,
-    ) -> impl Iterator<Item = Scope<types::Str, Value<Rc<Function<'a>>>>> + 'b {
-        self.0.pop_scopes(index)
+    ) -> impl Iterator<Item = Scope<types::Str, Value<Shared<Function<'a>>>>> + 'b {
+        self.scopes.pop_scopes(index)
     }
 
     pub(crate) fn append_scopes(
         &mut self,
-        scopes: Vec<Scope<types::Str, Value<Rc<Function<'a>>>>>,
+        scopes: Vec<Scope<types::Str, Value<Shared<Function<'a>>>>>,
     ) {
-        self.0.append_scopes(scopes)
+        self.scopes.append_scopes(scopes)
     }
 
     pub(crate) fn index_scope_for_var(&self, name: &str) -> Option<usize> {
-        self.0.index_scope_for_var(name)
+        self.scopes.index_scope_for_var(name)
     }
 
     /// Set a variable to a value in the current scope. If a variable already exists in a writable
     /// scope, it is updated, else a new variable is created in the current scope, possibly
     /// shadowing other variables
-    pub fn set<T: Into<Value<Rc<Function<'a>>>>>(&mut self, name: &str, value: T) {
+    pub fn set<T: Into<Value<Shared<Function<'a>>>>>(&mut self, name: &str, value: T) {
         let value = value.into();
-        if let Some(val) = self.0.get_mut(name) {
+        if let Some(val) = self.scopes.get_mut(name) {
             std::mem::replace(val, value);
         } else {
-            self.0.set(name, value);
+            self.scopes.set(name, value);
         }
     }
 
@@ -142,7 +176,7 @@ impl<'a> Variables<'a> {
     /// working directory which the leading `HOME` prefix replaced with a tilde character.
     fn get_simplified_directory(&self) -> types::Str {
         let home = self.get_str("HOME").unwrap_or_else(|_| "?".into());
-        env::var("PWD").unwrap().replace(&*home, "~").into()
+        self.get_env("PWD").unwrap_or("?").replace(&*home, "~").into()
     }
 
     /// Indicates if name is valid for functions and variables
@@ -154,12 +188,12 @@ impl<'a> Variables<'a> {
 
     /// Remove a variable from the current scope. If the value can't be removed (it is outside a
     /// function or does not exist), returns None
-    pub fn remove(&mut self, name: &str) -> Option<Value<Rc<Function<'a>>>> {
+    pub fn remove(&mut self, name: &str) -> Option<Value<Shared<Function<'a>>>> {
         if name.starts_with("super::") || name.starts_with("global::") {
             // Cannot mutate outer namespace
             return None;
         }
-        self.0.remove_variable(name)
+        self.scopes.remove_variable(name)
     }
 
     /// Get the string value associated with a name on the current scope. This includes fetching
@@ -182,14 +216,15 @@ impl<'a> Variables<'a> {
                     .map_err(|cause| Error::InvalidHex(variable.into(), cause))?;
                 Ok((c as char).to_string().into())
             }
-            Some(("env", variable)) => {
-                env::var(variable).map(Into::into).map_err(|_| Error::UnknownEnv(variable.into()))
-            }
+            Some(("env", variable)) => self
+                .get_env(variable)
+                .map(Into::into)
+                .ok_or_else(|| Error::UnknownEnv(variable.into())),
             Some(("super", _)) | Some(("global", _)) | None => {
                 // Otherwise, it's just a simple variable name.
                 match self.get(name) {
                     Some(Value::Str(val)) => Ok(val.clone()),
-                    _ => env::var(name).map(Into::into).map_err(|_| Error::VarNotFound),
+                    _ => self.get_env(name).map(Into::into).ok_or(Error::VarNotFound),
                 }
             }
             Some((..)) => Err(Error::UnsupportedNamespace(name.into())),
@@ -197,7 +232,7 @@ impl<'a> Variables<'a> {
     }
 
     /// Get a variable on the current scope
-    pub fn get(&self, mut name: &str) -> Option<&Value<Rc<Function<'a>>>> {
+    pub fn get(&self, mut name: &str) -> Option<&Value<Shared<Function<'a>>>> {
         const GLOBAL_NS: &str = "global::";
         const SUPER_NS: &str = "super::";
 
@@ -216,22 +251,22 @@ impl<'a> Variables<'a> {
         } else {
             Namespace::Any
         };
-        self.0.get(name, namespace)
+        self.scopes.get(name, namespace)
     }
 
     /// Get a mutable access to a variable on the current scope
-    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value<Rc<Function<'a>>>> {
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value<Shared<Function<'a>>>> {
         if name.starts_with("super::") || name.starts_with("global::") {
             // Cannot mutate outer namespace
             return None;
         }
-        self.0.get_mut(name)
+        self.scopes.get_mut(name)
     }
 }
 
 impl<'a> Default for Variables<'a> {
     fn default() -> Self {
-        let mut map: Scopes<types::Str, Value<Rc<Function<'a>>>> = Scopes::with_capacity(64);
+        let mut map: Scopes<types::Str, Value<Shared<Function<'a>>>> = Scopes::with_capacity(64);
         map.set("HISTORY_SIZE", "1000");
         map.set("HISTFILE_SIZE", "100000");
         map.set(
@@ -248,17 +283,22 @@ impl<'a> Default for Variables<'a> {
 
         map.set("CDPATH", Array::new());
 
+        // Inherit the environment of the process, ignoring the variables which are not unicode
+        let mut env: BTreeMap<String, String> = env::vars_os()
+            .filter_map(|(key, val)| Some((key.into_string().ok()?, val.into_string().ok()?)))
+            .collect();
+
         // Initialize the HOST variable
         let mut host_name = [0_u8; 512];
-        env::set_var(
-            "HOST",
-            &gethostname(&mut host_name)
+        env.insert(
+            "HOST".into(),
+            gethostname(&mut host_name)
                 .ok()
                 .map_or_else(|| "?".into(), CStr::to_string_lossy)
-                .as_ref(),
+                .into_owned(),
         );
 
-        Variables(map)
+        Variables { scopes: map, env }
     }
 }
 
@@ -269,7 +309,6 @@ pub(crate) mod tests {
         expansion::{Expander, Result, Select},
         shell::IonError,
     };
-    use serial_test_derive::serial;
 
     pub struct VariableExpander<'a>(pub Variables<'a>);
 
@@ -320,10 +359,9 @@ pub(crate) mod tests {
     }
 
     #[test]
-    #[serial]
     fn minimal_directory_var_should_compact_path() {
-        let variables = Variables::default();
-        env::set_var("PWD", "/var/log/nix");
+        let mut variables = Variables::default();
+        variables.set_env("PWD", "/var/log/nix");
         assert_eq!(
             types::Str::from("v/l/nix"),
             variables.get_str("MWD").expect("no value returned"),
@@ -331,10 +369,9 @@ pub(crate) mod tests {
     }
 
     #[test]
-    #[serial]
     fn minimal_directory_var_shouldnt_compact_path() {
-        let variables = Variables::default();
-        env::set_var("PWD", "/var/log");
+        let mut variables = Variables::default();
+        variables.set_env("PWD", "/var/log");
         assert_eq!(
             types::Str::from("/var/log"),
             variables.get_str("MWD").expect("no value returned"),
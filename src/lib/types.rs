@@ -3,6 +3,14 @@ use smallvec::SmallVec;
 pub use types_rs::{array, types::*};
 
 pub use crate::shell::flow_control::Function;
+/// The pointer shared values such as functions are stored behind: `Rc`, or `Arc` with the `send`
+/// feature so that shells can be moved to other threads
+#[cfg(not(feature = "send"))]
+pub type Shared<T> = std::rc::Rc<T>;
+/// The pointer shared values such as functions are stored behind: `Rc`, or `Arc` with the `send`
+/// feature so that shells can be moved to other threads
+#[cfg(feature = "send")]
+pub type Shared<T> = std::sync::Arc<T>;
 /// A owned version of a set of arguments for spawning a command
 pub type Args = SmallVec<[small::String; 4]>;
 /// Construct a new Array containing the given arguments
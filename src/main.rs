@@ -9,6 +9,7 @@ use nix::{
 use std::{
     fs,
     io::{stdin, BufReader},
+    path::PathBuf,
     process,
     sync::Arc,
 };
@@ -63,6 +64,18 @@ struct CommandLineArgs {
     /// Do not execute any commands, perform only syntax checking
     #[cfg_attr(feature = "advanced_arg_parsing", structopt(short = "-n", long = "--no-execute"))]
     no_execute:       bool,
+    /// Treat references to unset variables as errors
+    #[cfg_attr(feature = "advanced_arg_parsing", structopt(short = "-u", long = "--no-unset"))]
+    no_unset:         bool,
+    /// Run in restricted mode, for scripts that can not be trusted
+    #[cfg_attr(feature = "advanced_arg_parsing", structopt(short = "-r", long = "--restricted"))]
+    restricted:       bool,
+    /// Allow sourcing the scripts of the directory in restricted mode
+    #[cfg_attr(
+        feature = "advanced_arg_parsing",
+        structopt(long = "--source-dir", number_of_values = 1, parse(from_os_str))
+    )]
+    source_dirs:      Vec<PathBuf>,
     /// Evaluate given commands instead of reading from the commandline
     #[cfg_attr(feature = "advanced_arg_parsing", structopt(short = "-c"))]
     command:          Option<String>,
@@ -86,6 +99,9 @@ fn parse_args() -> CommandLineArgs {
     let mut command = None;
     let mut key_bindings = None;
     let mut no_execute = false;
+    let mut no_unset = false;
+    let mut restricted = false;
+    let mut source_dirs = Vec::new();
     let mut print_commands = false;
     let mut interactive = false;
     let mut fake_interactive = false;
@@ -110,6 +126,15 @@ fn parse_args() -> CommandLineArgs {
             }
             "-x" => print_commands = true,
             "-n" | "--no-execute" => no_execute = true,
+            "-u" | "--no-unset" => no_unset = true,
+            "-r" | "--restricted" => restricted = true,
+            "--source-dir" => match args.next() {
+                Some(dir) => source_dirs.push(PathBuf::from(dir)),
+                None => {
+                    eprintln!("ion: no directory given for option --source-dir");
+                    process::exit(1);
+                }
+            },
             "-c" => command = args.next(),
             "-v" | "--version" => version = true,
             "-h" | "--help" => {
@@ -129,6 +154,9 @@ fn parse_args() -> CommandLineArgs {
         interactive,
         fake_interactive,
         no_execute,
+        no_unset,
+        restricted,
+        source_dirs,
         command,
         version,
         args: additional_arguments,
@@ -185,6 +213,9 @@ fn main() {
     })));
 
     shell.opts_mut().no_exec = command_line_args.no_execute;
+    shell.opts_mut().no_unset = command_line_args.no_unset;
+    shell.opts_mut().restricted = command_line_args.restricted;
+    shell.opts_mut().source_dirs = command_line_args.source_dirs;
     shell.opts_mut().grab_tty = stdin_is_a_tty;
     if command_line_args.print_commands {
         shell.set_pre_command(Some(Box::new(|_shell, pipeline| {